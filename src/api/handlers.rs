@@ -1,16 +1,18 @@
 //! The handlers for the API endpoints.
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
 
 use hyper::server::Request;
 use hyper::status::StatusCode;
 use hyper::method::Method;
+use hyper::uri::RequestUri;
 use uuid::Uuid;
 
 use super::super::Result;
 use super::super::constants::VERSION;
 use super::super::utils::Addr;
 use super::super::sdk::SdkInfo;
+use super::super::breakpad::{parse_debug_id, get_module_name, write_symbol_file};
 use super::super::memdb::read::{MemDb, Symbol as MemDbSymbol};
 use super::super::memdb::stash::MemDbStash;
 use super::server::{ServerContext, load_request_data};
@@ -21,6 +23,8 @@ struct SymbolLookupRequest {
     sdk_id: String,
     cpu_name: String,
     symbols: Vec<Symbol>,
+    #[serde(default)]
+    demangle: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -28,7 +32,17 @@ struct Symbol {
     object_uuid: Option<Uuid>,
     object_name: Option<String>,
     symbol: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    demangled_symbol: Option<String>,
     addr: Addr,
+    symbol_addr: Option<Addr>,
+    instruction_offset: Option<u64>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    filename: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    lineno: Option<u32>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    section: Option<String>,
 }
 
 macro_rules! assert_method {
@@ -40,13 +54,22 @@ macro_rules! assert_method {
     }
 }
 
-impl<'a> From<MemDbSymbol<'a>> for Symbol {
-    fn from(sym: MemDbSymbol<'a>) -> Symbol {
+impl Symbol {
+    /// Creates a response symbol for a lookup of the given address.
+    ///
+    /// If `demangle` is set the demangled symbol is included as well.
+    fn from_lookup<'a>(sym: MemDbSymbol<'a>, addr: u64, demangle: bool) -> Symbol {
         Symbol {
             object_uuid: Some(sym.object_uuid()),
             object_name: Some(sym.object_name().to_string()),
             symbol: Some(sym.symbol().to_string()),
+            demangled_symbol: if demangle { sym.demangled_symbol() } else { None },
             addr: Addr(sym.addr()),
+            symbol_addr: Some(Addr(sym.addr())),
+            instruction_offset: Some(addr - sym.addr()),
+            filename: sym.filename().map(|x| x.to_string()),
+            lineno: sym.lineno(),
+            section: sym.section_kind().map(|x| x.name().to_string()),
         }
     }
 }
@@ -61,6 +84,12 @@ struct SdksResponse {
     sdks: Vec<String>,
 }
 
+#[derive(Serialize)]
+struct SdkInfoResponse {
+    sdk_id: String,
+    metadata: BTreeMap<String, String>,
+}
+
 #[derive(Serialize)]
 struct VersionResponse {
     version: String,
@@ -117,19 +146,20 @@ pub fn lookup_symbol_handler(ctx: &ServerContext, mut req: Request) -> Result<Ap
     let mut rv = vec![];
     for symq in data.symbols {
         let mut rvsym = None;
+        let addr: u64 = symq.addr.into();
         if let Some(ref uuid) = symq.object_uuid {
             for sdk_info in sdk_infos.iter() {
                 if let Some(sym) = lc.get_memdb(sdk_info)?.lookup_by_uuid(
-                   uuid, symq.addr.into()) {
-                    rvsym = Some(sym.into());
+                   uuid, addr) {
+                    rvsym = Some(Symbol::from_lookup(sym, addr, data.demangle));
                     break;
                 }
             }
         } else if let Some(ref name) = symq.object_name {
             for sdk_info in sdk_infos.iter() {
                 if let Some(sym) = lc.get_memdb(sdk_info)?.lookup_by_object_name(
-                   name, &data.cpu_name, symq.addr.into()) {
-                    rvsym = Some(sym.into());
+                   name, &data.cpu_name, addr) {
+                    rvsym = Some(Symbol::from_lookup(sym, addr, data.demangle));
                     break;
                 }
             }
@@ -151,6 +181,87 @@ pub fn list_sdks_handler(ctx: &ServerContext, req: Request) -> Result<ApiRespons
     }, StatusCode::Ok)
 }
 
+/// Shows the metadata of a single SDK at `/sdks/<sdk_id>`.
+pub fn sdk_info_handler(ctx: &ServerContext, req: Request) -> Result<ApiResponse>
+{
+    assert_method!(req, Method::Get);
+    let sdk_id = match req.uri {
+        RequestUri::AbsolutePath(ref path) => {
+            path["/sdks/".len()..].split('?').next().unwrap_or("").to_string()
+        }
+        _ => { return Err(ApiError::BadRequest.into()); }
+    };
+    let info = match SdkInfo::from_filename(&sdk_id) {
+        Some(info) => info,
+        None => { return Err(ApiError::SdkNotFound.into()); }
+    };
+    if !ctx.stash.list_sdks()?.contains(&info) {
+        return Err(ApiError::SdkNotFound.into());
+    }
+    let memdb = ctx.stash.get_memdb(&info)?;
+    ApiResponse::new(SdkInfoResponse {
+        sdk_id: info.sdk_id(),
+        metadata: memdb.metadata()?,
+    }, StatusCode::Ok)
+}
+
+/// Serves Breakpad symbol files at `/<debug_file>/<debug_id>/<debug_file>.sym`.
+///
+/// The image is looked up by the UUID in the debug id in all SDKs and the
+/// symbol file is generated from the first memdb that has it under the
+/// module name of the debug file.
+pub fn symbol_file_handler(ctx: &ServerContext, req: Request) -> Result<ApiResponse>
+{
+    assert_method!(req, Method::Get);
+    let request = match req.uri {
+        RequestUri::AbsolutePath(ref path) => {
+            let path = path.split('?').next().unwrap_or("");
+            let mut parts = path.trim_left_matches('/').splitn(3, '/');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(debug_file), Some(debug_id), Some(filename))
+                    if filename.ends_with(".sym") && !filename.contains('/') =>
+                {
+                    parse_debug_id(debug_id).map(|uuid| (debug_file, uuid))
+                }
+                _ => None,
+            }
+        }
+        _ => { return Err(ApiError::BadRequest.into()); }
+    };
+    let (debug_file, uuid) = match request {
+        Some(request) => request,
+        None => { return Err(ApiError::NotFound.into()); }
+    };
+
+    for info in ctx.stash.list_sdks()? {
+        // a broken SDK must not keep the others from being searched
+        let memdb = match ctx.stash.get_memdb(&info) {
+            Ok(memdb) => memdb,
+            Err(err) => {
+                warn!("Skipping SDK {} for symbol file: {}", info.sdk_id(), err);
+                continue;
+            }
+        };
+        let tagged_object = match memdb.get_tagged_object_name(&uuid) {
+            Ok(Some(tagged_object)) => tagged_object,
+            Ok(None) => { continue; }
+            Err(err) => {
+                warn!("Skipping SDK {} for symbol file: {}", info.sdk_id(), err);
+                continue;
+            }
+        };
+        // the debug file of the path has to match the module of the object
+        let object_name = tagged_object.rsplitn(2, ':').nth(1).unwrap_or("");
+        if get_module_name(object_name) != debug_file {
+            continue;
+        }
+        let mut body = vec![];
+        write_symbol_file(&mut body, &memdb, &uuid, &tagged_object)?;
+        return Ok(ApiResponse::new_text(body, StatusCode::Ok));
+    }
+    Err(ApiError::NotFound.into())
+}
+
 /// Server version info.
 pub fn version_handler(_ctx: &ServerContext, req: Request) -> Result<ApiResponse>
 {
@@ -190,6 +190,10 @@ impl ApiServer {
                         "/lookup" => handlers::lookup_symbol_handler,
                         "/sdks" => handlers::list_sdks_handler,
                         "/version" => handlers::version_handler,
+                        p if p.starts_with("/sdks/") => handlers::sdk_info_handler,
+                        p if p.split('?').next().unwrap_or("").ends_with(".sym") => {
+                            handlers::symbol_file_handler
+                        }
                         _ => not_found,
                     }
                 }
@@ -14,6 +14,7 @@ use super::super::constants::VERSION;
 pub struct ApiResponse {
     body: Vec<u8>,
     status: StatusCode,
+    content_type: ContentType,
 }
 
 /// Represents API Errors.
@@ -44,9 +45,19 @@ impl ApiResponse {
         Ok(ApiResponse {
             body: body,
             status: status,
+            content_type: ContentType::json(),
         })
     }
 
+    /// Creates a new plain text response.
+    pub fn new_text(body: Vec<u8>, status: StatusCode) -> ApiResponse {
+        ApiResponse {
+            body: body,
+            status: status,
+            content_type: ContentType::plaintext(),
+        }
+    }
+
     /// Creates an API response from a given error.
     pub fn from_error(err: Error) -> Result<ApiResponse> {
         if_chain! {
@@ -74,7 +85,7 @@ impl ApiResponse {
         *resp.status_mut() = self.status;
         resp.headers_mut().set(Server(format!("sentry-symbolserver/{}", VERSION)));
         resp.headers_mut().set(ContentLength(self.body.len() as u64));
-        resp.headers_mut().set(ContentType::json());
+        resp.headers_mut().set(self.content_type.clone());
         if !is_head {
             resp.send(&self.body[..])?;
         }
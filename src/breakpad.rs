@@ -0,0 +1,362 @@
+//! Breakpad Symbol Files
+//!
+//! Breakpad stores symbols in text files with one record per line.  This
+//! module reads the `MODULE`, `FILE`, `FUNC` and `PUBLIC` records together
+//! with the line records of functions.  Call frame information, inlinees
+//! and other records are ignored.
+//!
+//! Objects in memdb files can also be exported as symbol files.  A symbol
+//! in a memdb ends where the next symbol or end marker starts so it is
+//! written as a `FUNC` record of that size.  Only a last symbol without an
+//! end marker becomes a `PUBLIC` record.  Stripped functions keep their
+//! `<redacted>` placeholder.  Line information is not exported.
+use std::fs;
+use std::io::{Read, Write, BufWriter};
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, HashSet};
+
+use uuid::Uuid;
+use walkdir;
+
+use super::{Result, ErrorKind};
+use super::sdk::SdkInfo;
+use super::memdb::read::MemDb;
+
+/// A line record of a function
+#[derive(Debug, Clone, Copy)]
+pub struct BreakpadLine {
+    addr: u64,
+    size: u64,
+    line: u32,
+    file: u32,
+}
+
+/// A function from a `FUNC` record
+#[derive(Debug, Clone)]
+pub struct BreakpadFunction {
+    addr: u64,
+    size: u64,
+    name: String,
+    lines: Vec<BreakpadLine>,
+}
+
+/// The contents of a Breakpad symbol file
+#[derive(Debug, Clone)]
+pub struct BreakpadModule {
+    os: String,
+    arch: String,
+    debug_id: String,
+    uuid: Uuid,
+    name: String,
+    files: BTreeMap<u32, String>,
+    functions: Vec<BreakpadFunction>,
+    publics: Vec<(u64, String)>,
+}
+
+fn parse_hex(value: Option<&str>) -> Result<u64> {
+    let value = value.ok_or_else(|| ErrorKind::BadBreakpad)?;
+    u64::from_str_radix(value, 16).map_err(|_| ErrorKind::BadBreakpad.into())
+}
+
+fn parse_dec(value: Option<&str>) -> Result<u32> {
+    let value = value.ok_or_else(|| ErrorKind::BadBreakpad)?;
+    value.parse().map_err(|_| ErrorKind::BadBreakpad.into())
+}
+
+/// Strips the optional `m` flag of `FUNC` and `PUBLIC` records.
+fn strip_multiple(rest: &str) -> &str {
+    if rest.starts_with("m ") {
+        &rest[2..]
+    } else {
+        rest
+    }
+}
+
+impl BreakpadModule {
+    /// Parses the contents of a symbol file.
+    pub fn parse(data: &str) -> Result<BreakpadModule> {
+        let mut lines = data.lines();
+        let header = lines.next().ok_or_else(|| ErrorKind::BadBreakpad)?;
+        let mut parts = header.splitn(5, ' ');
+        if parts.next() != Some("MODULE") {
+            return Err(ErrorKind::BadBreakpad.into());
+        }
+        let os = parts.next().ok_or_else(|| ErrorKind::BadBreakpad)?;
+        let arch = parts.next().ok_or_else(|| ErrorKind::BadBreakpad)?;
+        let debug_id = parts.next().ok_or_else(|| ErrorKind::BadBreakpad)?;
+        let name = parts.next().ok_or_else(|| ErrorKind::BadBreakpad)?;
+
+        let uuid = parse_debug_id(debug_id).ok_or_else(|| ErrorKind::BadBreakpad)?;
+
+        let mut files = BTreeMap::new();
+        let mut functions: Vec<BreakpadFunction> = vec![];
+        let mut publics = vec![];
+        let mut in_function = false;
+        for line in lines {
+            let mut parts = line.splitn(2, ' ');
+            let record = parts.next().unwrap_or("");
+            let rest = parts.next().unwrap_or("");
+            match record {
+                "FILE" => {
+                    let mut parts = rest.splitn(2, ' ');
+                    let id = parse_dec(parts.next())?;
+                    let name = parts.next().ok_or_else(|| ErrorKind::BadBreakpad)?;
+                    files.insert(id, name.to_string());
+                    in_function = false;
+                }
+                "FUNC" => {
+                    // the parameter size comes before the name
+                    let mut parts = strip_multiple(rest).splitn(4, ' ');
+                    let addr = parse_hex(parts.next())?;
+                    let size = parse_hex(parts.next())?;
+                    parse_hex(parts.next())?;
+                    functions.push(BreakpadFunction {
+                        addr: addr,
+                        size: size,
+                        name: parts.next().unwrap_or("").to_string(),
+                        lines: vec![],
+                    });
+                    in_function = true;
+                }
+                "PUBLIC" => {
+                    let mut parts = strip_multiple(rest).splitn(3, ' ');
+                    let addr = parse_hex(parts.next())?;
+                    parse_hex(parts.next())?;
+                    publics.push((addr, parts.next().unwrap_or("").to_string()));
+                    in_function = false;
+                }
+                // other records such as `INLINE` can sit between a function
+                // and its line records.
+                "" => {}
+                _ if record.starts_with(|c: char| c.is_uppercase()) => {}
+                _ => {
+                    // line records directly follow their function
+                    if !in_function {
+                        return Err(ErrorKind::BadBreakpad.into());
+                    }
+                    let mut parts = line.splitn(4, ' ');
+                    let line = BreakpadLine {
+                        addr: parse_hex(parts.next())?,
+                        size: parse_hex(parts.next())?,
+                        line: parse_dec(parts.next())?,
+                        file: parse_dec(parts.next())?,
+                    };
+                    functions.last_mut().unwrap().lines.push(line);
+                }
+            }
+        }
+
+        Ok(BreakpadModule {
+            os: os.to_string(),
+            arch: arch.to_string(),
+            debug_id: debug_id.to_string(),
+            uuid: uuid,
+            name: name.to_string(),
+            files: files,
+            functions: functions,
+            publics: publics,
+        })
+    }
+
+    /// Parses a symbol file from the file system.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<BreakpadModule> {
+        let mut data = String::new();
+        fs::File::open(path)?.read_to_string(&mut data)?;
+        BreakpadModule::parse(&data)
+    }
+
+    /// The operating system of the module (eg: `Linux`)
+    pub fn os(&self) -> &str {
+        &self.os
+    }
+
+    /// The architecture of the module (eg: `arm64`)
+    pub fn arch(&self) -> &str {
+        &self.arch
+    }
+
+    /// The debug id as written in the file
+    pub fn debug_id(&self) -> &str {
+        &self.debug_id
+    }
+
+    /// The UUID of the module
+    ///
+    /// This is the debug id without the age.
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    /// The name of the module (eg: `libc.so`)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the name of a file from a `FILE` record
+    pub fn file(&self, id: u32) -> Option<&str> {
+        self.files.get(&id).map(|x| x.as_str())
+    }
+
+    /// The functions of the module
+    pub fn functions(&self) -> &[BreakpadFunction] {
+        &self.functions
+    }
+
+    /// The public symbols of the module as `(addr, name)`
+    pub fn publics(&self) -> &[(u64, String)] {
+        &self.publics
+    }
+}
+
+impl BreakpadFunction {
+    /// The address of the function relative to the image
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// The size of the function
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The name of the function
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The line records of the function
+    pub fn lines(&self) -> &[BreakpadLine] {
+        &self.lines
+    }
+}
+
+impl BreakpadLine {
+    /// The address of the line relative to the image
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// The size of the code of the line
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The line number
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// The id of the `FILE` record
+    pub fn file(&self) -> u32 {
+        self.file
+    }
+}
+
+/// Finds the symbol files at a path.
+///
+/// Folders are searched recursively for files ending in `.sym`.  Other
+/// paths are returned as is.
+pub fn find_symbol_files<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
+    let path = path.as_ref();
+    if !fs::metadata(path)?.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut rv = vec![];
+    for dent_res in walkdir::WalkDir::new(path).sort_by(|a, b| a.cmp(b)) {
+        let dent = dent_res?;
+        if dent.file_type().is_file() &&
+           dent.path().extension().and_then(|x| x.to_str()) == Some("sym") {
+            rv.push(dent.path().to_path_buf());
+        }
+    }
+    Ok(rv)
+}
+
+/// Parses the UUID out of a Breakpad debug id.
+///
+/// The debug id is the UUID followed by the age which is ignored.
+pub fn parse_debug_id(debug_id: &str) -> Option<Uuid> {
+    if debug_id.len() < 32 || !debug_id.is_char_boundary(32) {
+        return None;
+    }
+    Uuid::parse_str(&debug_id[..32]).ok()
+}
+
+/// Returns the Breakpad debug id of a UUID.
+///
+/// This is the UUID in uppercase hex followed by an age of zero.
+pub fn get_debug_id(uuid: &Uuid) -> String {
+    format!("{}0", uuid.simple().to_string().to_uppercase())
+}
+
+/// Returns the module name Breakpad uses for an object name.
+///
+/// This is the file name of the object (eg: `libc.so` for
+/// `/system/lib/libc.so`).
+pub fn get_module_name(object_name: &str) -> &str {
+    object_name.rsplit('/').next().unwrap_or(object_name)
+}
+
+fn get_os(info: &SdkInfo) -> &'static str {
+    match info.name() {
+        "Android" | "Linux" => "Linux",
+        _ => "mac",
+    }
+}
+
+/// Writes the symbol file of an object in a memdb.
+///
+/// The object is given by its UUID and its `name:arch` tagged object name.
+/// Symbols are written as `FUNC` records that end at the next index item
+/// so that Breakpad does not extend them over the padding after them.
+pub fn write_symbol_file<W: Write>(w: &mut W, memdb: &MemDb, uuid: &Uuid,
+                                   tagged_object: &str)
+    -> Result<()>
+{
+    let mut parts = tagged_object.rsplitn(2, ':');
+    let arch = parts.next().unwrap_or("");
+    let object_name = parts.next().unwrap_or("");
+    writeln!(w, "MODULE {} {} {} {}", get_os(memdb.info()), arch, get_debug_id(uuid),
+             get_module_name(object_name))?;
+    let mut entries = memdb.iter_index(uuid)?.peekable();
+    while let Some(entry_rv) = entries.next() {
+        let entry = entry_rv?;
+        let symbol = match entry.symbol() {
+            Some(symbol) => symbol,
+            None => { continue; }
+        };
+        let end = match entries.peek() {
+            Some(&Ok(ref next)) => Some(next.addr()),
+            _ => None,
+        };
+        if let Some(end) = end {
+            writeln!(w, "FUNC {:x} {:x} 0 {}", entry.addr(), end - entry.addr(), symbol)?;
+        } else {
+            writeln!(w, "PUBLIC {:x} 0 {}", entry.addr(), symbol)?;
+        }
+    }
+    Ok(())
+}
+
+/// Exports the objects of a memdb as Breakpad symbol files.
+///
+/// The files are written into `path` in the layout of a Breakpad symbol
+/// store which is `<module>/<debug id>/<module>.sym`.  Objects with the
+/// same UUID are only written once.  Returns the number of written files.
+pub fn export_memdb<P: AsRef<Path>>(memdb: &MemDb, path: P) -> Result<usize> {
+    let mut seen = HashSet::new();
+    for (tagged_object, uuid) in memdb.list_tagged_objects()? {
+        if !seen.insert(uuid) {
+            continue;
+        }
+        let object_name = tagged_object.rsplitn(2, ':').nth(1).unwrap_or("");
+        let module_name = get_module_name(object_name);
+        let dir = path.as_ref().join(module_name).join(get_debug_id(&uuid));
+        fs::create_dir_all(&dir)?;
+        let f = fs::File::create(dir.join(format!("{}.sym", module_name)))?;
+        let mut w = BufWriter::new(f);
+        write_symbol_file(&mut w, memdb, &uuid, &tagged_object)?;
+        w.flush()?;
+    }
+    Ok(seen.len())
+}
@@ -6,6 +6,7 @@ use std::process;
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 use std::sync::Mutex;
+use std::collections::HashMap;
 
 use clap::{App, Arg, SubCommand, ArgMatches, AppSettings};
 use chrono;
@@ -16,11 +17,18 @@ use openssl_probe::init_ssl_cert_env_vars;
 use tempdir::TempDir;
 use console::style;
 use indicatif::HumanDuration;
+use serde_json;
 
 use super::{Result, ResultExt, Error};
-use super::sdk::{Sdk, SdkInfo, DumpOptions};
+use super::sdk::{Sdk, SdkInfo, DumpOptions, DEVICE_SUPPORT_FOLDERS};
+use super::dsym::Object;
 use super::config::Config;
 use super::constants::VERSION;
+use super::memdb::read::{MemDb, NameMatch};
+use super::memdb::types::PackedSdkInfo;
+use super::memdb::write::{merge_memdbs, update_memdb, import_breakpad};
+use super::breakpad::{find_symbol_files, export_memdb};
+use super::memdb::diff::diff_memdbs;
 use super::memdb::stash::{MemDbStash, SyncOptions};
 use super::api::server::{ApiServer, BindOptions};
 use super::utils::ProgressReader;
@@ -125,25 +133,68 @@ fn config_from_matches(matches: &ArgMatches) -> Result<Config> {
     Ok(cfg)
 }
 
+/// The arguments of the commands that write memdb files
+fn dump_option_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("compress")
+            .short("c")
+            .long("compress")
+            .help("Write compressed files instead."),
+        Arg::with_name("no_name_index")
+            .long("no-name-index")
+            .help("Do not write the index for looking up symbols by name."),
+        Arg::with_name("low_memory")
+            .long("low-memory")
+            .help("Spill symbols to temporary files to reduce memory usage."),
+    ]
+}
+
+fn dump_options_from_matches(matches: &ArgMatches) -> DumpOptions {
+    DumpOptions {
+        compress: matches.is_present("compress"),
+        name_index: !matches.is_present("no_name_index"),
+        low_memory: matches.is_present("low_memory"),
+        ..Default::default()
+    }
+}
+
 fn get_default_sdks() -> Result<Vec<PathBuf>> {
     let mut rv = vec![];
-    let path = env::home_dir().ok_or_else(|| {
+    let base = env::home_dir().ok_or_else(|| {
         Error::from("Could not find home folder")
-    })?.join("Library/Developer/Xcode/iOS DeviceSupport");
-    for entry_rv in fs::read_dir(path).chain_err(
-        || Error::from("Could not find iOS DeviceSupport"))?
-    {
-        let entry = entry_rv?;
-        let md = entry.metadata()?;
-        if_chain! {
-            if md.is_dir();
-            if let Ok(plist_md) = entry.path().join("Info.plist").metadata();
-            if plist_md.is_file();
-            then {
-                rv.push(entry.path().to_path_buf());
+    })?.join("Library/Developer/Xcode");
+    let mut found_folder = false;
+    let mut seen: HashMap<String, PathBuf> = HashMap::new();
+    for &(folder, _) in DEVICE_SUPPORT_FOLDERS {
+        let entries = match fs::read_dir(base.join(folder)) {
+            Ok(entries) => entries,
+            Err(_) => { continue; }
+        };
+        found_folder = true;
+        for entry_rv in entries {
+            let entry = entry_rv?;
+            let md = entry.metadata()?;
+            // newer device support folders do not always have an
+            // Info.plist but they all have the symbols.
+            if !(md.is_dir() && (entry.path().join("Info.plist").is_file() ||
+                                 entry.path().join("Symbols").is_dir())) {
+                continue;
+            }
+            // folders of different device models with the same version
+            // would be converted into the same memdb file.
+            if let Some(info) = SdkInfo::from_path(entry.path()) {
+                if let Some(other) = seen.insert(info.memdb_filename(), entry.path()) {
+                    return Err(Error::from(format!(
+                        "Device support folders {} and {} are both SDK {}",
+                        other.display(), entry.path().display(), info.sdk_id())));
+                }
             }
+            rv.push(entry.path().to_path_buf());
         }
     }
+    if !found_folder {
+        return Err(Error::from("Could not find any DeviceSupport folder"));
+    }
     Ok(rv)
 }
 
@@ -210,10 +261,18 @@ fn execute() -> Result<()> {
                      .value_name("PATH")
                      .multiple(true)
                      .help("Path to the support folder"))
-                .arg(Arg::with_name("compress")
-                     .short("c")
-                     .long("compress")
-                     .help("Write compressed files instead."))
+                .args(&dump_option_args())
+                .arg(Arg::with_name("threads")
+                     .long("threads")
+                     .value_name("COUNT")
+                     .help("The number of threads that parse objects (defaults to \
+                            the number of CPUs)"))
+                .arg(Arg::with_name("metadata")
+                     .long("meta")
+                     .value_name("KEY=VALUE")
+                     .multiple(true)
+                     .number_of_values(1)
+                     .help("Records additional metadata (e.g. the device model)."))
                 .arg(Arg::with_name("share_to")
                      .hidden(true)
                      .long("share-to")
@@ -222,6 +281,109 @@ fn execute() -> Result<()> {
                      .short("o")
                      .long("output")
                      .help("Where the result should be stored")))
+        .subcommand(
+            SubCommand::with_name("merge-memdb")
+                .about("Merges several memdb files into one")
+                .arg(Arg::with_name("path")
+                     .index(1)
+                     .value_name("PATH")
+                     .multiple(true)
+                     .required(true)
+                     .help("Path to the memdb files to merge"))
+                .arg(Arg::with_name("sdk_id")
+                     .long("sdk-id")
+                     .value_name("SDK_ID")
+                     .help("The SDK id of the merged file (defaults to the \
+                            common major version)"))
+                .args(&dump_option_args())
+                .arg(Arg::with_name("output_path")
+                     .short("o")
+                     .long("output")
+                     .value_name("PATH")
+                     .help("Where the result should be stored")))
+        .subcommand(
+            SubCommand::with_name("update-memdb")
+                .about("Adds or replaces objects in a memdb file")
+                .arg(Arg::with_name("memdb_path")
+                     .index(1)
+                     .value_name("MEMDB")
+                     .required(true)
+                     .help("Path to the memdb file to update"))
+                .arg(Arg::with_name("path")
+                     .index(2)
+                     .value_name("PATH")
+                     .multiple(true)
+                     .required(true)
+                     .help("Path to the Mach-O files to add"))
+                .arg(Arg::with_name("base_path")
+                     .long("base")
+                     .value_name("PATH")
+                     .help("Name objects without an install name relative to this folder"))
+                .args(&dump_option_args())
+                .arg(Arg::with_name("output_path")
+                     .short("o")
+                     .long("output")
+                     .value_name("PATH")
+                     .help("Where the result should be stored (defaults to \
+                            replacing the memdb file or writing a .memdbz \
+                            file next to it when compressing)")))
+        .subcommand(
+            SubCommand::with_name("import-breakpad")
+                .about("Converts Breakpad symbol files into a memdb file")
+                .arg(Arg::with_name("path")
+                     .index(1)
+                     .value_name("PATH")
+                     .multiple(true)
+                     .required(true)
+                     .help("Path to the .sym files or folders containing them"))
+                .arg(Arg::with_name("sdk_id")
+                     .long("sdk-id")
+                     .value_name("SDK_ID")
+                     .required(true)
+                     .help("The SDK id of the memdb file (e.g. Android_8.1.0_OPM1)"))
+                .args(&dump_option_args())
+                .arg(Arg::with_name("output_path")
+                     .short("o")
+                     .long("output")
+                     .value_name("PATH")
+                     .help("Where the result should be stored")))
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Exports the objects of a memdb file as Breakpad symbol files")
+                .arg(Arg::with_name("path")
+                     .index(1)
+                     .value_name("PATH")
+                     .required(true)
+                     .help("Path to the memdb file"))
+                .arg(Arg::with_name("output_path")
+                     .short("o")
+                     .long("output")
+                     .value_name("PATH")
+                     .help("The folder the symbol files are written to")))
+        .subcommand(
+            SubCommand::with_name("diff-memdb")
+                .about("Shows the differences between two memdb files")
+                .arg(Arg::with_name("old_path")
+                     .index(1)
+                     .value_name("OLD")
+                     .required(true)
+                     .help("Path to the old memdb file"))
+                .arg(Arg::with_name("new_path")
+                     .index(2)
+                     .value_name("NEW")
+                     .required(true)
+                     .help("Path to the new memdb file"))
+                .arg(Arg::with_name("json")
+                     .long("json")
+                     .help("Output the differences as JSON")))
+        .subcommand(
+            SubCommand::with_name("memdb-info")
+                .about("Shows the SDK and metadata of a memdb file")
+                .arg(Arg::with_name("path")
+                     .index(1)
+                     .value_name("PATH")
+                     .required(true)
+                     .help("Path to the memdb file")))
         .subcommand(
             SubCommand::with_name("dump-object")
                 .about("Dumps an object from a memdb in the stash")
@@ -235,6 +397,30 @@ fn execute() -> Result<()> {
                      .value_name("NAME_OR_UUID")
                      .required(true)
                      .help("The object to dump")))
+        .subcommand(
+            SubCommand::with_name("find-symbol")
+                .about("Finds symbols by name in a memdb from the stash")
+                .arg(Arg::with_name("sdk_id")
+                     .index(1)
+                     .value_name("SDK_ID")
+                     .required(true)
+                     .help("The SDK id to search"))
+                .arg(Arg::with_name("name")
+                     .index(2)
+                     .value_name("NAME")
+                     .required(true)
+                     .help("The symbol name to find"))
+                .arg(Arg::with_name("prefix")
+                     .long("prefix")
+                     .help("Find all symbols starting with the given name")))
+        .subcommand(
+            SubCommand::with_name("verify-memdb")
+                .about("Verifies the checksums of memdbs in the stash")
+                .arg(Arg::with_name("sdk_id")
+                     .index(1)
+                     .value_name("SDK_ID")
+                     .multiple(true)
+                     .help("The SDK ids to verify (defaults to all)")))
         .subcommand(
             SubCommand::with_name("sdk-fuzzy-match")
                 .about("Given an SDK ID finds the fuzzy matches in order of quality")
@@ -267,10 +453,70 @@ fn execute() -> Result<()> {
                  Path::new(matches.value_of("output_path").unwrap_or(".")))
             }
         };
-        convert_sdk_action(paths, output_path, compress, share_to)?;
+        let mut metadata = vec![];
+        for item in matches.values_of("metadata").into_iter().flat_map(|x| x) {
+            let mut iter = item.splitn(2, '=');
+            match (iter.next(), iter.next()) {
+                (Some(key), Some(value)) if !key.is_empty() => {
+                    metadata.push((key.to_string(), value.to_string()));
+                }
+                _ => {
+                    return Err(Error::from(format!("Invalid metadata '{}'", item)));
+                }
+            }
+        }
+        let mut options = DumpOptions {
+            compress: compress,
+            metadata: metadata,
+            ..dump_options_from_matches(matches)
+        };
+        if let Some(threads) = matches.value_of("threads") {
+            options.threads = threads.parse().chain_err(|| "invalid value for threads")?;
+        }
+        convert_sdk_action(paths, output_path, options, share_to)?;
+    } else if let Some(matches) = matches.subcommand_matches("merge-memdb") {
+        let paths: Vec<_> = matches.values_of("path").unwrap()
+            .map(|x| PathBuf::from(x)).collect();
+        let options = dump_options_from_matches(matches);
+        merge_memdb_action(paths, Path::new(matches.value_of("output_path").unwrap_or(".")),
+                           matches.value_of("sdk_id"), options)?;
+    } else if let Some(matches) = matches.subcommand_matches("update-memdb") {
+        let paths: Vec<_> = matches.values_of("path").unwrap()
+            .map(|x| PathBuf::from(x)).collect();
+        let options = dump_options_from_matches(matches);
+        update_memdb_action(Path::new(matches.value_of("memdb_path").unwrap()), paths,
+                            matches.value_of("base_path").map(|x| Path::new(x)),
+                            matches.value_of("output_path").map(|x| Path::new(x)),
+                            options)?;
+    } else if let Some(matches) = matches.subcommand_matches("import-breakpad") {
+        let paths: Vec<_> = matches.values_of("path").unwrap()
+            .map(|x| PathBuf::from(x)).collect();
+        let options = dump_options_from_matches(matches);
+        import_breakpad_action(paths, Path::new(matches.value_of("output_path").unwrap_or(".")),
+                               matches.value_of("sdk_id").unwrap(), options)?;
+    } else if let Some(matches) = matches.subcommand_matches("export") {
+        export_action(Path::new(matches.value_of("path").unwrap()),
+                      Path::new(matches.value_of("output_path").unwrap_or(".")))?;
+    } else if let Some(matches) = matches.subcommand_matches("diff-memdb") {
+        diff_memdb_action(Path::new(matches.value_of("old_path").unwrap()),
+                          Path::new(matches.value_of("new_path").unwrap()),
+                          matches.is_present("json"))?;
+    } else if let Some(matches) = matches.subcommand_matches("memdb-info") {
+        memdb_info_action(Path::new(matches.value_of("path").unwrap()))?;
     } else if let Some(matches) = matches.subcommand_matches("dump-object") {
         dump_object_action(&cfg, matches.value_of("sdk_id").unwrap(),
                            matches.value_of("name_or_uuid").unwrap())?;
+    } else if let Some(matches) = matches.subcommand_matches("find-symbol") {
+        find_symbol_action(&cfg, matches.value_of("sdk_id").unwrap(),
+                           matches.value_of("name").unwrap(),
+                           if matches.is_present("prefix") {
+                               NameMatch::Prefix
+                           } else {
+                               NameMatch::Exact
+                           })?;
+    } else if let Some(matches) = matches.subcommand_matches("verify-memdb") {
+        verify_memdb_action(&cfg, matches.values_of("sdk_id")
+            .map(|x| x.collect()).unwrap_or_else(|| vec![]))?;
     } else if let Some(matches) = matches.subcommand_matches("sdk-fuzzy-match") {
         sdk_fuzzy_match_action(&cfg, matches.value_of("sdk_id").unwrap())?;
     } else if let Some(matches) = matches.subcommand_matches("run") {
@@ -282,7 +528,7 @@ fn execute() -> Result<()> {
     Ok(())
 }
 
-fn convert_sdk_action(paths: Vec<PathBuf>, output_path: &Path, compress: bool,
+fn convert_sdk_action(paths: Vec<PathBuf>, output_path: &Path, options: DumpOptions,
                       share_to: Option<&str>)
     -> Result<()>
 {
@@ -294,7 +540,7 @@ fn convert_sdk_action(paths: Vec<PathBuf>, output_path: &Path, compress: bool,
         }
         let sdk = Sdk::new(&path)?;
         let mut dst = dst_base.join(sdk.info().memdb_filename());
-        if compress {
+        if options.compress {
             dst.set_extension("memdbz");
         }
 
@@ -307,11 +553,7 @@ fn convert_sdk_action(paths: Vec<PathBuf>, output_path: &Path, compress: bool,
         // make sure we close the file at the end, in case we want to
         // re-open it for compressing.
         let f = fs::File::create(&dst)?;
-        let options = DumpOptions {
-            compress: compress,
-            ..Default::default()
-        };
-        sdk.dump_memdb(f, options)?;
+        sdk.dump_memdb(f, options.clone())?;
         println!("Dumped in {}", HumanDuration(started.elapsed()));
 
         if let Some(url) = share_to {
@@ -322,6 +564,180 @@ fn convert_sdk_action(paths: Vec<PathBuf>, output_path: &Path, compress: bool,
     Ok(())
 }
 
+fn merge_memdb_action(paths: Vec<PathBuf>, output_path: &Path, sdk_id: Option<&str>,
+                      options: DumpOptions)
+    -> Result<()>
+{
+    let mut memdbs = vec![];
+    for path in paths.iter() {
+        memdbs.push(MemDb::from_path(path)?);
+    }
+
+    let info = if let Some(sdk_id) = sdk_id {
+        SdkInfo::from_filename(sdk_id).ok_or_else(|| Error::from("Invalid SDK ID"))?
+    } else {
+        // by default the merged file is named after the common major version
+        let first = memdbs[0].info();
+        if memdbs.iter().any(|x| x.info().name() != first.name() ||
+                                 x.info().version_major() != first.version_major()) {
+            return Err(Error::from("Memdbs are from different SDKs, use --sdk-id"));
+        }
+        SdkInfo::new(first.name(), first.version_major(), 0, 0, None)
+    };
+
+    let mut dst = env::current_dir().unwrap().join(output_path);
+    if dst.is_dir() {
+        dst = dst.join(info.memdb_filename());
+        if options.compress {
+            dst.set_extension("memdbz");
+        }
+    }
+
+    println!("Merging {} memdbs into {} SDK ({})",
+             style(memdbs.len()).cyan(),
+             style(info.name()).green(),
+             style(info.version()).cyan());
+    let started = Instant::now();
+    let f = fs::File::create(&dst)?;
+    merge_memdbs(f, &info, options, &memdbs[..])?;
+    println!("Merged in {}", HumanDuration(started.elapsed()));
+    Ok(())
+}
+
+fn update_memdb_action(memdb_path: &Path, paths: Vec<PathBuf>, base_path: Option<&Path>,
+                       output_path: Option<&Path>, options: DumpOptions)
+    -> Result<()>
+{
+    let mut objects = vec![];
+    for path in paths.iter() {
+        let name = match base_path.and_then(|base| path.strip_prefix(base).ok()) {
+            Some(rel) => format!("/{}", rel.display()),
+            None => format!("{}", path.display()),
+        };
+        objects.push((name, Object::from_path(path)?));
+    }
+
+    // without an output path the memdb is replaced once the new file
+    // was written completely.  Compressed files cannot be read back so
+    // they are written next to the memdb instead.
+    let replace = output_path.is_none() && !options.compress;
+    let dst = match output_path {
+        Some(path) => env::current_dir().unwrap().join(path),
+        None if options.compress => memdb_path.with_extension("memdbz"),
+        None => memdb_path.with_extension("memdb.tmp"),
+    };
+
+    let started = Instant::now();
+    {
+        let memdb = MemDb::from_path(memdb_path)?;
+        println!("Updating {} SDK ({}) with {} files",
+                 style(memdb.info().name()).green(),
+                 style(memdb.info().version()).cyan(),
+                 style(objects.len()).cyan());
+        let f = fs::File::create(&dst)?;
+        update_memdb(f, &memdb, options, &objects[..])?;
+    }
+    if replace {
+        fs::rename(&dst, memdb_path)?;
+    }
+    println!("Updated in {}", HumanDuration(started.elapsed()));
+    Ok(())
+}
+
+fn import_breakpad_action(paths: Vec<PathBuf>, output_path: &Path, sdk_id: &str,
+                          options: DumpOptions)
+    -> Result<()>
+{
+    let info = SdkInfo::from_filename(sdk_id).ok_or_else(|| Error::from("Invalid SDK ID"))?;
+    PackedSdkInfo::check_sdk_info(&info)?;
+    let mut files = vec![];
+    for path in paths.iter() {
+        files.extend(find_symbol_files(path)?);
+    }
+
+    let mut dst = env::current_dir().unwrap().join(output_path);
+    if dst.is_dir() {
+        dst = dst.join(info.memdb_filename());
+        if options.compress {
+            dst.set_extension("memdbz");
+        }
+    }
+
+    println!("Importing {} symbol files into {} SDK ({})",
+             style(files.len()).cyan(),
+             style(info.name()).green(),
+             style(info.version()).cyan());
+    let started = Instant::now();
+    let f = fs::File::create(&dst)?;
+    import_breakpad(f, &info, options, &files[..])?;
+    println!("Imported in {}", HumanDuration(started.elapsed()));
+    Ok(())
+}
+
+fn export_action(path: &Path, output_path: &Path) -> Result<()> {
+    let memdb = MemDb::from_path(path)?;
+    let dst = env::current_dir().unwrap().join(output_path);
+    println!("Exporting {} SDK ({} {})",
+             style(memdb.info().name()).green(),
+             style(memdb.info().version()).cyan(),
+             style(memdb.info().build().unwrap_or("UNKNOWN")).cyan());
+    let started = Instant::now();
+    let count = export_memdb(&memdb, &dst)?;
+    println!("Exported {} symbol files in {}", style(count).cyan(),
+             HumanDuration(started.elapsed()));
+    Ok(())
+}
+
+fn diff_memdb_action(old_path: &Path, new_path: &Path, json: bool) -> Result<()> {
+    let old = MemDb::from_path(old_path)?;
+    let new = MemDb::from_path(new_path)?;
+    let diff = diff_memdbs(&old, &new)?;
+
+    if json {
+        serde_json::to_writer_pretty(&mut io::stdout(), &diff)
+            .chain_err(|| "Could not write diff")?;
+        println!("");
+        return Ok(());
+    }
+
+    for obj in diff.removed_objects() {
+        println!("{} {} ({})", style("-").red(), obj.name(), obj.uuid());
+    }
+    for obj in diff.added_objects() {
+        println!("{} {} ({})", style("+").green(), obj.name(), obj.uuid());
+    }
+    for obj in diff.changed_objects() {
+        println!("{} {} ({} -> {})", style("~").yellow(), obj.name(),
+                 obj.old_uuid(), obj.new_uuid());
+        for sym in obj.removed_symbols() {
+            println!("    {} {}", style("-").red(), sym);
+        }
+        for sym in obj.added_symbols() {
+            println!("    {} {}", style("+").green(), sym);
+        }
+    }
+    if diff.is_empty() {
+        println!("No differences");
+    }
+    Ok(())
+}
+
+fn memdb_info_action(path: &Path) -> Result<()> {
+    let memdb = MemDb::from_path(path)?;
+    println!("{} SDK ({} {})",
+             style(memdb.info().name()).green(),
+             style(memdb.info().version()).cyan(),
+             style(memdb.info().build().unwrap_or("UNKNOWN")).cyan());
+    let metadata = memdb.metadata()?;
+    if metadata.is_empty() {
+        println!("No metadata");
+    }
+    for (key, value) in metadata.iter() {
+        println!("  {}: {}", style(key).dim(), value);
+    }
+    Ok(())
+}
+
 fn share_sdk(path: &Path, url: &str, info: &SdkInfo) -> Result<()> {
     let mime: Mime = "application/x-xz".parse().unwrap();
     let client = new_hyper_client()?;
@@ -342,13 +758,67 @@ fn dump_object_action(config: &Config, sdk_id: &str, name_or_uuid: &str) -> Resu
     let uuid = memdb.find_uuid_fuzzy(name_or_uuid)?.ok_or_else(||
         Error::from("Object not found in SDK"))?;
 
-    for item_rv in memdb.iter_symbols(uuid)? {
+    for item_rv in memdb.iter_symbols(&uuid)? {
         let item = item_rv?;
         println!("{:>014x} {}", item.addr(), item.symbol());
     }
     Ok(())
 }
 
+fn find_symbol_action(config: &Config, sdk_id: &str, name: &str, mode: NameMatch)
+    -> Result<()>
+{
+    let stash = MemDbStash::new(config)?;
+    let info = SdkInfo::from_filename(sdk_id).ok_or_else(||
+        Error::from("Invalid SDK ID"))?;
+    let memdb = stash.get_memdb(&info)?;
+
+    for item_rv in memdb.lookup_by_name(name, mode)? {
+        let item = item_rv?;
+        let end = item.size().map(|size| format!("{:>014x}", item.addr() + size))
+            .unwrap_or_else(|| "?".to_string());
+        println!("{} {:<8} {:>014x}-{} {} ({})", item.object_uuid(), item.arch(),
+                 item.addr(), end, item.symbol(), item.object_name());
+    }
+    Ok(())
+}
+
+fn verify_memdb_action(config: &Config, sdk_ids: Vec<&str>) -> Result<()> {
+    let stash = MemDbStash::new(config)?;
+    let sdk_infos = if sdk_ids.is_empty() {
+        stash.list_sdks()?
+    } else {
+        let mut rv = vec![];
+        for sdk_id in sdk_ids {
+            rv.push(SdkInfo::from_filename(sdk_id).ok_or_else(||
+                Error::from("Invalid SDK ID"))?);
+        }
+        rv
+    };
+
+    let mut failed = 0;
+    for info in sdk_infos.iter() {
+        match stash.verify_sdk(info) {
+            Ok(true) => {
+                println!("{} {}", style("Verified").green(), info);
+            }
+            Ok(false) => {
+                println!("{} {} (no checksums)", style("Unverified").yellow(), info);
+            }
+            Err(err) => {
+                println!("{} {}: {}", style("Damaged").red(), info, err);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::from(format!(
+            "{} memdbs failed verification and were quarantined", failed)));
+    }
+    Ok(())
+}
+
 fn sdk_fuzzy_match_action(config: &Config, sdk_id: &str) -> Result<()> {
     let stash = MemDbStash::new(config)?;
     let sdk_infos = stash.fuzzy_match_sdk_id(sdk_id)?;
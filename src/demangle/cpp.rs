@@ -0,0 +1,717 @@
+//! Itanium C++ ABI demangling
+//!
+//! Names are formatted like `c++filt` does.  Local names, lambdas and
+//! expressions in template arguments are not supported.
+
+/// The maximum nesting of types before a symbol is rejected
+const MAX_DEPTH: usize = 128;
+
+/// A demangled type
+///
+/// Function types are kept apart so that pointers to functions can be
+/// formatted with the declarator in the middle.
+enum Ty {
+    Plain(String),
+    Function(String, String),
+}
+
+/// A demangled name
+struct Name {
+    text: String,
+    is_template: bool,
+    is_special: bool,
+    qualifiers: String,
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    depth: usize,
+    subs: Vec<String>,
+    template_args: Vec<String>,
+}
+
+impl Ty {
+    fn into_string(self) -> String {
+        match self {
+            Ty::Plain(s) => s,
+            Ty::Function(ret, params) => format!("{} {}", ret, params),
+        }
+    }
+}
+
+fn builtin_type(c: u8) -> Option<&'static str> {
+    Some(match c {
+        b'v' => "void",
+        b'w' => "wchar_t",
+        b'b' => "bool",
+        b'c' => "char",
+        b'a' => "signed char",
+        b'h' => "unsigned char",
+        b's' => "short",
+        b't' => "unsigned short",
+        b'i' => "int",
+        b'j' => "unsigned int",
+        b'l' => "long",
+        b'm' => "unsigned long",
+        b'x' => "long long",
+        b'y' => "unsigned long long",
+        b'n' => "__int128",
+        b'o' => "unsigned __int128",
+        b'f' => "float",
+        b'd' => "double",
+        b'e' => "long double",
+        b'g' => "__float128",
+        b'z' => "...",
+        _ => { return None; }
+    })
+}
+
+fn operator_name(code: &[u8]) -> Option<&'static str> {
+    Some(match code {
+        b"nw" => "new",
+        b"na" => "new[]",
+        b"dl" => "delete",
+        b"da" => "delete[]",
+        b"ps" | b"pl" => "+",
+        b"ng" | b"mi" => "-",
+        b"ad" | b"an" => "&",
+        b"de" | b"ml" => "*",
+        b"co" => "~",
+        b"dv" => "/",
+        b"rm" => "%",
+        b"or" => "|",
+        b"eo" => "^",
+        b"aS" => "=",
+        b"pL" => "+=",
+        b"mI" => "-=",
+        b"mL" => "*=",
+        b"dV" => "/=",
+        b"rM" => "%=",
+        b"aN" => "&=",
+        b"oR" => "|=",
+        b"eO" => "^=",
+        b"ls" => "<<",
+        b"rs" => ">>",
+        b"lS" => "<<=",
+        b"rS" => ">>=",
+        b"eq" => "==",
+        b"ne" => "!=",
+        b"lt" => "<",
+        b"gt" => ">",
+        b"le" => "<=",
+        b"ge" => ">=",
+        b"ss" => "<=>",
+        b"nt" => "!",
+        b"aa" => "&&",
+        b"oo" => "||",
+        b"pp" => "++",
+        b"mm" => "--",
+        b"cm" => ",",
+        b"pm" => "->*",
+        b"pt" => "->",
+        b"cl" => "()",
+        b"ix" => "[]",
+        b"qu" => "?",
+        _ => { return None; }
+    })
+}
+
+/// Returns the last component of a name without template arguments.
+///
+/// This is the name of constructors and destructors.
+fn last_component(name: &str) -> &str {
+    let mut depth = 0;
+    let mut start = 0;
+    let mut end = name.len();
+    let bytes = name.as_bytes();
+    for (idx, &c) in bytes.iter().enumerate() {
+        match c {
+            b'<' => {
+                if depth == 0 {
+                    end = idx;
+                }
+                depth += 1;
+            }
+            b'>' => { depth -= 1; }
+            b':' if depth == 0 && bytes.get(idx + 1) == Some(&b':') => {
+                start = idx + 2;
+                end = name.len();
+            }
+            _ => {}
+        }
+    }
+    &name[start..end]
+}
+
+/// Formats template arguments the way `c++filt` does.
+fn format_template_args(args: &[String]) -> String {
+    let mut rv = format!("<{}", args.join(", "));
+    if rv.ends_with('>') {
+        rv.push(' ');
+    }
+    rv.push('>');
+    rv
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).cloned()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.input.get(self.pos + offset).cloned()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn parse_number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().map_or(false, |c| c >= b'0' && c <= b'9') {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return None;
+        }
+        let digits = String::from_utf8_lossy(&self.input[start..self.pos]);
+        digits.parse().ok()
+    }
+
+    /// Parses the index of a substitution or template parameter.
+    ///
+    /// `_` is the first index, otherwise a base 36 number precedes it.
+    fn parse_seq_id(&mut self) -> Option<usize> {
+        let mut rv = 0usize;
+        let mut has_digits = false;
+        loop {
+            let c = try_opt!(self.peek());
+            self.pos += 1;
+            let digit = match c {
+                b'_' => {
+                    return Some(if has_digits { rv + 1 } else { 0 });
+                }
+                b'0'...b'9' => c - b'0',
+                b'A'...b'Z' => c - b'A' + 10,
+                _ => { return None; }
+            };
+            rv = try_opt!(rv.checked_mul(36).and_then(|x| x.checked_add(digit as usize)));
+            has_digits = true;
+        }
+    }
+
+    fn parse_source_name(&mut self) -> Option<String> {
+        let len = try_opt!(self.parse_number());
+        let end = try_opt!(self.pos.checked_add(len));
+        let name = try_opt!(self.input.get(self.pos..end));
+        self.pos = end;
+        let name = String::from_utf8_lossy(name).into_owned();
+        if name.starts_with("_GLOBAL__N") {
+            Some("(anonymous namespace)".into())
+        } else {
+            Some(name)
+        }
+    }
+
+    fn parse_encoding(&mut self) -> Option<String> {
+        match (self.peek(), self.peek_at(1)) {
+            (Some(b'T'), _) | (Some(b'G'), Some(b'V')) => {
+                return self.parse_special_name();
+            }
+            _ => {}
+        }
+
+        let name = try_opt!(self.parse_name(true));
+        if self.at_end() {
+            return Some(name.text);
+        }
+
+        // template functions other than constructors, destructors and
+        // conversion operators encode their return type.
+        let ret = if name.is_template && !name.is_special {
+            Some(try_opt!(self.parse_type()))
+        } else {
+            None
+        };
+        let params = try_opt!(self.parse_bare_function_type());
+        Some(match ret {
+            Some(ret) => format!("{} {}{}{}", ret, name.text, params, name.qualifiers),
+            None => format!("{}{}{}", name.text, params, name.qualifiers),
+        })
+    }
+
+    fn parse_special_name(&mut self) -> Option<String> {
+        let prefix = match (self.peek(), self.peek_at(1)) {
+            (Some(b'T'), Some(b'V')) => "vtable for ",
+            (Some(b'T'), Some(b'I')) => "typeinfo for ",
+            (Some(b'T'), Some(b'S')) => "typeinfo name for ",
+            (Some(b'G'), Some(b'V')) => {
+                self.pos += 2;
+                let name = try_opt!(self.parse_name(false));
+                return Some(format!("guard variable for {}", name.text));
+            }
+            (Some(b'T'), Some(b'h')) => {
+                self.pos += 2;
+                self.eat(b'n');
+                try_opt!(self.parse_number());
+                if !self.eat(b'_') {
+                    return None;
+                }
+                let encoding = try_opt!(self.parse_encoding());
+                return Some(format!("non-virtual thunk to {}", encoding));
+            }
+            _ => { return None; }
+        };
+        self.pos += 2;
+        Some(format!("{}{}", prefix, try_opt!(self.parse_type())))
+    }
+
+    fn parse_bare_function_type(&mut self) -> Option<String> {
+        let mut params = vec![];
+        while !self.at_end() && self.peek() != Some(b'E') {
+            params.push(try_opt!(self.parse_type()));
+        }
+        if params.is_empty() {
+            None
+        } else if params.len() == 1 && params[0] == "void" {
+            Some("()".into())
+        } else {
+            Some(format!("({})", params.join(", ")))
+        }
+    }
+
+    /// Parses a name.
+    ///
+    /// If `top` is set this is the name of the demangled entity and its
+    /// template arguments are the ones template parameters refer to.
+    fn parse_name(&mut self, top: bool) -> Option<Name> {
+        match (self.peek(), self.peek_at(1)) {
+            (Some(b'N'), _) => self.parse_nested_name(top),
+            (Some(b'S'), Some(b't')) => {
+                self.pos += 2;
+                let (name, _) = try_opt!(self.parse_unqualified_name(""));
+                self.parse_unscoped_name(format!("std::{}", name), top)
+            }
+            (Some(b'S'), _) => {
+                let sub = try_opt!(self.parse_substitution());
+                if self.peek() != Some(b'I') {
+                    return None;
+                }
+                let args = try_opt!(self.parse_template_args(top));
+                Some(Name {
+                    text: format!("{}{}", sub, args),
+                    is_template: true,
+                    is_special: false,
+                    qualifiers: String::new(),
+                })
+            }
+            (Some(b'Z'), _) => None,
+            _ => {
+                let (name, is_special) = try_opt!(self.parse_unqualified_name(""));
+                let mut rv = try_opt!(self.parse_unscoped_name(name, top));
+                rv.is_special = is_special;
+                Some(rv)
+            }
+        }
+    }
+
+    /// Finishes an unscoped name which might be followed by template args.
+    fn parse_unscoped_name(&mut self, name: String, top: bool) -> Option<Name> {
+        let mut rv = Name {
+            text: name,
+            is_template: false,
+            is_special: false,
+            qualifiers: String::new(),
+        };
+        if self.peek() == Some(b'I') {
+            self.subs.push(rv.text.clone());
+            rv.text.push_str(&try_opt!(self.parse_template_args(top)));
+            rv.is_template = true;
+        }
+        Some(rv)
+    }
+
+    fn parse_nested_name(&mut self, top: bool) -> Option<Name> {
+        self.pos += 1;
+        let mut qualifiers = String::new();
+        let (is_restrict, is_volatile, is_const) = (self.eat(b'r'), self.eat(b'V'), self.eat(b'K'));
+        if is_const {
+            qualifiers.push_str(" const");
+        }
+        if is_volatile {
+            qualifiers.push_str(" volatile");
+        }
+        if is_restrict {
+            qualifiers.push_str(" restrict");
+        }
+        if self.eat(b'R') {
+            qualifiers.push_str(" &");
+        } else if self.eat(b'O') {
+            qualifiers.push_str(" &&");
+        }
+
+        let mut text = String::new();
+        let mut is_template = false;
+        let mut is_special = false;
+        loop {
+            let substitutable = match try_opt!(self.peek()) {
+                b'E' => {
+                    self.pos += 1;
+                    break;
+                }
+                b'S' if text.is_empty() => {
+                    if self.peek_at(1) == Some(b't') {
+                        self.pos += 2;
+                        text.push_str("std");
+                    } else {
+                        text = try_opt!(self.parse_substitution());
+                    }
+                    false
+                }
+                b'T' if text.is_empty() => {
+                    text = try_opt!(self.parse_template_param());
+                    true
+                }
+                b'I' if !text.is_empty() => {
+                    text.push_str(&try_opt!(self.parse_template_args(top)));
+                    is_template = true;
+                    true
+                }
+                _ => {
+                    let (name, special) = {
+                        let enclosing = last_component(&text).to_string();
+                        try_opt!(self.parse_unqualified_name(&enclosing))
+                    };
+                    if !text.is_empty() {
+                        text.push_str("::");
+                    }
+                    text.push_str(&name);
+                    is_template = false;
+                    is_special = special;
+                    true
+                }
+            };
+            // all prefixes of the name are substitution candidates
+            if substitutable && self.peek() != Some(b'E') {
+                self.subs.push(text.clone());
+            }
+        }
+
+        if text.is_empty() {
+            return None;
+        }
+        Some(Name {
+            text: text,
+            is_template: is_template,
+            is_special: is_special,
+            qualifiers: qualifiers,
+        })
+    }
+
+    /// Parses an unqualified name.
+    ///
+    /// `enclosing` is the name of the class for constructors and
+    /// destructors.  Along with the name this returns if it was a
+    /// constructor, destructor or conversion operator.
+    fn parse_unqualified_name(&mut self, enclosing: &str) -> Option<(String, bool)> {
+        let c = try_opt!(self.peek());
+        let rv = match c {
+            b'0'...b'9' => (try_opt!(self.parse_source_name()), false),
+            b'L' => {
+                // names with internal linkage
+                self.pos += 1;
+                (try_opt!(self.parse_source_name()), false)
+            }
+            b'C' => {
+                match self.peek_at(1) {
+                    Some(b'1'...b'5') => {}
+                    _ => { return None; }
+                }
+                if enclosing.is_empty() {
+                    return None;
+                }
+                self.pos += 2;
+                (enclosing.to_string(), true)
+            }
+            b'D' => {
+                match self.peek_at(1) {
+                    Some(b'0'...b'5') => {}
+                    _ => { return None; }
+                }
+                if enclosing.is_empty() {
+                    return None;
+                }
+                self.pos += 2;
+                (format!("~{}", enclosing), true)
+            }
+            b'c' if self.peek_at(1) == Some(b'v') => {
+                self.pos += 2;
+                (format!("operator {}", try_opt!(self.parse_type())), true)
+            }
+            b'a'...b'z' => {
+                let op = try_opt!(self.input.get(self.pos..self.pos + 2).and_then(operator_name));
+                self.pos += 2;
+                let sep = if op.starts_with(|c: char| c.is_alphabetic()) { " " } else { "" };
+                (format!("operator{}{}", sep, op), false)
+            }
+            _ => { return None; }
+        };
+
+        // ABI tags are appended to the name
+        let (mut name, special) = rv;
+        while self.eat(b'B') {
+            name = format!("{}[abi:{}]", name, try_opt!(self.parse_source_name()));
+        }
+        Some((name, special))
+    }
+
+    fn parse_substitution(&mut self) -> Option<String> {
+        self.pos += 1;
+        let rv = match try_opt!(self.peek()) {
+            b'a' => "std::allocator",
+            b'b' => "std::basic_string",
+            b's' => "std::string",
+            b'i' => "std::istream",
+            b'o' => "std::ostream",
+            b'd' => "std::iostream",
+            _ => {
+                let idx = try_opt!(self.parse_seq_id());
+                return self.subs.get(idx).cloned();
+            }
+        };
+        self.pos += 1;
+        Some(rv.to_string())
+    }
+
+    fn parse_template_param(&mut self) -> Option<String> {
+        self.pos += 1;
+        let idx = try_opt!(self.parse_seq_id());
+        self.template_args.get(idx).cloned()
+    }
+
+    fn parse_template_args(&mut self, top: bool) -> Option<String> {
+        self.pos += 1;
+        let mut args = vec![];
+        while !self.eat(b'E') {
+            args.push(try_opt!(self.parse_template_arg()));
+        }
+        let rv = format_template_args(&args);
+        if top {
+            self.template_args = args;
+        }
+        Some(rv)
+    }
+
+    fn parse_template_arg(&mut self) -> Option<String> {
+        // argument packs nest without going through `parse_ty`
+        if self.depth >= MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let rv = self.parse_template_arg_inner();
+        self.depth -= 1;
+        rv
+    }
+
+    fn parse_template_arg_inner(&mut self) -> Option<String> {
+        match try_opt!(self.peek()) {
+            b'L' => {
+                self.pos += 1;
+                self.parse_literal()
+            }
+            b'J' => {
+                self.pos += 1;
+                let mut args = vec![];
+                while !self.eat(b'E') {
+                    args.push(try_opt!(self.parse_template_arg()));
+                }
+                Some(args.join(", "))
+            }
+            b'X' => None,
+            _ => self.parse_type(),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Option<String> {
+        let ty = try_opt!(self.peek());
+        self.pos += 1;
+        let negative = self.eat(b'n');
+        let start = self.pos;
+        while self.peek().map_or(false, |c| c != b'E') {
+            self.pos += 1;
+        }
+        let value = format!("{}{}", if negative { "-" } else { "" },
+                            String::from_utf8_lossy(&self.input[start..self.pos]));
+        if !self.eat(b'E') || value.is_empty() {
+            return None;
+        }
+        Some(match ty {
+            b'b' if value == "0" => "false".into(),
+            b'b' if value == "1" => "true".into(),
+            b'i' => value,
+            b'j' => format!("{}u", value),
+            b'l' => format!("{}l", value),
+            b'm' => format!("{}ul", value),
+            b'x' => format!("{}ll", value),
+            b'y' => format!("{}ull", value),
+            _ => format!("({}){}", try_opt!(builtin_type(ty)), value),
+        })
+    }
+
+    fn parse_type(&mut self) -> Option<String> {
+        self.parse_ty().map(|ty| ty.into_string())
+    }
+
+    fn parse_ty(&mut self) -> Option<Ty> {
+        if self.depth >= MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let rv = self.parse_ty_inner();
+        self.depth -= 1;
+        rv
+    }
+
+    fn parse_ty_inner(&mut self) -> Option<Ty> {
+        let c = try_opt!(self.peek());
+        if let Some(name) = builtin_type(c) {
+            self.pos += 1;
+            return Some(Ty::Plain(name.to_string()));
+        }
+
+        let rv = match c {
+            b'D' => {
+                let name = match try_opt!(self.peek_at(1)) {
+                    b'n' => "decltype(nullptr)",
+                    b'a' => "auto",
+                    b'i' => "char32_t",
+                    b's' => "char16_t",
+                    b'u' => "char8_t",
+                    _ => { return None; }
+                };
+                self.pos += 2;
+                return Some(Ty::Plain(name.to_string()));
+            }
+            b'P' | b'R' | b'O' => {
+                self.pos += 1;
+                let (ptr, decl) = match c {
+                    b'P' => ("*", "(*)"),
+                    b'R' => ("&", "(&)"),
+                    _ => ("&&", "(&&)"),
+                };
+                match try_opt!(self.parse_ty()) {
+                    Ty::Plain(inner) => Ty::Plain(format!("{}{}", inner, ptr)),
+                    Ty::Function(ret, params) => Ty::Plain(format!("{} {}{}", ret, decl, params)),
+                }
+            }
+            b'r' | b'V' | b'K' => {
+                let (is_restrict, is_volatile, is_const) =
+                    (self.eat(b'r'), self.eat(b'V'), self.eat(b'K'));
+                let mut inner = try_opt!(self.parse_type());
+                if is_const {
+                    inner.push_str(" const");
+                }
+                if is_volatile {
+                    inner.push_str(" volatile");
+                }
+                if is_restrict {
+                    inner.push_str(" restrict");
+                }
+                Ty::Plain(inner)
+            }
+            b'F' => {
+                self.pos += 1;
+                self.eat(b'Y');
+                let ret = try_opt!(self.parse_type());
+                let params = try_opt!(self.parse_bare_function_type());
+                if !self.eat(b'E') {
+                    return None;
+                }
+                Ty::Function(ret, params)
+            }
+            b'A' => {
+                self.pos += 1;
+                let dim = try_opt!(self.parse_number());
+                if !self.eat(b'_') {
+                    return None;
+                }
+                Ty::Plain(format!("{} [{}]", try_opt!(self.parse_type()), dim))
+            }
+            b'M' => {
+                self.pos += 1;
+                let class = try_opt!(self.parse_type());
+                match try_opt!(self.parse_ty()) {
+                    Ty::Plain(member) => Ty::Plain(format!("{} {}::*", member, class)),
+                    Ty::Function(ret, params) => {
+                        Ty::Plain(format!("{} ({}::*){}", ret, class, params))
+                    }
+                }
+            }
+            b'T' => {
+                let mut rv = try_opt!(self.parse_template_param());
+                if self.peek() == Some(b'I') {
+                    self.subs.push(rv.clone());
+                    rv.push_str(&try_opt!(self.parse_template_args(false)));
+                }
+                Ty::Plain(rv)
+            }
+            b'u' => {
+                self.pos += 1;
+                Ty::Plain(try_opt!(self.parse_source_name()))
+            }
+            b'S' if self.peek_at(1) != Some(b't') => {
+                let sub = try_opt!(self.parse_substitution());
+                if self.peek() != Some(b'I') {
+                    // substitutions are not added again
+                    return Some(Ty::Plain(sub));
+                }
+                Ty::Plain(format!("{}{}", sub, try_opt!(self.parse_template_args(false))))
+            }
+            b'N' | b'S' | b'Z' | b'0'...b'9' => {
+                Ty::Plain(try_opt!(self.parse_name(false)).text)
+            }
+            _ => { return None; }
+        };
+
+        let sub = match rv {
+            Ty::Plain(ref s) => s.clone(),
+            Ty::Function(ref ret, ref params) => format!("{} {}", ret, params),
+        };
+        self.subs.push(sub);
+        Some(rv)
+    }
+}
+
+/// Demangles an Itanium C++ symbol.
+pub fn demangle(sym: &str) -> Option<String> {
+    let mangled = if sym.starts_with("__Z") {
+        &sym[3..]
+    } else if sym.starts_with("_Z") {
+        &sym[2..]
+    } else {
+        return None;
+    };
+    let mut parser = Parser {
+        input: mangled.as_bytes(),
+        pos: 0,
+        depth: 0,
+        subs: vec![],
+        template_args: vec![],
+    };
+    let rv = try_opt!(parser.parse_encoding());
+    if parser.at_end() {
+        Some(rv)
+    } else {
+        None
+    }
+}
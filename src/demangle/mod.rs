@@ -0,0 +1,17 @@
+//! Demangling of symbol names
+//!
+//! System libraries contain symbols mangled with the Itanium C++ ABI and
+//! with the Swift mangling.  This module turns them into readable names.
+//! Only the commonly used parts of both manglings are supported, symbols
+//! that use anything else are not demangled.
+mod cpp;
+mod swift;
+
+/// Demangles a C++ or Swift symbol.
+///
+/// The leading underscore of Mach-O symbols is optional.  If the symbol is
+/// not mangled or uses unsupported parts of the mangling `None` is
+/// returned.
+pub fn demangle(sym: &str) -> Option<String> {
+    cpp::demangle(sym).or_else(|| swift::demangle(sym))
+}
@@ -0,0 +1,491 @@
+//! Swift demangling
+//!
+//! This understands the Swift 4 and 5 mangling for functions,
+//! initializers, deinitializers and variable accessors of non-generic
+//! declarations.  Like the Swift demangler it works as a stack machine:
+//! every operator pops the nodes it applies to and pushes the result.
+
+/// A node on the demangler stack
+#[derive(Clone)]
+enum Node {
+    Identifier(String),
+    Module(String),
+    Type(String),
+    Tuple(Vec<String>),
+    FunctionType(Vec<String>, String, bool),
+    EmptyList,
+    FirstElementMarker,
+    Throws,
+    Entity(String),
+}
+
+/// The limit for the total size of all nodes pushed for a symbol
+const MAX_TOTAL_SIZE: usize = 1024 * 1024;
+
+struct Demangler<'a> {
+    input: &'a [u8],
+    pos: usize,
+    stack: Vec<Node>,
+    subs: Vec<Node>,
+    class_types: Vec<String>,
+    size: usize,
+}
+
+impl Node {
+    /// The number of bytes the node contributes to the output
+    fn size(&self) -> usize {
+        match *self {
+            Node::Identifier(ref s) | Node::Module(ref s) | Node::Type(ref s) |
+            Node::Entity(ref s) => s.len(),
+            Node::Tuple(ref elements) => elements.iter().map(|x| x.len() + 2).sum(),
+            Node::FunctionType(ref params, ref result, _) => {
+                params.iter().map(|x| x.len() + 2).sum::<usize>() + result.len()
+            }
+            Node::EmptyList | Node::FirstElementMarker | Node::Throws => 0,
+        }
+    }
+}
+
+fn standard_type(c: u8) -> Option<&'static str> {
+    Some(match c {
+        b'a' => "Array",
+        b'b' => "Bool",
+        b'D' => "Dictionary",
+        b'd' => "Double",
+        b'f' => "Float",
+        b'h' => "Set",
+        b'i' => "Int",
+        b'J' => "Character",
+        b'N' => "ClosedRange",
+        b'n' => "Range",
+        b'O' => "ObjectIdentifier",
+        b'P' => "UnsafePointer",
+        b'p' => "UnsafeMutablePointer",
+        b'q' => "Optional",
+        b'R' => "UnsafeBufferPointer",
+        b'r' => "UnsafeMutableBufferPointer",
+        b'S' => "String",
+        b's' => "Substring",
+        b'u' => "UInt",
+        b'V' => "UnsafeRawPointer",
+        b'v' => "UnsafeMutableRawPointer",
+        b'W' => "UnsafeRawBufferPointer",
+        b'w' => "UnsafeMutableRawBufferPointer",
+        _ => { return None; }
+    })
+}
+
+fn format_params(labels: &[String], params: &[String]) -> String {
+    if labels.is_empty() {
+        params.join(", ")
+    } else {
+        labels.iter().zip(params.iter())
+            .map(|(label, param)| format!("{}: {}", label, param))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl<'a> Demangler<'a> {
+    fn next(&mut self) -> Option<u8> {
+        let rv = self.input.get(self.pos).cloned();
+        self.pos += 1;
+        rv
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).cloned()
+    }
+
+    fn parse_number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().map_or(false, |c| c >= b'0' && c <= b'9') {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return None;
+        }
+        String::from_utf8_lossy(&self.input[start..self.pos]).parse().ok()
+    }
+
+    /// Rejects repeat counts larger than the input.
+    ///
+    /// Every repeated node ends up in the output so a legitimate count can
+    /// never be this large, but a bogus one would exhaust memory.
+    fn check_count(&self, count: usize) -> Option<usize> {
+        if count > self.input.len() {
+            None
+        } else {
+            Some(count)
+        }
+    }
+
+    /// Checks if the top of the stack is an empty list or a first element
+    /// marker.
+    fn peek_list_markers(&self) -> Option<(bool, bool)> {
+        match self.stack.last() {
+            Some(&Node::EmptyList) => Some((true, false)),
+            Some(&Node::FirstElementMarker) => Some((false, true)),
+            Some(_) => Some((false, false)),
+            None => None,
+        }
+    }
+
+    /// Pushes a node unless the nodes pushed so far get too large.
+    ///
+    /// Substitutions copy nodes, so nested and repeated ones would otherwise
+    /// grow the output exponentially even if every count is checked.
+    fn push(&mut self, node: Node) -> Option<()> {
+        self.size += node.size();
+        if self.size > MAX_TOTAL_SIZE {
+            return None;
+        }
+        self.stack.push(node);
+        Some(())
+    }
+
+    fn push_substitutable(&mut self, node: Node) -> Option<()> {
+        self.subs.push(node.clone());
+        self.push(node)
+    }
+
+    fn pop_identifier(&mut self) -> Option<String> {
+        match self.stack.pop() {
+            Some(Node::Identifier(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn pop_context(&mut self) -> Option<String> {
+        match self.stack.pop() {
+            Some(Node::Identifier(name)) | Some(Node::Module(name)) |
+            Some(Node::Type(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn pop_type(&mut self) -> Option<String> {
+        match self.stack.pop() {
+            Some(Node::Type(ty)) => Some(ty),
+            Some(Node::Tuple(elements)) => Some(format!("({})", elements.join(", "))),
+            Some(Node::EmptyList) => Some("()".into()),
+            Some(Node::FunctionType(params, result, throws)) => {
+                Some(format!("({}){} -> {}", params.join(", "),
+                             if throws { " throws" } else { "" }, result))
+            }
+            _ => None,
+        }
+    }
+
+    /// Pops the result and parameters of a function.
+    ///
+    /// Multiple parameters are mangled as a tuple.
+    fn pop_function_signature(&mut self) -> Option<(Vec<String>, String, bool)> {
+        let throws = match self.stack.last() {
+            Some(&Node::Throws) => true,
+            _ => false,
+        };
+        if throws {
+            self.stack.pop();
+        }
+        let params = match self.stack.pop() {
+            Some(Node::EmptyList) => vec![],
+            Some(Node::Tuple(elements)) => elements,
+            Some(node) => {
+                self.stack.push(node);
+                vec![try_opt!(self.pop_type())]
+            }
+            None => { return None; }
+        };
+        let result = try_opt!(self.pop_type());
+        Some((params, result, throws))
+    }
+
+    /// Pops the argument labels, the name and the context of an entity.
+    ///
+    /// Labels are identifiers between the name and the signature.  There
+    /// is either one for each parameter or none at all.
+    fn pop_entity_name(&mut self, has_name: bool, param_count: usize)
+        -> Option<(String, String, Vec<String>)>
+    {
+        let idents = self.stack.iter().rev().take_while(|node| {
+            match **node {
+                Node::Identifier(_) => true,
+                _ => false,
+            }
+        }).count();
+        // a module context is an identifier too
+        let needed = (if has_name { 1 } else { 0 }) +
+            (if idents == self.stack.len() { 1 } else { 0 });
+        if idents < needed {
+            return None;
+        }
+        let label_count = idents - needed;
+        if label_count != 0 && label_count != param_count {
+            return None;
+        }
+        let mut labels = vec![];
+        for _ in 0..label_count {
+            labels.push(try_opt!(self.pop_identifier()));
+        }
+        labels.reverse();
+        let name = if has_name {
+            try_opt!(self.pop_identifier())
+        } else {
+            String::new()
+        };
+        let context = try_opt!(self.pop_context());
+        Some((context, name, labels))
+    }
+
+    fn demangle_identifier(&mut self) -> Option<()> {
+        // identifiers starting with zero use word substitutions
+        if self.peek() == Some(b'0') {
+            return None;
+        }
+        let len = try_opt!(self.parse_number());
+        let end = try_opt!(self.pos.checked_add(len));
+        let name = try_opt!(self.input.get(self.pos..end));
+        self.pos = end;
+        let name = String::from_utf8_lossy(name).into_owned();
+        try_opt!(self.push_substitutable(Node::Identifier(name)));
+        Some(())
+    }
+
+    fn demangle_standard_type(&mut self) -> Option<()> {
+        let count = self.parse_number().unwrap_or(1);
+        let count = try_opt!(self.check_count(count));
+        let c = try_opt!(self.next());
+        match c {
+            b'g' => {
+                let ty = try_opt!(self.pop_type());
+                try_opt!(self.push(Node::Type(format!("{}?", ty))));
+            }
+            b'o' => try_opt!(self.push(Node::Module("__C".into()))),
+            b'C' => try_opt!(self.push(Node::Module("__C_Synthesized".into()))),
+            _ => {
+                let name = format!("Swift.{}", try_opt!(standard_type(c)));
+                for _ in 0..count {
+                    try_opt!(self.push(Node::Type(name.clone())));
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn demangle_substitution(&mut self) -> Option<()> {
+        loop {
+            let number = self.parse_number();
+            let c = try_opt!(self.next());
+            if c == b'_' {
+                let idx = number.map_or(0, |num| num + 1);
+                let node = try_opt!(self.subs.get(idx).cloned());
+                try_opt!(self.push(node));
+                return Some(());
+            }
+            // multi substitutions are letters with an optional count
+            let count = try_opt!(self.check_count(number.unwrap_or(1)));
+            let idx = match c {
+                b'a'...b'z' => c - b'a',
+                b'A'...b'Z' => c - b'A',
+                _ => { return None; }
+            };
+            let node = try_opt!(self.subs.get(idx as usize).cloned());
+            for _ in 0..count {
+                try_opt!(self.push(node.clone()));
+            }
+            // an uppercase letter ends the substitution
+            if c >= b'A' && c <= b'Z' {
+                return Some(());
+            }
+        }
+    }
+
+    fn demangle_nominal_type(&mut self, is_class: bool) -> Option<()> {
+        let name = try_opt!(self.pop_identifier());
+        let context = try_opt!(self.pop_context());
+        let full_name = format!("{}.{}", context, name);
+        if is_class {
+            self.class_types.push(full_name.clone());
+        }
+        try_opt!(self.push_substitutable(Node::Type(full_name)));
+        Some(())
+    }
+
+    fn demangle_bound_generic_type(&mut self) -> Option<()> {
+        let mut args = vec![];
+        loop {
+            let (is_list, is_marker) = try_opt!(self.peek_list_markers());
+            if is_marker {
+                return None;
+            } else if is_list {
+                self.stack.pop();
+                break;
+            }
+            args.push(try_opt!(self.pop_type()));
+        }
+        args.reverse();
+        let base = match self.stack.pop() {
+            Some(Node::Type(name)) => name,
+            _ => { return None; }
+        };
+        try_opt!(self.push_substitutable(Node::Type(format!("{}<{}>", base, args.join(", ")))));
+        Some(())
+    }
+
+    fn demangle_tuple(&mut self) -> Option<()> {
+        let mut elements = vec![];
+        loop {
+            let (is_list, is_marker) = try_opt!(self.peek_list_markers());
+            if is_list || is_marker {
+                self.stack.pop();
+            }
+            if is_list {
+                break;
+            }
+            elements.push(try_opt!(self.pop_type()));
+            if is_marker {
+                break;
+            }
+        }
+        elements.reverse();
+        try_opt!(self.push(Node::Tuple(elements)));
+        Some(())
+    }
+
+    fn demangle_function(&mut self) -> Option<()> {
+        let (params, result, throws) = try_opt!(self.pop_function_signature());
+        let (context, name, labels) = try_opt!(self.pop_entity_name(true, params.len()));
+        let rv = format!("{}.{}({}){} -> {}", context, name, format_params(&labels, &params),
+                         if throws { " throws" } else { "" }, result);
+        try_opt!(self.push(Node::Entity(rv)));
+        Some(())
+    }
+
+    fn demangle_variable(&mut self) -> Option<()> {
+        let ty = try_opt!(self.pop_type());
+        let name = try_opt!(self.pop_identifier());
+        let context = try_opt!(self.pop_context());
+        let accessor = match try_opt!(self.next()) {
+            b'p' => "",
+            b'g' => ".getter",
+            b's' => ".setter",
+            b'M' => ".modify",
+            b'r' => ".read",
+            b'w' => ".willset",
+            b'W' => ".didset",
+            _ => { return None; }
+        };
+        try_opt!(self.push(Node::Entity(format!("{}.{}{} : {}", context, name, accessor, ty))));
+        Some(())
+    }
+
+    fn demangle_function_like(&mut self) -> Option<()> {
+        let c = try_opt!(self.next());
+        let rv = match c {
+            b'C' | b'c' => {
+                let (params, result, throws) = match self.stack.pop() {
+                    Some(Node::FunctionType(params, result, throws)) => (params, result, throws),
+                    _ => { return None; }
+                };
+                let (context, _, labels) = try_opt!(self.pop_entity_name(false, params.len()));
+                let name = if c == b'C' && self.class_types.contains(&context) {
+                    "__allocating_init"
+                } else {
+                    "init"
+                };
+                format!("{}.{}({}){} -> {}", context, name, format_params(&labels, &params),
+                        if throws { " throws" } else { "" }, result)
+            }
+            b'D' => format!("{}.__deallocating_deinit", try_opt!(self.pop_context())),
+            b'd' => format!("{}.deinit", try_opt!(self.pop_context())),
+            _ => { return None; }
+        };
+        try_opt!(self.push(Node::Entity(rv)));
+        Some(())
+    }
+
+    fn demangle_entity_modifier(&mut self, prefix: &str) -> Option<()> {
+        match self.stack.pop() {
+            Some(Node::Entity(entity)) => {
+                try_opt!(self.push(Node::Entity(format!("{}{}", prefix, entity))));
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    fn demangle_operator(&mut self) -> Option<()> {
+        let c = try_opt!(self.peek());
+        if c >= b'0' && c <= b'9' {
+            return self.demangle_identifier();
+        }
+        self.pos += 1;
+        match c {
+            b'y' => try_opt!(self.push(Node::EmptyList)),
+            b'_' => try_opt!(self.push(Node::FirstElementMarker)),
+            b'K' => try_opt!(self.push(Node::Throws)),
+            b's' => try_opt!(self.push(Node::Module("Swift".into()))),
+            b'S' => { return self.demangle_standard_type(); }
+            b'A' => { return self.demangle_substitution(); }
+            b'C' => { return self.demangle_nominal_type(true); }
+            b'V' | b'O' | b'P' | b'a' => { return self.demangle_nominal_type(false); }
+            b'G' => { return self.demangle_bound_generic_type(); }
+            b't' => { return self.demangle_tuple(); }
+            b'c' => {
+                let (params, result, throws) = try_opt!(self.pop_function_signature());
+                try_opt!(self.push(Node::FunctionType(params, result, throws)));
+            }
+            b'F' => { return self.demangle_function(); }
+            b'v' => { return self.demangle_variable(); }
+            b'f' => { return self.demangle_function_like(); }
+            b'Z' => { return self.demangle_entity_modifier("static "); }
+            b'T' => {
+                if self.next() != Some(b'o') {
+                    return None;
+                }
+                return self.demangle_entity_modifier("@objc ");
+            }
+            _ => { return None; }
+        }
+        Some(())
+    }
+}
+
+/// Demangles a Swift symbol.
+pub fn demangle(sym: &str) -> Option<String> {
+    let sym = if sym.starts_with("_$") || sym.starts_with("__T0") {
+        &sym[1..]
+    } else {
+        sym
+    };
+    let mangled = if sym.starts_with("$s") || sym.starts_with("$S") {
+        &sym[2..]
+    } else if sym.starts_with("_T0") {
+        &sym[3..]
+    } else {
+        return None;
+    };
+
+    let mut demangler = Demangler {
+        input: mangled.as_bytes(),
+        pos: 0,
+        stack: vec![],
+        subs: vec![],
+        class_types: vec![],
+        size: 0,
+    };
+    while demangler.pos < demangler.input.len() {
+        try_opt!(demangler.demangle_operator());
+    }
+    match demangler.stack.pop() {
+        Some(Node::Entity(rv)) => {
+            if demangler.stack.is_empty() {
+                Some(rv)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
@@ -1,70 +1,530 @@
 //! Mach-O Dsym Support
 //!
 //! This module adds support for reading macho files and to extract some
-//! limited set of debug symbols from it.  This is exclusively used for
-//! system symbols from iOS SDKs and similar where actual DWARF info is
-//! not contained, just symbol tabs.
+//! limited set of debug symbols from it.  For system symbols from iOS SDKs
+//! and similar the symbol tables are used together with the Objective-C
+//! runtime metadata.  dSYM bundles additionally provide line information
+//! and functions from their `__DWARF` segment.
+//!
+//! ELF files from Android and Linux are exposed through the same types.
+//! Their variant is identified by the GNU build id.
 use std::io::Cursor;
 use std::path::Path;
 use std::borrow::Cow;
+use std::slice;
+use std::str::from_utf8;
 
 use memmap;
 use uuid::Uuid;
+use byteorder::{ByteOrder, LittleEndian};
 use mach_object::{OFile, Symbol, Section, SymbolIter, SymbolReader, DyLib,
-    LoadCommand, MachCommand, get_arch_name_from_types, get_arch_from_flag,
-    SEG_TEXT, SECT_TEXT, cpu_type_t, cpu_subtype_t};
+    LoadCommand, MachCommand, LinkEditData, get_arch_name_from_types,
+    get_arch_from_flag, SEG_TEXT, S_ATTR_PURE_INSTRUCTIONS, S_ATTR_SOME_INSTRUCTIONS};
 
 use super::{Result, Error, ErrorKind};
+use super::elf::{ElfFile, is_elf};
+use super::dwarf::{LineTable, Subprogram};
+
+/// The name of placeholder symbols for functions that were stripped
+///
+/// Functions that are only known from `LC_FUNCTION_STARTS` are recorded
+/// under this name so that lookups stop at their start.
+pub const STRIPPED_SYMBOL: &'static str = "<redacted>";
 
+const LC_DYLD_CHAINED_FIXUPS: u32 = 0x80000034;
+
+// pointer formats of chained fixups that Objective-C metadata is read from
+const DYLD_CHAINED_PTR_ARM64E: u16 = 1;
+const DYLD_CHAINED_PTR_64: u16 = 2;
+const DYLD_CHAINED_PTR_32: u16 = 3;
+const DYLD_CHAINED_PTR_64_OFFSET: u16 = 6;
+const DYLD_CHAINED_PTR_ARM64E_USERLAND: u16 = 9;
+const DYLD_CHAINED_PTR_ARM64E_USERLAND24: u16 = 12;
 
 enum Backing<'a> {
     Buf(Cow<'a, [u8]>),
     Mmap(memmap::Mmap),
 }
 
-/// Mach-O objects
+enum ObjectFile {
+    MachO(OFile),
+    Elf(ElfFile),
+}
+
+/// Mach-O and ELF objects
 ///
-/// This represents mach objects from either a mmaped file or an in-memory
+/// This represents objects from either a mmaped file or an in-memory
 /// byte slice.
 pub struct Object<'a> {
     backing: Backing<'a>,
-    ofile: OFile,
+    file: ObjectFile,
     variants: Vec<Variant>,
 }
 
 /// Represents an iterator over symbols
 pub struct SymbolIterator<'a> {
     iter: Option<SymbolIter<'a>>,
+    elf_iter: Option<slice::Iter<'a, (u64, String)>>,
 }
 
 /// Provides access to symbols in an object
 pub struct Symbols<'a> {
-    cputype: cpu_type_t,
-    cpusubtype: cpu_subtype_t,
-    ofile: &'a OFile,
+    arch: &'static str,
+    ofile: Option<&'a OFile>,
+    elf: Option<&'a ElfFile>,
     cursor: Cursor<&'a [u8]>,
 }
 
 /// Represents a variant in an object
 pub struct Variant {
-    cputype: cpu_type_t,
-    cpusubtype: cpu_subtype_t,
+    arch: &'static str,
     uuid: Option<Uuid>,
     name: Option<String>,
     vmaddr: u64,
     vmsize: u64,
+    code_sections: Vec<CodeSection>,
+}
+
+/// Reads the data of a variant by virtual address
+struct VmReader<'a> {
+    buffer: &'a [u8],
+    sections: Vec<(&'a str, u64, u64, usize)>,
+    is_64: bool,
+    image_base: u64,
+    pointer_format: Option<u16>,
+}
+
+/// An executable section of a variant
+#[derive(Debug, Clone)]
+pub struct CodeSection {
+    name: String,
+    addr: u64,
+    size: u64,
 }
 
 impl<'a> Symbols<'a> {
     /// Returns the architecture for these symbols
     pub fn arch(&self) -> &str {
-        get_arch_name_from_types(self.cputype, self.cpusubtype).unwrap_or("unknown")
+        self.arch
     }
 
     /// Returns an iterator over contained symbols.
     pub fn iter(&'a mut self) -> SymbolIterator<'a> {
         SymbolIterator {
-            iter: self.ofile.symbols(&mut self.cursor),
+            iter: match self.ofile {
+                Some(ofile) => ofile.symbols(&mut self.cursor),
+                None => None,
+            },
+            elf_iter: self.elf.map(|elf| elf.symbols().iter()),
+        }
+    }
+
+    /// Returns the sorted addresses of all functions in the object.
+    ///
+    /// This is read from the `LC_FUNCTION_STARTS` load command and also
+    /// covers functions that were stripped from the symbol table.  These
+    /// are recorded as `STRIPPED_SYMBOL`.  If the object does not have
+    /// function starts an empty vector is returned.
+    pub fn function_starts(&self) -> Result<Vec<u64>> {
+        let mut rv = vec![];
+        if let Some(&OFile::MachFile { ref commands, .. }) = self.ofile {
+            let mut text_vmaddr = 0;
+            let mut function_starts = None;
+            for &MachCommand(ref load_cmd, _) in commands {
+                match load_cmd {
+                    &LoadCommand::FunctionStarts(LinkEditData { off, size }) => {
+                        function_starts = Some((off as usize, size as usize));
+                    }
+                    &LoadCommand::Segment { ref segname, vmaddr, .. } => {
+                        if segname == SEG_TEXT {
+                            text_vmaddr = vmaddr as u64;
+                        }
+                    }
+                    &LoadCommand::Segment64 { ref segname, vmaddr, .. } => {
+                        if segname == SEG_TEXT {
+                            text_vmaddr = vmaddr as u64;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if let Some((off, size)) = function_starts {
+                let buffer = *self.cursor.get_ref();
+                if off.wrapping_add(size) < off || off + size > buffer.len() {
+                    return Err(ErrorKind::BadMachO.into());
+                }
+                // the function starts are encoded as a zero terminated
+                // sequence of uleb128 deltas starting at the text segment.
+                let data = &buffer[off..off + size];
+                let mut pos = 0;
+                let mut addr = text_vmaddr;
+                while pos < data.len() {
+                    let delta = read_uleb128(data, &mut pos).ok_or_else(|| {
+                        Error::from(ErrorKind::BadMachO)
+                    })?;
+                    if delta == 0 {
+                        break;
+                    }
+                    addr = addr.checked_add(delta).ok_or_else(|| {
+                        Error::from(ErrorKind::BadMachO)
+                    })?;
+                    rv.push(addr);
+                }
+            }
+        }
+        Ok(rv)
+    }
+}
+
+impl<'a> Symbols<'a> {
+    /// Returns the data of a section in the `__DWARF` segment if it exists.
+    ///
+    /// For ELF files the section is looked up with its ELF name, so
+    /// `__debug_line` becomes `.debug_line`.
+    fn dwarf_section(&self, name: &str) -> Result<Option<&'a [u8]>> {
+        if let Some(elf) = self.elf {
+            let sect = match elf.section(&format!(".{}", name.trim_left_matches('_'))) {
+                Some(sect) if sect.has_data() => sect,
+                _ => { return Ok(None); }
+            };
+            let buffer = *self.cursor.get_ref();
+            let off = sect.offset() as usize;
+            let size = sect.size() as usize;
+            if off.wrapping_add(size) < off || off + size > buffer.len() {
+                return Err(ErrorKind::BadElf.into());
+            }
+            return Ok(Some(&buffer[off..off + size]));
+        }
+        if let Some(&OFile::MachFile { ref commands, .. }) = self.ofile {
+            for &MachCommand(ref load_cmd, _) in commands {
+                let sections = match load_cmd {
+                    &LoadCommand::Segment { ref segname, ref sections, .. } |
+                    &LoadCommand::Segment64 { ref segname, ref sections, .. } => {
+                        if segname != "__DWARF" {
+                            continue;
+                        }
+                        sections
+                    }
+                    _ => { continue; }
+                };
+                for sect in sections.iter() {
+                    if sect.sectname != name {
+                        continue;
+                    }
+                    let buffer = *self.cursor.get_ref();
+                    let off = sect.offset as usize;
+                    let size = sect.size as usize;
+                    if off.wrapping_add(size) < off || off + size > buffer.len() {
+                        return Err(ErrorKind::BadMachO.into());
+                    }
+                    return Ok(Some(&buffer[off..off + size]));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn vm_reader(&self) -> VmReader<'a> {
+        let buffer = *self.cursor.get_ref();
+        let mut sections = vec![];
+        let mut is_64 = false;
+        let mut image_base = 0;
+        let mut pointer_format = None;
+        if let Some(&OFile::MachFile { ref commands, .. }) = self.ofile {
+            for &MachCommand(ref load_cmd, _) in commands {
+                let segment_sections = match load_cmd {
+                    &LoadCommand::Segment { ref segname, vmaddr, ref sections, .. } => {
+                        if segname == SEG_TEXT {
+                            image_base = vmaddr as u64;
+                        }
+                        sections
+                    }
+                    &LoadCommand::Segment64 { ref segname, vmaddr, ref sections, .. } => {
+                        if segname == SEG_TEXT {
+                            image_base = vmaddr as u64;
+                        }
+                        is_64 = true;
+                        sections
+                    }
+                    &LoadCommand::Command { cmd: LC_DYLD_CHAINED_FIXUPS, ref payload } => {
+                        // images we cannot tell the format of resolve no
+                        // pointers at all.
+                        pointer_format = Some(get_chained_pointer_format(buffer, payload)
+                            .unwrap_or(0));
+                        continue;
+                    }
+                    _ => { continue; }
+                };
+                for sect in segment_sections.iter() {
+                    sections.push((&sect.sectname[..], sect.addr as u64, sect.size as u64,
+                                   sect.offset as usize));
+                }
+            }
+        }
+        VmReader {
+            buffer: buffer,
+            sections: sections,
+            is_64: is_64,
+            image_base: image_base,
+            pointer_format: pointer_format,
+        }
+    }
+
+    /// Returns the Objective-C methods from the runtime metadata.
+    ///
+    /// The methods of all classes in `__objc_classlist` are returned as
+    /// `(addr, name)` with names like `-[Class selector]`.  This also
+    /// finds methods that were stripped from the symbol table.  Metadata
+    /// that cannot be read is skipped, as are images with chained fixups
+    /// in a pointer format we do not know.
+    pub fn objc_methods(&self) -> Vec<(u64, String)> {
+        let vm = self.vm_reader();
+        let mut rv = vec![];
+        if let Some((addr, size)) = vm.section("__objc_classlist") {
+            let ptr_size = vm.ptr_size();
+            let mut offset = 0;
+            while offset + ptr_size <= size {
+                if let Some(cls) = vm.ptr(addr + offset) {
+                    vm.read_objc_class(cls, &mut rv);
+                }
+                offset += ptr_size;
+            }
+        }
+        rv
+    }
+
+    /// Returns the line table from the DWARF debug info if available.
+    pub fn line_table(&self) -> Result<Option<LineTable>> {
+        match self.dwarf_section("__debug_line")? {
+            Some(data) => Ok(Some(LineTable::parse(data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the functions from the DWARF debug info.
+    ///
+    /// Objects without debug info return an empty vector.
+    pub fn subprograms(&self) -> Result<Vec<Subprogram>> {
+        let debug_info = match self.dwarf_section("__debug_info")? {
+            Some(data) => data,
+            None => { return Ok(vec![]); }
+        };
+        let debug_abbrev = self.dwarf_section("__debug_abbrev")?.unwrap_or(&b""[..]);
+        let debug_str = self.dwarf_section("__debug_str")?.unwrap_or(&b""[..]);
+        Subprogram::parse_all(debug_info, debug_abbrev, debug_str)
+    }
+}
+
+impl<'a> VmReader<'a> {
+    fn ptr_size(&self) -> u64 {
+        if self.is_64 { 8 } else { 4 }
+    }
+
+    fn section(&self, name: &str) -> Option<(u64, u64)> {
+        self.sections.iter()
+            .find(|x| x.0 == name)
+            .map(|x| (x.1, x.2))
+    }
+
+    fn data(&self, addr: u64, len: u64) -> Option<&'a [u8]> {
+        for &(_, sect_addr, sect_size, sect_offset) in self.sections.iter() {
+            if addr < sect_addr || addr - sect_addr >= sect_size {
+                continue;
+            }
+            if len > sect_size - (addr - sect_addr) {
+                return None;
+            }
+            let off = sect_offset + (addr - sect_addr) as usize;
+            return self.buffer.get(off..off + len as usize);
+        }
+        None
+    }
+
+    fn u32(&self, addr: u64) -> Option<u32> {
+        self.data(addr, 4).map(LittleEndian::read_u32)
+    }
+
+    /// Reads a pointer and resolves it to an address.
+    ///
+    /// In images with chained fixups the pointer is decoded according to
+    /// the pointer format of the image.  Binds to other images cannot be
+    /// resolved.  Other images only use the lower 36 bits since dyld
+    /// shared caches keep flags in the upper bits.
+    fn ptr(&self, addr: u64) -> Option<u64> {
+        let rv = if self.is_64 {
+            let raw = try_opt!(self.data(addr, 8).map(LittleEndian::read_u64));
+            if raw == 0 {
+                return None;
+            }
+            match self.pointer_format {
+                Some(format) => try_opt!(self.resolve_chained_ptr(raw, format)),
+                None => raw & 0xf_ffff_ffff,
+            }
+        } else {
+            let raw = try_opt!(self.u32(addr));
+            match self.pointer_format {
+                // the top bit marks binds, rebases use the lower 26 bits
+                Some(DYLD_CHAINED_PTR_32) if raw & 0x8000_0000 == 0 => (raw & 0x3ff_ffff) as u64,
+                Some(_) => { return None; }
+                None => raw as u64,
+            }
+        };
+        if rv == 0 { None } else { Some(rv) }
+    }
+
+    /// Resolves the target of a 64-bit chained rebase.
+    ///
+    /// Depending on the format the target is either an address or an
+    /// offset from the start of the image.  Authenticated arm64e rebases
+    /// always store an offset.
+    fn resolve_chained_ptr(&self, raw: u64, format: u16) -> Option<u64> {
+        let is_bind = raw >> 63 != 0;
+        match format {
+            DYLD_CHAINED_PTR_ARM64E |
+            DYLD_CHAINED_PTR_ARM64E_USERLAND |
+            DYLD_CHAINED_PTR_ARM64E_USERLAND24 => {
+                // arm64e uses the top bit for authentication instead
+                let is_auth = raw >> 63 != 0;
+                if (raw >> 62) & 1 != 0 {
+                    None
+                } else if is_auth {
+                    Some(self.image_base + (raw & 0xffff_ffff))
+                } else if format == DYLD_CHAINED_PTR_ARM64E {
+                    Some(raw & 0x7ff_ffff_ffff)
+                } else {
+                    Some(self.image_base + (raw & 0x7ff_ffff_ffff))
+                }
+            }
+            DYLD_CHAINED_PTR_64 if !is_bind => Some(raw & 0xf_ffff_ffff),
+            DYLD_CHAINED_PTR_64_OFFSET if !is_bind => {
+                Some(self.image_base + (raw & 0xf_ffff_ffff))
+            }
+            _ => None,
+        }
+    }
+
+    fn cstr(&self, addr: u64) -> Option<&'a str> {
+        for &(_, sect_addr, sect_size, sect_offset) in self.sections.iter() {
+            if addr < sect_addr || addr - sect_addr >= sect_size {
+                continue;
+            }
+            let off = sect_offset + (addr - sect_addr) as usize;
+            let end = sect_offset + sect_size as usize;
+            let data = try_opt!(self.buffer.get(off..end));
+            let len = try_opt!(data.iter().position(|&x| x == 0));
+            return from_utf8(&data[..len]).ok();
+        }
+        None
+    }
+
+    /// Adds the instance and class methods of a class.
+    fn read_objc_class(&self, cls: u64, out: &mut Vec<(u64, String)>) -> Option<()> {
+        let ro = try_opt!(self.objc_class_ro(cls));
+        // the name comes after the flags, sizes and ivar layout
+        let name_offset = if self.is_64 { 24 } else { 16 };
+        let class_name = try_opt!(self.ptr(ro + name_offset).and_then(|x| self.cstr(x)));
+        if let Some(methods) = self.ptr(ro + name_offset + self.ptr_size()) {
+            self.read_objc_methods(methods, '-', class_name, out);
+        }
+
+        // class methods are the instance methods of the metaclass
+        if_chain! {
+            if let Some(metacls) = self.ptr(cls);
+            if let Some(meta_ro) = self.objc_class_ro(metacls);
+            if let Some(methods) = self.ptr(meta_ro + name_offset + self.ptr_size());
+            then {
+                self.read_objc_methods(methods, '+', class_name, out);
+            }
+        }
+        Some(())
+    }
+
+    /// Returns the address of the read-only data of a class.
+    fn objc_class_ro(&self, cls: u64) -> Option<u64> {
+        // isa, superclass, cache and vtable come before the data pointer
+        // whose lower bits are flags.
+        self.ptr(cls + 4 * self.ptr_size()).map(|x| x & !7)
+    }
+
+    /// Adds the methods of a method list until an entry cannot be read.
+    fn read_objc_methods(&self, list: u64, prefix: char, class_name: &str,
+                         out: &mut Vec<(u64, String)>) -> Option<()> {
+        let flags = try_opt!(self.u32(list));
+        let count = try_opt!(self.u32(list + 4)) as u64;
+        let entsize = (flags & 0xfffc) as u64;
+        let relative = flags & 0x8000_0000 != 0;
+        let min_entsize = if relative { 12 } else { 3 * self.ptr_size() };
+        if entsize < min_entsize {
+            return None;
+        }
+        for idx in 0..count {
+            let method = list + 8 + idx * entsize;
+            let (sel, imp) = if relative {
+                // relative methods store offsets to a selector reference
+                // and the implementation.
+                let sel_ref = try_opt!(self.relative_addr(method));
+                let imp = try_opt!(self.relative_addr(method + 8));
+                (try_opt!(self.ptr(sel_ref)), imp)
+            } else {
+                let ptr_size = self.ptr_size();
+                (try_opt!(self.ptr(method)), try_opt!(self.ptr(method + 2 * ptr_size)))
+            };
+            if let Some(sel) = self.cstr(sel) {
+                // thumb functions have the lowest bit set
+                out.push((imp & !1, format!("{}[{} {}]", prefix, class_name, sel)));
+            }
+        }
+        Some(())
+    }
+
+    fn relative_addr(&self, addr: u64) -> Option<u64> {
+        let offset = try_opt!(self.u32(addr)) as i32;
+        Some((addr as i64 + offset as i64) as u64)
+    }
+}
+
+/// Reads the pointer format from the `LC_DYLD_CHAINED_FIXUPS` data.
+///
+/// The format of the first segment with fixups is used as images do not
+/// mix formats.
+fn get_chained_pointer_format(buffer: &[u8], payload: &[u8]) -> Option<u16> {
+    let read_u32 = |data: &[u8], offset: usize| {
+        data.get(offset..offset + 4).map(LittleEndian::read_u32)
+    };
+    let data_offset = try_opt!(read_u32(payload, 0)) as usize;
+    let data_size = try_opt!(read_u32(payload, 4)) as usize;
+    let data_end = try_opt!(data_offset.checked_add(data_size));
+    let data = try_opt!(buffer.get(data_offset..data_end));
+
+    // the starts of the segments come after the header
+    let starts = try_opt!(read_u32(data, 4)) as usize;
+    let seg_count = try_opt!(read_u32(data, starts)) as usize;
+    for idx in 0..seg_count {
+        let seg_offset = try_opt!(read_u32(data, starts + 4 + idx * 4)) as usize;
+        if seg_offset == 0 {
+            continue;
+        }
+        // size and page size come before the pointer format
+        let format_offset = starts + seg_offset + 6;
+        return data.get(format_offset..format_offset + 2).map(LittleEndian::read_u16);
+    }
+    None
+}
+
+/// Reads an unsigned LEB128 number and advances the position.
+pub fn read_uleb128(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut rv = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = try_opt!(data.get(*pos));
+        *pos += 1;
+        if shift < 64 {
+            rv |= ((byte & 0x7f) as u64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(rv);
         }
     }
 }
@@ -73,13 +533,15 @@ impl<'a> Iterator for SymbolIterator<'a> {
     type Item = (u64, &'a str);
 
     fn next(&mut self) -> Option<(u64, &'a str)> {
+        if let Some(ref mut elf_iter) = self.elf_iter {
+            return elf_iter.next().map(|&(addr, ref name)| (addr, name.as_str()));
+        }
         let iter = try_opt!(self.iter.as_mut());
         while let Some(sym) = iter.next() {
             if let Symbol::Defined { ref name, ref section, entry, .. } = sym {
                 if name.is_some() {
                     if let &Some(ref sect) = section {
-                        let Section { ref sectname, ref segname, .. } = **sect;
-                        if segname == SEG_TEXT && sectname == SECT_TEXT {
+                        if is_code_section(sect) {
                             return Some((entry as u64, name.unwrap()));
                         }
                     }
@@ -106,13 +568,33 @@ impl<'a> Backing<'a> {
     }
 }
 
+/// Checks if a section contains instructions.
+fn is_code_section(sect: &Section) -> bool {
+    let attrs = sect.flags.sect_attrs();
+    attrs.contains(S_ATTR_PURE_INSTRUCTIONS) || attrs.contains(S_ATTR_SOME_INSTRUCTIONS)
+}
+
 fn extract_variant<'a>(variants: &'a mut Vec<Variant>, file: &'a OFile) {
     if let &OFile::MachFile { ref header, ref commands, .. } = file {
         let mut variant_uuid = None;
         let mut variant_name = None;
         let mut variant_vmaddr = 0;
         let mut variant_vmsize = 0;
+        let mut code_sections = vec![];
         for &MachCommand(ref load_cmd, _) in commands {
+            match load_cmd {
+                &LoadCommand::Segment { ref sections, .. } |
+                &LoadCommand::Segment64 { ref sections, .. } => {
+                    for sect in sections.iter().filter(|x| is_code_section(x)) {
+                        code_sections.push(CodeSection {
+                            name: sect.sectname.clone(),
+                            addr: sect.addr as u64,
+                            size: sect.size as u64,
+                        });
+                    }
+                }
+                _ => {}
+            }
             match load_cmd {
                 &LoadCommand::Uuid(uuid) => {
                     variant_uuid = Some(uuid);
@@ -136,21 +618,57 @@ fn extract_variant<'a>(variants: &'a mut Vec<Variant>, file: &'a OFile) {
             }
         }
         variants.push(Variant {
-            cputype: header.cputype,
-            cpusubtype: header.cpusubtype,
+            arch: get_arch_name_from_types(header.cputype, header.cpusubtype)
+                .unwrap_or("unknown"),
             uuid: variant_uuid,
             name: variant_name,
             vmaddr: variant_vmaddr,
             vmsize: variant_vmsize,
+            code_sections: code_sections,
         })
     }
 }
 
+/// Adds the single variant of an ELF file.
+///
+/// ELF files do not record their install path so the variant has no name.
+fn extract_elf_variant(variants: &mut Vec<Variant>, elf: &ElfFile) {
+    variants.push(Variant {
+        arch: elf.arch(),
+        uuid: elf.uuid(),
+        name: None,
+        vmaddr: elf.vmaddr(),
+        vmsize: elf.vmsize(),
+        code_sections: elf.sections().iter()
+            .filter(|sect| sect.is_code())
+            .map(|sect| CodeSection {
+                name: sect.name().to_string(),
+                addr: sect.addr(),
+                size: sect.size(),
+            })
+            .collect(),
+    })
+}
+
 impl<'a> Object<'a> {
 
-    fn from_backing(backing: Backing<'a>) -> Result<Object<'a>> {
-        let ofile = OFile::parse(&mut backing.cursor(0))?;
+    fn from_backing(backing: Backing<'a>, offset: u64) -> Result<Object<'a>> {
         let mut variants = vec![];
+        if offset == 0 && is_elf(backing.buffer()) {
+            let elf = ElfFile::parse(backing.buffer())?;
+            extract_elf_variant(&mut variants, &elf);
+            return Ok(Object {
+                backing: backing,
+                file: ObjectFile::Elf(elf),
+                variants: variants,
+            });
+        }
+
+        let ofile = {
+            let mut cursor = backing.cursor(0);
+            cursor.set_position(offset);
+            OFile::parse(&mut cursor)?
+        };
 
         match ofile {
             OFile::FatFile { ref files, .. } => {
@@ -166,29 +684,39 @@ impl<'a> Object<'a> {
 
         Ok(Object {
             backing: backing,
-            ofile: ofile,
+            file: ObjectFile::MachO(ofile),
             variants: variants,
         })
     }
 
     fn from_cow(cow: Cow<'a, [u8]>) -> Result<Object<'a>> {
-        Object::from_backing(Backing::Buf(cow))
+        Object::from_backing(Backing::Buf(cow), 0)
     }
 
-    /// Parses a macho object from a given slice.
+    /// Parses an object from a given slice.
     pub fn from_slice(buf: &'a [u8]) -> Result<Object<'a>> {
         Object::from_cow(Cow::Borrowed(buf))
     }
 
-    /// Parses a macho object from a vector.
+    /// Parses an object from a vector.
     pub fn from_vec(buf: Vec<u8>) -> Result<Object<'a>> {
         Object::from_cow(Cow::Owned(buf))
     }
 
-    /// Parses a macho object from a memory mapped file.
+    /// Parses an object from a memory mapped file.
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Object<'a>> {
         let mmap = memmap::Mmap::open_path(path, memmap::Protection::Read)?;
-        Object::from_backing(Backing::Mmap(mmap))
+        Object::from_backing(Backing::Mmap(mmap), 0)
+    }
+
+    /// Parses a macho object that is embedded in a memory mapped file.
+    ///
+    /// The mach header is at `offset` but the file offsets in its load
+    /// commands are relative to the start of the file.  This is how images
+    /// are stored in dyld shared caches.
+    pub fn from_path_at<P: AsRef<Path>>(path: P, offset: u64) -> Result<Object<'a>> {
+        let mmap = memmap::Mmap::open_path(path, memmap::Protection::Read)?;
+        Object::from_backing(Backing::Mmap(mmap), offset)
     }
 
     /// Return a slice of the variants
@@ -201,18 +729,34 @@ impl<'a> Object<'a> {
     ///
     /// The architecture can be found from the provided variant.
     pub fn symbols<'b>(&'a self, arch: &'b str) -> Result<Symbols<'a>> {
+        let ofile = match self.file {
+            ObjectFile::MachO(ref ofile) => ofile,
+            ObjectFile::Elf(ref elf) => {
+                if elf.arch() != arch {
+                    return Err(ErrorKind::MissingArchitecture(arch.to_string()).into());
+                }
+                return Ok(Symbols {
+                    arch: elf.arch(),
+                    ofile: None,
+                    elf: Some(elf),
+                    cursor: self.backing.cursor(0),
+                });
+            }
+        };
+
         let &(cputype, cpusubtype) = get_arch_from_flag(arch).ok_or_else(|| {
             Error::from(ErrorKind::UnknownArchitecture(arch.to_string()))
         })?;
 
-        match self.ofile {
+        match *ofile {
             OFile::FatFile { ref files, .. } => {
                 for &(ref arch, ref file) in files {
                     if arch.cputype == cputype && arch.cpusubtype == cpusubtype {
                         return Ok(Symbols {
-                            cputype: arch.cputype,
-                            cpusubtype: arch.cpusubtype,
-                            ofile: file,
+                            arch: get_arch_name_from_types(arch.cputype, arch.cpusubtype)
+                                .unwrap_or("unknown"),
+                            ofile: Some(file),
+                            elf: None,
                             cursor: self.backing.cursor(arch.offset as usize),
                         });
                     }
@@ -221,9 +765,10 @@ impl<'a> Object<'a> {
             OFile::MachFile { ref header, .. } => {
                 if header.cputype == cputype && header.cpusubtype == cpusubtype {
                     return Ok(Symbols {
-                        cputype: header.cputype,
-                        cpusubtype: header.cpusubtype,
-                        ofile: &self.ofile,
+                        arch: get_arch_name_from_types(header.cputype, header.cpusubtype)
+                            .unwrap_or("unknown"),
+                        ofile: Some(ofile),
+                        elf: None,
                         cursor: self.backing.cursor(0),
                     });
                 }
@@ -238,7 +783,7 @@ impl<'a> Object<'a> {
 impl Variant {
     /// Returns the architecture of this variant
     pub fn arch(&self) -> &str {
-        get_arch_name_from_types(self.cputype, self.cpusubtype).unwrap_or("unknown")
+        self.arch
     }
 
     /// Returns the contained name of the variant
@@ -251,7 +796,8 @@ impl Variant {
     /// Returns the UUID of the variant
     ///
     /// Normally mach-o files have contained UUIDs.  In case we have one, it's
-    /// being returned here.
+    /// being returned here.  For ELF files this is derived from the GNU
+    /// build id.
     pub fn uuid(&self) -> Option<Uuid> {
         self.uuid
     }
@@ -265,4 +811,26 @@ impl Variant {
     pub fn vmsize(&self) -> u64 {
         self.vmsize
     }
+
+    /// The sections of the variant that contain instructions
+    pub fn code_sections(&self) -> &[CodeSection] {
+        &self.code_sections
+    }
+}
+
+impl CodeSection {
+    /// The name of the section (eg: `__stubs`)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The vmaddr of the section
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// The size of the section
+    pub fn size(&self) -> u64 {
+        self.size
+    }
 }
@@ -0,0 +1,538 @@
+//! DWARF Support
+//!
+//! This module reads the parts of the DWARF debug information that are
+//! needed for symbolication: the line programs in `__debug_line` and the
+//! subprograms in `__debug_info`.  Only DWARF versions 2 to 4 as emitted
+//! by Apple's toolchains are supported.
+use std::str::from_utf8;
+use std::collections::HashMap;
+
+use super::{Result, Error, ErrorKind};
+use super::dsym::read_uleb128;
+
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_LINKAGE_NAME: u64 = 0x6e;
+const DW_AT_MIPS_LINKAGE_NAME: u64 = 0x2007;
+
+const DW_FORM_ADDR: u64 = 0x01;
+const DW_FORM_BLOCK2: u64 = 0x03;
+const DW_FORM_BLOCK4: u64 = 0x04;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_BLOCK1: u64 = 0x0a;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_FLAG: u64 = 0x0c;
+const DW_FORM_SDATA: u64 = 0x0d;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_REF_ADDR: u64 = 0x10;
+const DW_FORM_REF1: u64 = 0x11;
+const DW_FORM_REF2: u64 = 0x12;
+const DW_FORM_REF4: u64 = 0x13;
+const DW_FORM_REF8: u64 = 0x14;
+const DW_FORM_REF_UDATA: u64 = 0x15;
+const DW_FORM_INDIRECT: u64 = 0x16;
+const DW_FORM_SEC_OFFSET: u64 = 0x17;
+const DW_FORM_EXPRLOC: u64 = 0x18;
+const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+const DW_FORM_REF_SIG8: u64 = 0x20;
+
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+const DW_LNE_DEFINE_FILE: u8 = 3;
+
+
+/// The rows of all line programs in a `__debug_line` section
+pub struct LineTable {
+    files: Vec<String>,
+    rows: Vec<LineRow>,
+}
+
+/// A row in a line table
+///
+/// A row with a line of zero marks the end of a sequence.
+#[derive(Debug, Clone, Copy)]
+pub struct LineRow {
+    addr: u64,
+    file: usize,
+    line: u32,
+}
+
+/// A function described in `__debug_info`
+#[derive(Debug)]
+pub struct Subprogram {
+    name: String,
+    low_pc: u64,
+    high_pc: u64,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+struct Abbrev {
+    tag: u64,
+    attrs: Vec<(u64, u64)>,
+}
+
+enum Value<'a> {
+    Addr(u64),
+    Unsigned(u64),
+    Str(&'a str),
+    StrOffset(u64),
+    Other,
+}
+
+fn bad_dwarf() -> Error {
+    Error::from(ErrorKind::BadDwarf)
+}
+
+impl<'a> Reader<'a> {
+
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader {
+            data: data,
+            pos: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.data.len() - self.pos {
+            return Err(bad_dwarf());
+        }
+        let rv = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(rv)
+    }
+
+    fn uint(&mut self, size: usize) -> Result<u64> {
+        let mut rv = 0u64;
+        for (idx, &byte) in self.bytes(size)?.iter().enumerate() {
+            rv |= (byte as u64) << (idx * 8);
+        }
+        Ok(rv)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(self.uint(2)? as u16)
+    }
+
+    fn uleb(&mut self) -> Result<u64> {
+        read_uleb128(self.data, &mut self.pos).ok_or_else(bad_dwarf)
+    }
+
+    fn sleb(&mut self) -> Result<i64> {
+        let mut rv = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                rv |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    rv |= -1 << shift;
+                }
+                return Ok(rv);
+            }
+        }
+    }
+
+    fn cstr(&mut self) -> Result<&'a str> {
+        let len = self.data[self.pos..].iter().position(|&x| x == 0)
+            .ok_or_else(bad_dwarf)?;
+        let rv = from_utf8(self.bytes(len)?)?;
+        self.pos += 1;
+        Ok(rv)
+    }
+
+    /// Reads a unit length and returns the unit and the size of offsets.
+    fn unit(&mut self) -> Result<(Reader<'a>, usize)> {
+        let mut len = self.uint(4)?;
+        let mut offset_size = 4;
+        if len == 0xffff_ffff {
+            len = self.uint(8)?;
+            offset_size = 8;
+        }
+        if len > (self.data.len() - self.pos) as u64 {
+            return Err(bad_dwarf());
+        }
+        Ok((Reader::new(self.bytes(len as usize)?), offset_size))
+    }
+}
+
+impl LineTable {
+    /// Runs all line programs in a `__debug_line` section
+    ///
+    /// The rows are sorted by address.
+    pub fn parse(data: &[u8]) -> Result<LineTable> {
+        let mut rv = LineTable {
+            files: vec![],
+            rows: vec![],
+        };
+        let mut files_map = HashMap::new();
+        let mut reader = Reader::new(data);
+        while !reader.is_empty() {
+            let (mut unit, offset_size) = reader.unit()?;
+            let version = unit.u16()?;
+            if version < 2 || version > 4 {
+                continue;
+            }
+            let header_len = unit.uint(offset_size)? as usize;
+            let program_start = unit.pos + header_len;
+            let min_inst_len = unit.u8()? as u64;
+            if version >= 4 {
+                unit.u8()?;
+            }
+            let _default_is_stmt = unit.u8()?;
+            let line_base = unit.u8()? as i8 as i64;
+            let line_range = unit.u8()? as u64;
+            let opcode_base = unit.u8()?;
+            if line_range == 0 || opcode_base == 0 {
+                return Err(bad_dwarf());
+            }
+            let opcode_lengths = unit.bytes(opcode_base as usize - 1)?;
+
+            let mut dirs = vec![];
+            loop {
+                let dir = unit.cstr()?;
+                if dir.is_empty() {
+                    break;
+                }
+                dirs.push(dir);
+            }
+
+            // file indexes of the program are mapped to the shared list
+            let mut files = vec![];
+            loop {
+                let name = unit.cstr()?;
+                if name.is_empty() {
+                    break;
+                }
+                let dir = unit.uleb()? as usize;
+                unit.uleb()?;
+                unit.uleb()?;
+                files.push(rv.add_file(&mut files_map, &dirs, dir, name));
+            }
+
+            if program_start > unit.data.len() {
+                return Err(bad_dwarf());
+            }
+            unit.pos = program_start;
+
+            let mut addr = 0u64;
+            let mut file = 1u64;
+            let mut line = 1i64;
+            macro_rules! emit_row {
+                ($line:expr) => {
+                    if let Some(&file) = files.get((file as usize).wrapping_sub(1)) {
+                        rv.rows.push(LineRow {
+                            addr: addr,
+                            file: file,
+                            line: $line,
+                        });
+                    }
+                }
+            }
+
+            while !unit.is_empty() {
+                let opcode = unit.u8()?;
+                if opcode >= opcode_base {
+                    let adj = (opcode - opcode_base) as u64;
+                    addr = addr.wrapping_add((adj / line_range) * min_inst_len);
+                    line += line_base + (adj % line_range) as i64;
+                    emit_row!(line as u32);
+                    continue;
+                }
+                match opcode {
+                    0 => {
+                        let len = unit.uleb()? as usize;
+                        let mut ext = Reader::new(unit.bytes(len)?);
+                        match ext.u8()? {
+                            DW_LNE_END_SEQUENCE => {
+                                emit_row!(0);
+                                addr = 0;
+                                file = 1;
+                                line = 1;
+                            }
+                            DW_LNE_SET_ADDRESS => {
+                                let size = ext.data.len() - 1;
+                                if size > 8 {
+                                    return Err(bad_dwarf());
+                                }
+                                addr = ext.uint(size)?;
+                            }
+                            DW_LNE_DEFINE_FILE => {
+                                let name = ext.cstr()?;
+                                let dir = ext.uleb()? as usize;
+                                files.push(rv.add_file(&mut files_map, &dirs, dir, name));
+                            }
+                            _ => {}
+                        }
+                    }
+                    DW_LNS_COPY => {
+                        emit_row!(line as u32);
+                    }
+                    DW_LNS_ADVANCE_PC => {
+                        addr = addr.wrapping_add(unit.uleb()? * min_inst_len);
+                    }
+                    DW_LNS_ADVANCE_LINE => {
+                        line += unit.sleb()?;
+                    }
+                    DW_LNS_SET_FILE => {
+                        file = unit.uleb()?;
+                    }
+                    DW_LNS_CONST_ADD_PC => {
+                        let adj = (255 - opcode_base) as u64;
+                        addr = addr.wrapping_add((adj / line_range) * min_inst_len);
+                    }
+                    DW_LNS_FIXED_ADVANCE_PC => {
+                        addr = addr.wrapping_add(unit.u16()? as u64);
+                    }
+                    _ => {
+                        // other standard opcodes only have operands we skip
+                        for _ in 0..opcode_lengths[opcode as usize - 1] {
+                            unit.uleb()?;
+                        }
+                    }
+                }
+            }
+        }
+
+        // ends of sequences go before rows starting at the same address
+        rv.rows.sort_by_key(|row| (row.addr, row.line != 0));
+        Ok(rv)
+    }
+
+    fn add_file(&mut self, files_map: &mut HashMap<String, usize>, dirs: &[&str],
+                dir: usize, name: &str) -> usize {
+        let path = match dirs.get(dir.wrapping_sub(1)) {
+            Some(dir) if !name.starts_with('/') => format!("{}/{}", dir, name),
+            _ => name.to_string(),
+        };
+        if let Some(&idx) = files_map.get(&path) {
+            return idx;
+        }
+        self.files.push(path.clone());
+        files_map.insert(path, self.files.len() - 1);
+        self.files.len() - 1
+    }
+
+    /// The file names the rows refer to
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// The rows sorted by address
+    pub fn rows(&self) -> &[LineRow] {
+        &self.rows
+    }
+}
+
+impl LineRow {
+    /// The address of the row
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// The index of the file name in the line table
+    pub fn file(&self) -> usize {
+        self.file
+    }
+
+    /// The line number or zero for the end of a sequence
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+}
+
+impl Subprogram {
+    /// Finds all named subprograms with an address range
+    ///
+    /// The linkage name is preferred over the plain name so that the names
+    /// match the ones in the symbol table.
+    pub fn parse_all(debug_info: &[u8], debug_abbrev: &[u8], debug_str: &[u8])
+        -> Result<Vec<Subprogram>>
+    {
+        let mut rv = vec![];
+        let mut abbrevs_cache = HashMap::new();
+        let mut reader = Reader::new(debug_info);
+        while !reader.is_empty() {
+            let (mut unit, offset_size) = reader.unit()?;
+            let version = unit.u16()?;
+            if version < 2 || version > 4 {
+                continue;
+            }
+            let abbrev_offset = unit.uint(offset_size)? as usize;
+            let address_size = unit.u8()? as usize;
+            if !abbrevs_cache.contains_key(&abbrev_offset) {
+                let abbrevs = parse_abbrevs(debug_abbrev, abbrev_offset)?;
+                abbrevs_cache.insert(abbrev_offset, abbrevs);
+            }
+            let abbrevs = &abbrevs_cache[&abbrev_offset];
+
+            while !unit.is_empty() {
+                let code = unit.uleb()?;
+                if code == 0 {
+                    continue;
+                }
+                let abbrev = abbrevs.get(&code).ok_or_else(bad_dwarf)?;
+                let mut name = None;
+                let mut linkage_name = None;
+                let mut low_pc = None;
+                let mut high_pc = None;
+                for &(attr, form) in abbrev.attrs.iter() {
+                    let value = read_value(&mut unit, form, version, offset_size,
+                                           address_size)?;
+                    if abbrev.tag != DW_TAG_SUBPROGRAM {
+                        continue;
+                    }
+                    match attr {
+                        DW_AT_NAME => { name = Some(value); }
+                        DW_AT_LINKAGE_NAME | DW_AT_MIPS_LINKAGE_NAME => {
+                            linkage_name = Some(value);
+                        }
+                        DW_AT_LOW_PC => { low_pc = Some(value); }
+                        DW_AT_HIGH_PC => { high_pc = Some(value); }
+                        _ => {}
+                    }
+                }
+
+                let low_pc = match low_pc {
+                    Some(Value::Addr(addr)) => addr,
+                    _ => { continue; }
+                };
+                let high_pc = match high_pc {
+                    Some(Value::Addr(addr)) => addr,
+                    Some(Value::Unsigned(len)) => low_pc.wrapping_add(len),
+                    _ => low_pc,
+                };
+                let name = match linkage_name.or(name) {
+                    Some(Value::Str(name)) => name,
+                    Some(Value::StrOffset(offset)) => get_str(debug_str, offset)?,
+                    _ => { continue; }
+                };
+                rv.push(Subprogram {
+                    name: name.to_string(),
+                    low_pc: low_pc,
+                    high_pc: high_pc,
+                });
+            }
+        }
+        Ok(rv)
+    }
+
+    /// The name of the function
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The address of the first instruction
+    pub fn low_pc(&self) -> u64 {
+        self.low_pc
+    }
+
+    /// The address after the last instruction
+    pub fn high_pc(&self) -> u64 {
+        self.high_pc
+    }
+}
+
+fn parse_abbrevs(data: &[u8], offset: usize) -> Result<HashMap<u64, Abbrev>> {
+    if offset > data.len() {
+        return Err(bad_dwarf());
+    }
+    let mut rv = HashMap::new();
+    let mut reader = Reader::new(&data[offset..]);
+    loop {
+        let code = reader.uleb()?;
+        if code == 0 {
+            break;
+        }
+        let tag = reader.uleb()?;
+        // the entries are read in order so the children flag is not needed
+        reader.u8()?;
+        let mut attrs = vec![];
+        loop {
+            let attr = reader.uleb()?;
+            let form = reader.uleb()?;
+            if attr == 0 && form == 0 {
+                break;
+            }
+            attrs.push((attr, form));
+        }
+        rv.insert(code, Abbrev {
+            tag: tag,
+            attrs: attrs,
+        });
+    }
+    Ok(rv)
+}
+
+fn read_value<'a>(reader: &mut Reader<'a>, form: u64, version: u16, offset_size: usize,
+                  address_size: usize) -> Result<Value<'a>> {
+    Ok(match form {
+        DW_FORM_ADDR => Value::Addr(reader.uint(address_size)?),
+        DW_FORM_DATA1 | DW_FORM_REF1 | DW_FORM_FLAG => Value::Unsigned(reader.uint(1)?),
+        DW_FORM_DATA2 | DW_FORM_REF2 => Value::Unsigned(reader.uint(2)?),
+        DW_FORM_DATA4 | DW_FORM_REF4 => Value::Unsigned(reader.uint(4)?),
+        DW_FORM_DATA8 | DW_FORM_REF8 | DW_FORM_REF_SIG8 => Value::Unsigned(reader.uint(8)?),
+        DW_FORM_UDATA | DW_FORM_REF_UDATA => Value::Unsigned(reader.uleb()?),
+        DW_FORM_SDATA => { reader.sleb()?; Value::Other }
+        DW_FORM_STRING => Value::Str(reader.cstr()?),
+        DW_FORM_STRP => Value::StrOffset(reader.uint(offset_size)?),
+        DW_FORM_SEC_OFFSET => Value::Unsigned(reader.uint(offset_size)?),
+        DW_FORM_REF_ADDR => {
+            // DWARF 2 used the address size for references
+            let size = if version == 2 { address_size } else { offset_size };
+            Value::Unsigned(reader.uint(size)?)
+        }
+        DW_FORM_BLOCK1 => { let len = reader.u8()? as usize; reader.bytes(len)?; Value::Other }
+        DW_FORM_BLOCK2 => { let len = reader.uint(2)? as usize; reader.bytes(len)?; Value::Other }
+        DW_FORM_BLOCK4 => { let len = reader.uint(4)? as usize; reader.bytes(len)?; Value::Other }
+        DW_FORM_BLOCK | DW_FORM_EXPRLOC => {
+            let len = reader.uleb()? as usize;
+            reader.bytes(len)?;
+            Value::Other
+        }
+        DW_FORM_FLAG_PRESENT => Value::Other,
+        DW_FORM_INDIRECT => {
+            let form = reader.uleb()?;
+            return read_value(reader, form, version, offset_size, address_size);
+        }
+        _ => { return Err(bad_dwarf()); }
+    })
+}
+
+fn get_str(data: &[u8], offset: u64) -> Result<&str> {
+    let offset = offset as usize;
+    if offset >= data.len() {
+        return Err(bad_dwarf());
+    }
+    let mut reader = Reader::new(&data[offset..]);
+    reader.cstr()
+}
@@ -0,0 +1,362 @@
+//! ELF Support
+//!
+//! Android and Linux system libraries are ELF files.  This module reads the
+//! parts of such a file that are needed to index it: the GNU build id, the
+//! loaded text range, the executable sections and the function symbols.
+//! Only little endian files are supported.
+use std::cmp;
+use std::str::from_utf8;
+
+use uuid::Uuid;
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::{Result, ErrorKind};
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOTE: u32 = 7;
+const SHT_NOBITS: u32 = 8;
+const SHT_DYNSYM: u32 = 11;
+const SHF_EXECINSTR: u64 = 4;
+
+const STT_FUNC: u8 = 2;
+const SHN_UNDEF: u16 = 0;
+const NT_GNU_BUILD_ID: u32 = 3;
+
+const EM_386: u16 = 3;
+const EM_MIPS: u16 = 8;
+const EM_ARM: u16 = 40;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+
+/// A section of an ELF file
+#[derive(Debug, Clone)]
+pub struct ElfSection {
+    name: String,
+    kind: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+}
+
+/// The indexable parts of an ELF file
+pub struct ElfFile {
+    arch: &'static str,
+    build_id: Option<Vec<u8>>,
+    vmaddr: u64,
+    vmsize: u64,
+    sections: Vec<ElfSection>,
+    symbols: Vec<(u64, String)>,
+}
+
+/// Checks if the data starts like an ELF file.
+pub fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(b"\x7fELF")
+}
+
+/// Reads fields whose size depends on the ELF class
+struct Reader<'a> {
+    data: &'a [u8],
+    is_64: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn get_data(&self, offset: u64, len: u64) -> Result<&'a [u8]> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.data.len() as u64 => {
+                Ok(&self.data[offset as usize..end as usize])
+            }
+            _ => Err(ErrorKind::BadElf.into()),
+        }
+    }
+
+    fn u8(&self, offset: u64) -> Result<u8> {
+        Ok(self.get_data(offset, 1)?[0])
+    }
+
+    fn u16(&self, offset: u64) -> Result<u16> {
+        Ok(LittleEndian::read_u16(self.get_data(offset, 2)?))
+    }
+
+    fn u32(&self, offset: u64) -> Result<u32> {
+        Ok(LittleEndian::read_u32(self.get_data(offset, 4)?))
+    }
+
+    fn u64(&self, offset: u64) -> Result<u64> {
+        Ok(LittleEndian::read_u64(self.get_data(offset, 8)?))
+    }
+
+    /// Reads an address or offset which is 32 bit in 32 bit files.
+    fn addr(&self, offset: u64) -> Result<u64> {
+        if self.is_64 {
+            self.u64(offset)
+        } else {
+            self.u32(offset).map(|x| x as u64)
+        }
+    }
+
+    fn cstr(&self, offset: u64) -> Result<&'a str> {
+        let rest = self.data.get(offset as usize..).ok_or_else(|| ErrorKind::BadElf)?;
+        let len = rest.iter().position(|&x| x == 0).ok_or_else(|| ErrorKind::BadElf)?;
+        Ok(from_utf8(&rest[..len])?)
+    }
+
+    /// Finds the GNU build id in a region of notes.
+    fn build_id(&self, offset: u64, size: u64) -> Result<Option<Vec<u8>>> {
+        let align = |x: u64| (x + 3) & !3;
+        let end = offset.checked_add(size).ok_or_else(|| ErrorKind::BadElf)?;
+        let mut pos = offset;
+        while pos + 12 <= end {
+            let name_size = self.u32(pos)? as u64;
+            let desc_size = self.u32(pos + 4)? as u64;
+            let kind = self.u32(pos + 8)?;
+            let desc_offset = pos + 12 + align(name_size);
+            if kind == NT_GNU_BUILD_ID && self.get_data(pos + 12, name_size)? == b"GNU\x00" {
+                return Ok(Some(self.get_data(desc_offset, desc_size)?.to_vec()));
+            }
+            pos = desc_offset + align(desc_size);
+        }
+        Ok(None)
+    }
+}
+
+fn get_arch_name(machine: u16, is_64: bool) -> &'static str {
+    match machine {
+        EM_386 => "x86",
+        EM_X86_64 => "x86_64",
+        EM_ARM => "arm",
+        EM_AARCH64 => "arm64",
+        EM_MIPS => if is_64 { "mips64" } else { "mips" },
+        _ => "unknown",
+    }
+}
+
+impl ElfFile {
+    /// Parses an ELF file.
+    pub fn parse(data: &[u8]) -> Result<ElfFile> {
+        if !is_elf(data) || data.len() < 16 {
+            return Err(ErrorKind::BadElf.into());
+        }
+        let is_64 = match data[4] {
+            1 => false,
+            2 => true,
+            _ => { return Err(ErrorKind::BadElf.into()); }
+        };
+        if data[5] != 1 {
+            return Err(ErrorKind::BadElf.into());
+        }
+        let r = Reader { data: data, is_64: is_64 };
+
+        let machine = r.u16(18)?;
+        let (phoff, shoff) = if is_64 {
+            (r.u64(32)?, r.u64(40)?)
+        } else {
+            (r.u32(28)? as u64, r.u32(32)? as u64)
+        };
+        // the sizes and counts come after the flags
+        let counts = if is_64 { 54 } else { 42 };
+        let phentsize = r.u16(counts)? as u64;
+        let phnum = r.u16(counts + 2)? as u64;
+        let shentsize = r.u16(counts + 4)? as u64;
+        let shnum = r.u16(counts + 6)? as u64;
+        let shstrndx = r.u16(counts + 8)? as usize;
+
+        // the image starts with the first loaded segment and the symbols
+        // end with the last executable one.
+        let mut vmaddr = None;
+        let mut text_end = 0;
+        let mut build_id = None;
+        for idx in 0..phnum {
+            let off = phoff + idx * phentsize;
+            let (flags, offset, vaddr, filesz, memsz) = if is_64 {
+                (r.u32(off + 4)?, r.u64(off + 8)?, r.u64(off + 16)?,
+                 r.u64(off + 32)?, r.u64(off + 40)?)
+            } else {
+                (r.u32(off + 24)?, r.u32(off + 4)? as u64, r.u32(off + 8)? as u64,
+                 r.u32(off + 16)? as u64, r.u32(off + 20)? as u64)
+            };
+            match r.u32(off)? {
+                PT_LOAD => {
+                    vmaddr = Some(cmp::min(vmaddr.unwrap_or(vaddr), vaddr));
+                    if flags & PF_X != 0 {
+                        text_end = cmp::max(text_end, vaddr + memsz);
+                    }
+                }
+                PT_NOTE if build_id.is_none() => {
+                    build_id = r.build_id(offset, filesz)?;
+                }
+                _ => {}
+            }
+        }
+
+        let mut sections = vec![];
+        for idx in 0..shnum {
+            let off = shoff + idx * shentsize;
+            let (flags, addr, offset, size, link) = if is_64 {
+                (r.u64(off + 8)?, r.u64(off + 16)?, r.u64(off + 24)?,
+                 r.u64(off + 32)?, r.u32(off + 40)?)
+            } else {
+                (r.u32(off + 8)? as u64, r.u32(off + 12)? as u64, r.u32(off + 16)? as u64,
+                 r.u32(off + 20)? as u64, r.u32(off + 24)?)
+            };
+            sections.push(ElfSection {
+                name: String::new(),
+                kind: r.u32(off + 4)?,
+                flags: flags,
+                addr: addr,
+                offset: offset,
+                size: size,
+                link: link,
+            });
+        }
+        if shstrndx < sections.len() {
+            let names_offset = sections[shstrndx].offset;
+            for idx in 0..sections.len() {
+                let name_offset = r.u32(shoff + idx as u64 * shentsize)? as u64;
+                sections[idx].name = r.cstr(names_offset + name_offset)?.to_string();
+            }
+        }
+
+        // object files have no program headers but keep the build id in
+        // the note section.
+        if build_id.is_none() {
+            for sect in sections.iter().filter(|x| x.kind == SHT_NOTE) {
+                build_id = r.build_id(sect.offset, sect.size)?;
+                if build_id.is_some() {
+                    break;
+                }
+            }
+        }
+
+        Ok(ElfFile {
+            arch: get_arch_name(machine, is_64),
+            build_id: build_id,
+            vmaddr: vmaddr.unwrap_or(0),
+            vmsize: text_end.saturating_sub(vmaddr.unwrap_or(0)),
+            symbols: read_symbols(&r, &sections, machine)?,
+            sections: sections,
+        })
+    }
+
+    /// The architecture of the file (eg: `arm64`)
+    pub fn arch(&self) -> &'static str {
+        self.arch
+    }
+
+    /// The GNU build id if the file has one
+    pub fn build_id(&self) -> Option<&[u8]> {
+        self.build_id.as_ref().map(|x| &x[..])
+    }
+
+    /// The build id as UUID
+    ///
+    /// Like Breakpad this uses the first 16 bytes of the build id read as
+    /// a little endian GUID, so the first three fields are byte swapped.
+    /// Shorter build ids are padded with zeros.
+    pub fn uuid(&self) -> Option<Uuid> {
+        let build_id = try_opt!(self.build_id.as_ref());
+        if build_id.is_empty() {
+            return None;
+        }
+        let mut bytes = [0u8; 16];
+        let len = cmp::min(16, build_id.len());
+        bytes[..len].copy_from_slice(&build_id[..len]);
+        bytes[0..4].reverse();
+        bytes[4..6].reverse();
+        bytes[6..8].reverse();
+        Uuid::from_bytes(&bytes).ok()
+    }
+
+    /// The address of the first loaded segment
+    pub fn vmaddr(&self) -> u64 {
+        self.vmaddr
+    }
+
+    /// The size from `vmaddr` to the end of the last executable segment
+    pub fn vmsize(&self) -> u64 {
+        self.vmsize
+    }
+
+    /// The sections of the file
+    pub fn sections(&self) -> &[ElfSection] {
+        &self.sections
+    }
+
+    /// Finds a section by name.
+    pub fn section(&self, name: &str) -> Option<&ElfSection> {
+        self.sections.iter().find(|x| x.name == name)
+    }
+
+    /// The function symbols as `(addr, name)`
+    ///
+    /// These come from `.symtab` and if the file was stripped from
+    /// `.dynsym`.
+    pub fn symbols(&self) -> &[(u64, String)] {
+        &self.symbols
+    }
+}
+
+/// Reads the defined function symbols of the most complete symbol table.
+fn read_symbols(r: &Reader, sections: &[ElfSection], machine: u16) -> Result<Vec<(u64, String)>> {
+    let mut rv = vec![];
+    let symtab = match sections.iter().find(|x| x.kind == SHT_SYMTAB)
+            .or_else(|| sections.iter().find(|x| x.kind == SHT_DYNSYM)) {
+        Some(symtab) => symtab,
+        None => { return Ok(rv); }
+    };
+    let strtab = sections.get(symtab.link as usize).ok_or_else(|| ErrorKind::BadElf)?;
+    let entsize = if r.is_64 { 24 } else { 16 };
+    for idx in 0..symtab.size / entsize {
+        let off = symtab.offset + idx * entsize;
+        let (info, shndx, value) = if r.is_64 {
+            (r.u8(off + 4)?, r.u16(off + 6)?, r.u64(off + 8)?)
+        } else {
+            (r.u8(off + 12)?, r.u16(off + 14)?, r.u32(off + 4)? as u64)
+        };
+        let name_offset = r.u32(off)? as u64;
+        if info & 0xf != STT_FUNC || shndx == SHN_UNDEF || value == 0 || name_offset == 0 {
+            continue;
+        }
+        // thumb functions have the lowest bit set
+        let addr = if machine == EM_ARM { value & !1 } else { value };
+        rv.push((addr, r.cstr(strtab.offset + name_offset)?.to_string()));
+    }
+    Ok(rv)
+}
+
+impl ElfSection {
+    /// The name of the section (eg: `.text`)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The vmaddr of the section
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// The file offset of the section
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The size of the section
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Checks if the section contains instructions.
+    pub fn is_code(&self) -> bool {
+        self.flags & SHF_EXECINSTR != 0
+    }
+
+    /// Checks if the section has data in the file.
+    pub fn has_data(&self) -> bool {
+        self.kind != SHT_NOBITS
+    }
+}
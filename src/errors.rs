@@ -33,6 +33,32 @@ error_chain! {
         BadMemDb {
             description("bad memdb file")
         }
+        MemDbChecksumMismatch {
+            description("memdb checksum mismatch")
+        }
+        BadMachO {
+            description("bad mach-o file")
+        }
+        BadDwarf {
+            description("bad DWARF data")
+        }
+        BadSharedCache {
+            description("bad dyld shared cache")
+        }
+        BadElf {
+            description("bad ELF file")
+        }
+        BadBreakpad {
+            description("bad breakpad symbol file")
+        }
+        SdkInfoTooLong(what: &'static str, limit: usize) {
+            description("SDK info does not fit into a memdb")
+            display("SDK {} is longer than {} bytes", what, limit)
+        }
+        MemDbLimitExceeded(what: &'static str) {
+            description("memdb limit exceeded")
+            display("memdb limit exceeded: too many {}", what)
+        }
         ConfigError(err: serde_yaml::Error) {
             description("failed to load config file")
             display("failed to load config file: {}", err)
@@ -19,6 +19,8 @@ extern crate clap;
 extern crate console;
 extern crate indicatif;
 extern crate xz2;
+extern crate brotli2;
+extern crate byteorder;
 extern crate tempdir;
 extern crate tempfile;
 extern crate humansize;
@@ -47,6 +49,11 @@ pub mod config;
 pub mod s3;
 pub mod cli;
 pub mod dsym;
+pub mod elf;
+pub mod dwarf;
+pub mod demangle;
+pub mod breakpad;
+pub mod shared_cache;
 pub mod sdk;
 pub mod api;
 pub mod constants;
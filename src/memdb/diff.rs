@@ -0,0 +1,170 @@
+//! Compares the contents of two memdb files
+//!
+//! Objects are matched up by their `name:arch` tagged names.  For objects
+//! that exist in both files but changed their UUID the symbols are
+//! compared as well.  Names can have several UUIDs in one file (for
+//! instance when two SDK builds were merged), in that case the UUIDs that
+//! cannot be matched up are reported as added and removed objects.
+use std::collections::{BTreeMap, BTreeSet};
+
+use uuid::Uuid;
+
+use super::read::MemDb;
+use super::super::Result;
+
+
+/// An object that only exists in one of the memdb files
+#[derive(Serialize, Debug)]
+pub struct ObjectRef {
+    name: String,
+    uuid: Uuid,
+}
+
+/// An object that changed its UUID between the memdb files
+#[derive(Serialize, Debug)]
+pub struct ObjectChange {
+    name: String,
+    old_uuid: Uuid,
+    new_uuid: Uuid,
+    added_symbols: Vec<String>,
+    removed_symbols: Vec<String>,
+}
+
+/// The differences between two memdb files
+#[derive(Serialize, Debug)]
+pub struct MemDbDiff {
+    added_objects: Vec<ObjectRef>,
+    removed_objects: Vec<ObjectRef>,
+    changed_objects: Vec<ObjectChange>,
+}
+
+impl ObjectRef {
+
+    /// The `name:arch` tagged name of the object
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The UUID of the object
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+}
+
+impl ObjectChange {
+
+    /// The `name:arch` tagged name of the object
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The UUID of the object in the old file
+    pub fn old_uuid(&self) -> Uuid {
+        self.old_uuid
+    }
+
+    /// The UUID of the object in the new file
+    pub fn new_uuid(&self) -> Uuid {
+        self.new_uuid
+    }
+
+    /// The symbols that only exist in the new file
+    pub fn added_symbols(&self) -> &[String] {
+        &self.added_symbols
+    }
+
+    /// The symbols that only exist in the old file
+    pub fn removed_symbols(&self) -> &[String] {
+        &self.removed_symbols
+    }
+}
+
+impl MemDbDiff {
+
+    /// Objects that only exist in the new file
+    pub fn added_objects(&self) -> &[ObjectRef] {
+        &self.added_objects
+    }
+
+    /// Objects that only exist in the old file
+    pub fn removed_objects(&self) -> &[ObjectRef] {
+        &self.removed_objects
+    }
+
+    /// Objects that exist in both files with different UUIDs
+    pub fn changed_objects(&self) -> &[ObjectChange] {
+        &self.changed_objects
+    }
+
+    /// Indicates that both files contain the same objects
+    pub fn is_empty(&self) -> bool {
+        self.added_objects.is_empty() &&
+        self.removed_objects.is_empty() &&
+        self.changed_objects.is_empty()
+    }
+}
+
+fn get_objects(memdb: &MemDb) -> Result<BTreeMap<String, BTreeSet<Uuid>>> {
+    let mut rv = BTreeMap::new();
+    for (name, uuid) in memdb.list_tagged_objects()? {
+        rv.entry(name).or_insert_with(BTreeSet::new).insert(uuid);
+    }
+    Ok(rv)
+}
+
+fn get_symbols(memdb: &MemDb, uuid: &Uuid) -> Result<BTreeSet<String>> {
+    let mut rv = BTreeSet::new();
+    for sym_rv in memdb.iter_symbols(uuid)? {
+        rv.insert(sym_rv?.symbol().to_string());
+    }
+    Ok(rv)
+}
+
+/// Compares an old and a new memdb file
+pub fn diff_memdbs(old: &MemDb, new: &MemDb) -> Result<MemDbDiff> {
+    let old_objects = get_objects(old)?;
+    let new_objects = get_objects(new)?;
+    let mut rv = MemDbDiff {
+        added_objects: vec![],
+        removed_objects: vec![],
+        changed_objects: vec![],
+    };
+
+    let no_uuids = BTreeSet::new();
+    let names: BTreeSet<_> = old_objects.keys().chain(new_objects.keys()).collect();
+    for name in names {
+        let old_uuids = old_objects.get(name).unwrap_or(&no_uuids);
+        let new_uuids = new_objects.get(name).unwrap_or(&no_uuids);
+        let removed: Vec<_> = old_uuids.difference(new_uuids).cloned().collect();
+        let added: Vec<_> = new_uuids.difference(old_uuids).cloned().collect();
+
+        // only a single UUID on both sides can be compared symbol by symbol
+        if removed.len() == 1 && added.len() == 1 {
+            let old_symbols = get_symbols(old, &removed[0])?;
+            let new_symbols = get_symbols(new, &added[0])?;
+            rv.changed_objects.push(ObjectChange {
+                name: name.clone(),
+                old_uuid: removed[0],
+                new_uuid: added[0],
+                added_symbols: new_symbols.difference(&old_symbols).cloned().collect(),
+                removed_symbols: old_symbols.difference(&new_symbols).cloned().collect(),
+            });
+            continue;
+        }
+
+        for uuid in removed {
+            rv.removed_objects.push(ObjectRef {
+                name: name.clone(),
+                uuid: uuid,
+            });
+        }
+        for uuid in added {
+            rv.added_objects.push(ObjectRef {
+                name: name.clone(),
+                uuid: uuid,
+            });
+        }
+    }
+
+    Ok(rv)
+}
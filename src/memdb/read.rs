@@ -4,23 +4,35 @@
 //! in-memory database format which is a flat file on the file system
 //! that gets mmaped into the process.
 use std::str::from_utf8;
-use std::mem;
-use std::slice;
+use std::io::Read;
 use std::path::Path;
+use std::marker::PhantomData;
 use std::borrow::Cow;
-use std::ffi::CStr;
-use std::os::raw::c_char;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
 
 use std::fmt;
+use md5;
 use uuid::Uuid;
 use memmap::{Mmap, Protection};
+use brotli2::read::BrotliDecoder;
+use byteorder::{ByteOrder, LittleEndian};
 
-use super::types::{IndexItem, StoredSlice, MemDbHeader, IndexedUuid};
-use super::super::{Result, ErrorKind};
+use super::types::{IndexItem, StoredSlice, MemDbHeader, IndexedUuid, Stored,
+                   NameIndexItem, ObjectNameIndexItem, SectionEntry, SectionKind,
+                   ChecksumEntry, UuidSdkItem, LineItem, CodeSectionItem,
+                   CodeSectionKind, MEMDB_VERSION};
+use super::super::{Result, Error, ErrorKind};
 use super::super::sdk::SdkInfo;
-use super::super::utils::binsearch_by_key;
+use super::super::dsym::STRIPPED_SYMBOL;
+use super::super::demangle::demangle;
+use super::super::utils::binsearch_idx_by_key;
 
 
+/// The number of decompressed string blocks kept in memory per memdb.
+const BLOCK_CACHE_SIZE: usize = 16;
+
 enum Backing<'a> {
     Buf(Cow<'a, [u8]>),
     Mmap(Mmap),
@@ -28,8 +40,10 @@ enum Backing<'a> {
 
 /// Provides access to a memdb file
 pub struct MemDb<'a> {
+    header: MemDbHeader,
     info: SdkInfo,
-    backing: Backing<'a>
+    backing: Backing<'a>,
+    block_cache: Mutex<Vec<(usize, Arc<Vec<u8>>)>>,
 }
 
 /// Represents a symbol from a memdb file.
@@ -39,25 +53,125 @@ pub struct Symbol<'a> {
     object_name: Cow<'a, str>,
     symbol: Cow<'a, str>,
     addr: u64,
+    filename: Option<&'a str>,
+    lineno: Option<u32>,
+    section_kind: Option<CodeSectionKind>,
+}
+
+/// Represents an entry in the symbol index of an object.
+///
+/// Unlike `Symbol` this also covers the markers for the end of functions
+/// and images which have no symbol.
+#[derive(Debug)]
+pub struct IndexEntry<'a> {
+    object_name: Cow<'a, str>,
+    symbol: Option<Cow<'a, str>>,
+    addr: u64,
+}
+
+/// Represents a symbol found by name in a memdb file.
+#[derive(Debug)]
+pub struct NamedSymbol<'a> {
+    object_uuid: Uuid,
+    object_name: &'a str,
+    arch: &'a str,
+    symbol: Cow<'a, str>,
+    addr: u64,
+    size: Option<u64>,
+}
+
+/// Controls how symbol names are matched by `lookup_by_name`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NameMatch {
+    /// The symbol name has to match exactly
+    Exact,
+    /// The symbol name has to start with the given string
+    Prefix,
+}
+
+/// Iterates over the results of a lookup by symbol name
+pub struct NamedSymbolIter<'a> {
+    memdb: &'a MemDb<'a>,
+    index: Table<'a, NameIndexItem>,
+    name: String,
+    mode: NameMatch,
+    pos: usize,
 }
 
 /// Represents a symbol iterator
 pub struct SymbolIter<'a> {
     memdb: &'a MemDb<'a>,
-    uuid: &'a Uuid,
-    index: &'a [IndexItem],
+    uuid: Uuid,
+    index: Table<'a, IndexItem>,
     pos: usize,
 }
 
+/// Iterates over the raw symbol index of an object
+pub struct IndexEntryIter<'a> {
+    memdb: &'a MemDb<'a>,
+    index: Table<'a, IndexItem>,
+    pos: usize,
+}
+
+/// A view on a table of items whose layout depends on the file version.
+struct Table<'a, T> {
+    data: &'a [u8],
+    version: u32,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T: Stored> Table<'a, T> {
+
+    fn new(data: &'a [u8], version: u32) -> Table<'a, T> {
+        Table {
+            data: data,
+            version: version,
+            _marker: PhantomData,
+        }
+    }
+
+    fn empty() -> Table<'a, T> {
+        Table::new(&[][..], 0)
+    }
+
+    fn len(&self) -> usize {
+        if self.data.is_empty() {
+            0
+        } else {
+            self.data.len() / T::stored_size(self.version)
+        }
+    }
+
+    fn get(&self, idx: usize) -> Option<T> {
+        if idx >= self.len() {
+            return None;
+        }
+        let size = T::stored_size(self.version);
+        Some(T::decode(&self.data[idx * size..(idx + 1) * size], self.version))
+    }
+
+    /// Like `get` but fails with `BadMemDb` for indexes out of range.
+    fn get_checked(&self, idx: usize) -> Result<T> {
+        self.get(idx).ok_or_else(|| Error::from(ErrorKind::BadMemDb))
+    }
+
+    /// Finds the last item with a key smaller or equal to the given one.
+    fn binsearch_by_key<B: Ord, F: Fn(&T) -> B>(&self, key: B, f: F) -> Option<(usize, T)> {
+        binsearch_idx_by_key(self.len(), key, |idx| f(&self.get(idx).unwrap()))
+            .map(|idx| (idx, self.get(idx).unwrap()))
+    }
+}
+
 impl<'a> Iterator for SymbolIter<'a> {
     type Item = Result<Symbol<'a>>;
 
     fn next(&mut self) -> Option<Result<Symbol<'a>>> {
         loop {
-            if self.pos < self.index.len() {
-                let ii = &self.index[self.pos];
+            if let Some(ii) = self.index.get(self.pos) {
                 self.pos += 1;
-                match self.memdb.index_item_to_symbol(ii, self.uuid) {
+                match self.memdb.index_item_to_symbol(&ii, &self.uuid) {
+                    // placeholders for stripped functions are not listed
+                    Ok(Some(ref sym)) if sym.symbol() == STRIPPED_SYMBOL => { continue; }
                     Ok(Some(sym)) => { return Some(Ok(sym)); }
                     Ok(None) => { continue; }
                     Err(err) => { return Some(Err(err)); }
@@ -69,6 +183,52 @@ impl<'a> Iterator for SymbolIter<'a> {
     }
 }
 
+impl<'a> Iterator for IndexEntryIter<'a> {
+    type Item = Result<IndexEntry<'a>>;
+
+    fn next(&mut self) -> Option<Result<IndexEntry<'a>>> {
+        let ii = match self.index.get(self.pos) {
+            Some(ii) => ii,
+            None => { return None; }
+        };
+        self.pos += 1;
+        Some(self.memdb.index_item_to_entry(&ii))
+    }
+}
+
+impl<'a> Iterator for NamedSymbolIter<'a> {
+    type Item = Result<NamedSymbol<'a>>;
+
+    fn next(&mut self) -> Option<Result<NamedSymbol<'a>>> {
+        let item = match self.index.get(self.pos) {
+            Some(item) => item,
+            None => { return None; }
+        };
+        self.pos += 1;
+        let symbol = iter_try!(self.memdb.get_symbol_name(item.sym_id()));
+        let is_match = match self.mode {
+            NameMatch::Exact => &*symbol == self.name.as_str(),
+            NameMatch::Prefix => symbol.starts_with(self.name.as_str()),
+        };
+        if !is_match {
+            self.pos = self.index.len();
+            return None;
+        }
+        Some(self.memdb.name_index_item_to_symbol(&item, symbol))
+    }
+}
+
+impl<'a> fmt::Display for NamedSymbol<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:016x} {} ({}:{})",
+               self.object_uuid(),
+               self.addr(),
+               self.symbol(),
+               self.object_name(),
+               self.arch())
+    }
+}
+
 impl<'a> fmt::Display for Symbol<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:016x} {} ({})",
@@ -90,21 +250,33 @@ impl<'a> Backing<'a> {
         }
     }
 
-    fn get_slice<T>(&self, offset: usize, count: usize) -> Result<&[T]> {
-        let size = mem::size_of::<T>();
-        Ok(unsafe {
-            slice::from_raw_parts(
-                mem::transmute(self.get_data(offset, count * size)?.as_ptr()),
-                count
-            )
-        })
+    fn get_table<T: Stored>(&self, offset: usize, count: usize, version: u32)
+        -> Result<Table<T>>
+    {
+        let len = count.checked_mul(T::stored_size(version)).ok_or_else(|| {
+            Error::from(ErrorKind::BadMemDb)
+        })?;
+        Ok(Table::new(self.get_data(offset, len)?, version))
     }
 
-    #[inline(always)]
-    fn header(&self) -> Result<&MemDbHeader> {
-        unsafe {
-            Ok(mem::transmute(self.get_data(0, mem::size_of::<MemDbHeader>())?.as_ptr()))
+    fn header(&self) -> Result<MemDbHeader> {
+        let version = LittleEndian::read_u32(self.get_data(0, 4)?);
+        if version < 2 || version > MEMDB_VERSION {
+            return Err(ErrorKind::UnsupportedMemDbVersion.into());
+        }
+        let size = MemDbHeader::stored_size(version);
+        let mut header = MemDbHeader::decode(self.get_data(0, size)?, version);
+
+        // the widened index layout was written as version 3 for a while.
+        // The tagged object names directly follow the UUID index so its
+        // size tells the layouts apart.
+        let wide_uuids_end = (header.uuids_start as u64) +
+            (header.uuids_count as u64) * IndexedUuid::stored_size(4) as u64;
+        if version == 3 && header.uuids_count > 0 &&
+           wide_uuids_end == header.tagged_object_names_start as u64 {
+            header.version = 4;
         }
+        Ok(header)
     }
 
     #[inline(always)]
@@ -133,24 +305,96 @@ impl<'a> Symbol<'a> {
         &self.symbol
     }
 
+    /// The demangled symbol if it is a C++ or Swift symbol
+    pub fn demangled_symbol(&self) -> Option<String> {
+        demangle(&self.symbol)
+    }
+
+    /// The symbol address as u64
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// The source file of the looked up address if line info is available
+    pub fn filename(&self) -> Option<&str> {
+        self.filename
+    }
+
+    /// The line of the looked up address if line info is available
+    pub fn lineno(&self) -> Option<u32> {
+        self.lineno
+    }
+
+    /// The kind of section the looked up address is in if it is known
+    pub fn section_kind(&self) -> Option<CodeSectionKind> {
+        self.section_kind
+    }
+}
+
+impl<'a> IndexEntry<'a> {
+
+    /// The object name a string
+    pub fn object_name(&self) -> &str {
+        &self.object_name
+    }
+
+    /// The symbol as string or `None` for end markers
+    pub fn symbol(&self) -> Option<&str> {
+        self.symbol.as_ref().map(|x| &**x)
+    }
+
+    /// The address as u64
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+}
+
+impl<'a> NamedSymbol<'a> {
+
+    /// The uuid of the image
+    pub fn object_uuid(&self) -> Uuid {
+        self.object_uuid.clone()
+    }
+
+    /// The object name a string
+    pub fn object_name(&self) -> &str {
+        self.object_name
+    }
+
+    /// The architecture of the image
+    pub fn arch(&self) -> &str {
+        self.arch
+    }
+
+    /// The symbol as string
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
     /// The symbol address as u64
     pub fn addr(&self) -> u64 {
         self.addr
     }
+
+    /// The size of the symbol if known
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
 }
 
 fn load_memdb<'a>(backing: Backing<'a>) -> Result<MemDb<'a>> {
-    let info = {
-        let header = backing.header()?;
-        if header.version != 2 {
-            return Err(ErrorKind::UnsupportedMemDbVersion.into());
-        }
-        header.sdk_info.to_sdk_info()
-    };
-    Ok(MemDb {
+    let header = backing.header()?;
+    let info = header.sdk_info.to_sdk_info().ok_or_else(|| {
+        Error::from(ErrorKind::BadMemDb)
+    })?;
+    let rv = MemDb {
+        header: header,
         backing: backing,
         info: info,
-    })
+        block_cache: Mutex::new(vec![]),
+    };
+    rv.validate()?;
+    Ok(rv)
 }
 
 impl<'a> MemDb<'a> {
@@ -182,6 +426,10 @@ impl<'a> MemDb<'a> {
     }
 
     /// Finds a symbol by UUID and address.
+    ///
+    /// If the address falls outside of the function the closest symbol
+    /// belongs to (for instance because it's in a stripped function or
+    /// padding) `None` is returned.
     pub fn lookup_by_uuid(&'a self, uuid: &Uuid, addr: u64) -> Option<Symbol<'a>> {
         self.lookup_impl(uuid, addr).ok().and_then(|x| x)
     }
@@ -191,22 +439,39 @@ impl<'a> MemDb<'a> {
         -> Option<Symbol<'a>>
     {
         if let Ok(Some(uuid)) = self.find_uuid(object_name, arch) {
-            self.lookup_impl(uuid, addr).ok().and_then(|x| x)
+            self.lookup_impl(&uuid, addr).ok().and_then(|x| x)
         } else {
             None
         }
     }
 
     /// Given an object namd and architecture this finds the image UUID in the file.
-    pub fn find_uuid(&self, object_name: &str, arch: &str) -> Result<Option<&Uuid>> {
-        let header = self.backing.header()?;
-        let mut offset = header.tagged_object_names_start as usize;
+    pub fn find_uuid(&self, object_name: &str, arch: &str) -> Result<Option<Uuid>> {
         let refstr = format!("{}:{}", object_name, arch);
+        if let Some(index) = self.object_name_index()? {
+            let mut low = 0;
+            let mut high = index.len();
+            while low < high {
+                let mid = (low + high) / 2;
+                let item = index.get_checked(mid)?;
+                match self.get_cstr(item.tagged_object_name())?.cmp(refstr.as_str()) {
+                    Ordering::Less => { low = mid + 1; }
+                    Ordering::Greater => { high = mid; }
+                    Ordering::Equal => {
+                        return Ok(Some(*self.uuids()?.get_checked(item.uuid_idx())?.uuid()));
+                    }
+                }
+            }
+            return Ok(None);
+        }
+
+        // files without an object name index need to be scanned
+        let mut offset = self.header.tagged_object_names_start as usize;
         let mut uuid_idx = 0;
-        while offset < header.tagged_object_names_end as usize {
+        while offset < self.header.tagged_object_names_end as usize {
             let s = self.get_cstr(offset)?;
             if s == &refstr {
-                return Ok(Some(&self.uuids()?[uuid_idx].uuid));
+                return Ok(self.uuids()?.get(uuid_idx).map(|x| x.uuid));
             }
             offset += s.len() + 1;
             uuid_idx += 1;
@@ -216,12 +481,12 @@ impl<'a> MemDb<'a> {
 
     /// Given object name and architecture or UUID as string, this finds the
     /// UUID in the file.
-    pub fn find_uuid_fuzzy(&self, name_or_uuid: &str) -> Result<Option<&Uuid>> {
+    pub fn find_uuid_fuzzy(&self, name_or_uuid: &str) -> Result<Option<Uuid>> {
         if let Ok(parsed_uuid) = name_or_uuid.parse::<Uuid>() {
             let uuids = self.uuids()?;
-            if let Some(item) = binsearch_by_key(uuids, parsed_uuid, |item| *item.uuid()) {
+            if let Some((_, item)) = uuids.binsearch_by_key(parsed_uuid, |item| *item.uuid()) {
                 if item.uuid() == &parsed_uuid {
-                    return Ok(Some(item.uuid()));
+                    return Ok(Some(*item.uuid()));
                 }
             }
             return Ok(None)
@@ -239,98 +504,634 @@ impl<'a> MemDb<'a> {
         }
     }
 
+    /// Returns all `name:arch` tagged object names with their UUIDs.
+    pub fn list_tagged_objects(&self) -> Result<Vec<(String, Uuid)>> {
+        let mut rv = vec![];
+        let uuids = self.uuids()?;
+        if let Some(index) = self.object_name_index()? {
+            for idx in 0..index.len() {
+                let item = index.get_checked(idx)?;
+                rv.push((self.get_cstr(item.tagged_object_name())?.to_string(),
+                         *uuids.get_checked(item.uuid_idx())?.uuid()));
+            }
+            return Ok(rv);
+        }
+
+        // files without an object name index store one name per variant
+        let mut offset = self.header.tagged_object_names_start as usize;
+        let mut uuid_idx = 0;
+        while offset < self.header.tagged_object_names_end as usize {
+            let s = self.get_cstr(offset)?;
+            rv.push((s.to_string(), *uuids.get_checked(uuid_idx)?.uuid()));
+            offset += s.len() + 1;
+            uuid_idx += 1;
+        }
+        Ok(rv)
+    }
+
+    /// Returns the `name:arch` tagged object name of a variant.
+    ///
+    /// If several objects have the same UUID the first one is returned.
+    pub fn get_tagged_object_name(&self, uuid: &Uuid) -> Result<Option<String>> {
+        let uuid_idx = match self.uuids()?.binsearch_by_key(*uuid, |item| *item.uuid()) {
+            Some((uuid_idx, ref iuuid)) if iuuid.uuid() == uuid => uuid_idx,
+            _ => { return Ok(None); }
+        };
+
+        if let Some(names) = self.uuid_object_names()? {
+            let item = names.get_checked(uuid_idx)?;
+            if item.uuid_idx() != uuid_idx {
+                return Err(ErrorKind::BadMemDb.into());
+            }
+            return Ok(Some(self.get_cstr(item.tagged_object_name())?.to_string()));
+        }
+
+        // older files need to be scanned but only the name of the object
+        // we found is read from the file
+        if let Some(index) = self.object_name_index()? {
+            for idx in 0..index.len() {
+                let item = index.get_checked(idx)?;
+                if item.uuid_idx() == uuid_idx {
+                    return Ok(Some(self.get_cstr(item.tagged_object_name())?.to_string()));
+                }
+            }
+            return Ok(None);
+        }
+        let mut offset = self.header.tagged_object_names_start as usize;
+        for _ in 0..uuid_idx {
+            offset += self.get_cstr(offset)?.len() + 1;
+        }
+        Ok(Some(self.get_cstr(offset)?.to_string()))
+    }
+
+    /// Returns the SDKs the file was built from.
+    ///
+    /// For files that were not merged from other memdbs this is the SDK
+    /// of the file itself.
+    pub fn source_sdks(&self) -> Result<Vec<SdkInfo>> {
+        let data = match self.get_section_data(SectionKind::SourceSdks)? {
+            Some(data) => data,
+            None => { return Ok(vec![self.info.clone()]); }
+        };
+        let mut rv = vec![];
+        for sdk_id in from_utf8(data)?.split('\x00').filter(|x| !x.is_empty()) {
+            rv.push(SdkInfo::from_filename(sdk_id).ok_or_else(|| {
+                Error::from(ErrorKind::BadMemDb)
+            })?);
+        }
+        Ok(rv)
+    }
+
+    /// Returns the metadata recorded when the file was built.
+    ///
+    /// This contains at least the `converter_version` key for files written
+    /// by this version.  Older files have no metadata.
+    pub fn metadata(&self) -> Result<BTreeMap<String, String>> {
+        let mut rv = BTreeMap::new();
+        let data = match self.get_section_data(SectionKind::Metadata)? {
+            Some(data) => data,
+            None => { return Ok(rv); }
+        };
+        if data.last().map_or(false, |&x| x != 0) {
+            return Err(ErrorKind::BadMemDb.into());
+        }
+        let mut iter = from_utf8(data)?.split('\x00');
+        while let Some(key) = iter.next() {
+            if key.is_empty() {
+                break;
+            }
+            let value = iter.next().ok_or_else(|| Error::from(ErrorKind::BadMemDb))?;
+            rv.insert(key.to_string(), value.to_string());
+        }
+        Ok(rv)
+    }
+
+    /// Indicates that the file records the source SDKs of its variants.
+    pub fn is_merged(&self) -> Result<bool> {
+        Ok(self.uuid_sdk_index()?.is_some())
+    }
+
+    /// Returns the SDKs an object variant was found in.
+    pub fn sdks_for_uuid(&self, uuid: &Uuid) -> Result<Vec<SdkInfo>> {
+        let uuids = self.uuids()?;
+        let uuid_idx = match uuids.binsearch_by_key(*uuid, |item| *item.uuid()) {
+            Some((uuid_idx, iuuid)) => {
+                if iuuid.uuid() != uuid {
+                    return Ok(vec![]);
+                }
+                uuid_idx
+            }
+            None => { return Ok(vec![]); }
+        };
+        let index = match self.uuid_sdk_index()? {
+            Some(index) => index,
+            None => { return Ok(vec![self.info.clone()]); }
+        };
+
+        let sdks = self.source_sdks()?;
+        let mut low = 0;
+        let mut high = index.len();
+        while low < high {
+            let mid = (low + high) / 2;
+            if index.get_checked(mid)?.uuid_idx() < uuid_idx {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let mut rv = vec![];
+        while let Some(item) = index.get(low) {
+            if item.uuid_idx() != uuid_idx {
+                break;
+            }
+            rv.push(sdks.get(item.sdk_idx()).cloned().ok_or_else(|| {
+                Error::from(ErrorKind::BadMemDb)
+            })?);
+            low += 1;
+        }
+        Ok(rv)
+    }
+
+    /// Verifies the checksums recorded in the file.
+    ///
+    /// Returns `false` if the file does not contain checksums because it
+    /// was written by an older version.  Damaged files fail with
+    /// `MemDbChecksumMismatch`.
+    pub fn verify(&self) -> Result<bool> {
+        let checksums = match self.checksums()? {
+            Some(checksums) => checksums,
+            None => { return Ok(false); }
+        };
+        for idx in 0..checksums.len() {
+            let checksum = checksums.get_checked(idx)?;
+            let data = self.backing.get_data(checksum.offset(), checksum.len())?;
+            if &md5::compute(data).0 != checksum.digest() {
+                return Err(ErrorKind::MemDbChecksumMismatch.into());
+            }
+        }
+        Ok(true)
+    }
+
+    /// Finds symbols by name.
+    ///
+    /// This requires the memdb file to have been written with a symbol name
+    /// index.  If it's missing the returned iterator is empty.
+    pub fn lookup_by_name(&'a self, name: &str, mode: NameMatch)
+        -> Result<NamedSymbolIter<'a>>
+    {
+        let index = self.name_index()?.unwrap_or_else(Table::empty);
+        let mut low = 0;
+        let mut high = index.len();
+        while low < high {
+            let mid = (low + high) / 2;
+            if &*self.get_symbol_name(index.get_checked(mid)?.sym_id())? < name {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(NamedSymbolIter {
+            memdb: self,
+            index: index,
+            name: name.to_string(),
+            mode: mode,
+            pos: low,
+        })
+    }
+
     /// Returns the symbols for an Uuid
-    pub fn iter_symbols(&'a self, uuid: &'a Uuid) -> Result<SymbolIter<'a>> {
-        let index = self.get_index(uuid)?.unwrap_or(&[][..]);
+    ///
+    /// Placeholders for stripped functions are skipped.
+    pub fn iter_symbols(&'a self, uuid: &Uuid) -> Result<SymbolIter<'a>> {
+        let index = self.get_index(uuid)?.unwrap_or_else(Table::empty);
         Ok(SymbolIter {
             memdb: self,
-            uuid: uuid,
+            uuid: *uuid,
             index: index,
             pos: 0,
         })
     }
 
+    /// Returns the raw symbol index for an Uuid
+    pub fn iter_index(&'a self, uuid: &Uuid) -> Result<IndexEntryIter<'a>> {
+        let index = self.get_index(uuid)?.unwrap_or_else(Table::empty);
+        Ok(IndexEntryIter {
+            memdb: self,
+            index: index,
+            pos: 0,
+        })
+    }
+
+    /// Returns the line table of a variant.
+    ///
+    /// The rows are `(addr, filename, lineno)` sorted by address where a
+    /// line of zero marks the end of a sequence.  Variants without line
+    /// information return an empty vector.
+    pub fn get_lines(&'a self, uuid: &Uuid) -> Result<Vec<(u64, &'a str, u32)>> {
+        let mut rv = vec![];
+        if let Some(variant_idx) = self.get_variant_idx(uuid)? {
+            if let Some(lines) = self.get_line_table(variant_idx)? {
+                for idx in 0..lines.len() {
+                    let item = lines.get_checked(idx)?;
+                    rv.push((item.addr(), self.get_line_file(item.file())?, item.line()));
+                }
+            }
+        }
+        Ok(rv)
+    }
+
+    /// Returns the executable sections of a variant sorted by address.
+    ///
+    /// Files written before sections were recorded return an empty vector.
+    pub fn get_code_sections(&self, uuid: &Uuid) -> Result<Vec<CodeSectionItem>> {
+        let mut rv = vec![];
+        if let Some(variant_idx) = self.get_variant_idx(uuid)? {
+            let table = self.get_variant_table::<CodeSectionItem>(SectionKind::CodeSections,
+                                                                  variant_idx)?;
+            if let Some(sections) = table {
+                for idx in 0..sections.len() {
+                    rv.push(sections.get_checked(idx)?);
+                }
+            }
+        }
+        Ok(rv)
+    }
+
+    /// Validates the file layout.
+    ///
+    /// This checks that all tables and sections referenced from the header
+    /// are within the file so that later reads only need to check the
+    /// indexes of individual items.
+    fn validate(&self) -> Result<()> {
+        let head = &self.header;
+
+        let variants = self.variants()?;
+        for idx in 0..variants.len() {
+            let slice = variants.get_checked(idx)?;
+            if slice.is_compressed() ||
+               slice.len() % IndexItem::stored_size(head.version) != 0 {
+                return Err(ErrorKind::BadMemDb.into());
+            }
+            self.backing.get_data(slice.offset(), slice.len())?;
+        }
+
+        // the uuid index is binary searched so it has to be sorted
+        let uuids = self.uuids()?;
+        let mut last_uuid: Option<Uuid> = None;
+        for idx in 0..uuids.len() {
+            let iuuid = uuids.get_checked(idx)?;
+            if iuuid.idx() >= variants.len() ||
+               last_uuid.map_or(false, |x| x >= *iuuid.uuid()) {
+                return Err(ErrorKind::BadMemDb.into());
+            }
+            last_uuid = Some(*iuuid.uuid());
+        }
+
+        // tagged object names must be terminated within their region
+        let start = head.tagged_object_names_start as usize;
+        let end = head.tagged_object_names_end as usize;
+        if end < start {
+            return Err(ErrorKind::BadMemDb.into());
+        }
+        let names = self.backing.get_data(start, end - start)?;
+        if names.last().map_or(false, |&x| x != 0) {
+            return Err(ErrorKind::BadMemDb.into());
+        }
+
+        self.object_names()?;
+        self.symbols()?;
+        let sections = self.sections()?;
+        for idx in 0..sections.len() {
+            let section = sections.get_checked(idx)?;
+            self.backing.get_data(section.offset(), section.len())?;
+        }
+        self.object_name_index()?;
+        if let Some(names) = self.uuid_object_names()? {
+            if names.len() != uuids.len() {
+                return Err(ErrorKind::BadMemDb.into());
+            }
+        }
+        self.name_index()?;
+        self.checksums()?;
+        self.uuid_sdk_index()?;
+        self.metadata()?;
+
+        self.validate_variant_tables::<CodeSectionItem>(SectionKind::CodeSections)?;
+        if self.validate_variant_tables::<LineItem>(SectionKind::LineTables)? {
+            let files = self.get_section_data(SectionKind::LineFiles)?.ok_or_else(|| {
+                Error::from(ErrorKind::BadMemDb)
+            })?;
+            if files.last().map_or(false, |&x| x != 0) {
+                return Err(ErrorKind::BadMemDb.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a NUL terminated string from the tagged object names.
     fn get_cstr(&self, offset: usize) -> Result<&str> {
-        unsafe {
-            Ok(from_utf8(CStr::from_ptr(self.backing.buffer().as_ptr().offset(
-                offset as isize) as *const c_char).to_bytes())?)
+        let start = self.header.tagged_object_names_start as usize;
+        let end = self.header.tagged_object_names_end as usize;
+        if offset < start || offset >= end {
+            return Err(ErrorKind::BadMemDb.into());
         }
+        let bytes = self.backing.get_data(offset, end - offset)?;
+        let len = bytes.iter().position(|&x| x == 0).ok_or_else(|| {
+            Error::from(ErrorKind::BadMemDb)
+        })?;
+        Ok(from_utf8(&bytes[..len])?)
     }
 
     fn lookup_impl(&'a self, uuid: &Uuid, addr: u64) -> Result<Option<Symbol<'a>>>
     {
-        if let Some(index) = self.get_index(uuid)? {
-            if let Some(item) = binsearch_by_key(index, addr, |item| item.addr()) {
-                return Ok(self.index_item_to_symbol(item, uuid)?);
+        if let Some(variant_idx) = self.get_variant_idx(uuid)? {
+            let index = self.get_variant_index(variant_idx)?;
+            if let Some((_, item)) = index.binsearch_by_key(addr, |item| item.addr()) {
+                let mut rv = self.index_item_to_symbol(&item, uuid)?;
+                if rv.is_none() {
+                    rv = self.code_section_to_symbol(&item, uuid, variant_idx, addr)?;
+                }
+                if let Some(ref mut sym) = rv {
+                    if let Some((filename, lineno)) = self.lookup_line(variant_idx, addr)? {
+                        sym.filename = Some(filename);
+                        sym.lineno = Some(lineno);
+                    }
+                    sym.section_kind = self.lookup_code_section(variant_idx, addr)?;
+                }
+                return Ok(rv);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Finds the executable section an address is in.
+    fn find_code_section(&self, variant_idx: usize, addr: u64)
+        -> Result<Option<CodeSectionItem>>
+    {
+        let table = self.get_variant_table::<CodeSectionItem>(SectionKind::CodeSections,
+                                                              variant_idx)?;
+        if let Some(sections) = table {
+            if let Some((_, item)) = sections.binsearch_by_key(addr, |item| item.addr()) {
+                if addr - item.addr() < item.size() {
+                    return Ok(Some(item));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn lookup_code_section(&self, variant_idx: usize, addr: u64)
+        -> Result<Option<CodeSectionKind>>
+    {
+        Ok(self.find_code_section(variant_idx, addr)?.map(|item| item.kind()))
+    }
+
+    /// Reports an address after an end marker by its section.
+    ///
+    /// Sections without symbols (such as `__stubs`) only have the end
+    /// marker of the previous section before them.  Addresses in them are
+    /// reported as the start of the section so that frames there are still
+    /// labelled with the section kind.
+    fn code_section_to_symbol(&'a self, ii: &IndexItem, uuid: &Uuid, variant_idx: usize,
+                              addr: u64)
+        -> Result<Option<Symbol<'a>>>
+    {
+        let section = match self.find_code_section(variant_idx, addr)? {
+            Some(section) => section,
+            None => { return Ok(None); }
+        };
+        // an end marker within the section ends a function before the
+        // address, which is then padding rather than an unknown function.
+        if ii.addr() > section.addr() {
+            return Ok(None);
+        }
+        Ok(Some(Symbol {
+            object_uuid: uuid.clone(),
+            object_name: self.get_object_name(ii.src_id())?,
+            symbol: Cow::Borrowed(STRIPPED_SYMBOL),
+            addr: section.addr(),
+            filename: None,
+            lineno: None,
+            section_kind: Some(section.kind()),
+        }))
+    }
+
+    fn lookup_line(&'a self, variant_idx: usize, addr: u64) -> Result<Option<(&'a str, u32)>> {
+        if let Some(lines) = self.get_line_table(variant_idx)? {
+            if let Some((_, item)) = lines.binsearch_by_key(addr, |item| item.addr()) {
+                if item.line() > 0 {
+                    return Ok(Some((self.get_line_file(item.file())?, item.line())));
+                }
             }
         }
         Ok(None)
     }
 
     #[inline(always)]
-    fn uuids(&self) -> Result<&[IndexedUuid]> {
-        let head = self.backing.header()?;
-        self.backing.get_slice(head.uuids_start as usize, head.uuids_count as usize)
+    fn uuids(&self) -> Result<Table<IndexedUuid>> {
+        let head = &self.header;
+        self.backing.get_table(head.uuids_start as usize, head.uuids_count as usize,
+                               head.version)
     }
 
     #[inline(always)]
-    fn variants(&self) -> Result<&[StoredSlice]> {
-        let head = self.backing.header()?;
-        self.backing.get_slice(head.variants_start as usize, head.variants_count as usize)
+    fn variants(&self) -> Result<Table<StoredSlice>> {
+        let head = &self.header;
+        self.backing.get_table(head.variants_start as usize, head.variants_count as usize,
+                               head.version)
     }
 
     #[inline(always)]
-    fn get_index(&self, uuid: &Uuid) -> Result<Option<&[IndexItem]>> {
+    fn get_variant_idx(&self, uuid: &Uuid) -> Result<Option<usize>> {
         let uuids = self.uuids()?;
-        if let Some(iuuid) = binsearch_by_key(uuids, *uuid, |item| *item.uuid()) {
+        if let Some((_, iuuid)) = uuids.binsearch_by_key(*uuid, |item| *item.uuid()) {
             // only consider exact matches
-            if iuuid.uuid() != uuid {
-                return Ok(None);
+            if iuuid.uuid() == uuid {
+                return Ok(Some(iuuid.idx()));
+            }
+        }
+        Ok(None)
+    }
+
+    #[inline(always)]
+    fn get_index(&self, uuid: &Uuid) -> Result<Option<Table<IndexItem>>> {
+        match self.get_variant_idx(uuid)? {
+            Some(idx) => Ok(Some(self.get_variant_index(idx)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_variant_index(&self, idx: usize) -> Result<Table<IndexItem>> {
+        let variant_slice = self.variants()?.get_checked(idx)?;
+        Ok(Table::new(self.backing.get_data(variant_slice.offset(), variant_slice.len())?,
+                      self.header.version))
+    }
+
+    fn sections(&self) -> Result<Table<SectionEntry>> {
+        let head = &self.header;
+        if head.version < 3 {
+            return Ok(Table::empty());
+        }
+        self.backing.get_table(head.sections_start as usize, head.sections_count as usize,
+                               head.version)
+    }
+
+    fn get_section_data(&self, kind: SectionKind) -> Result<Option<&[u8]>> {
+        let sections = self.sections()?;
+        for idx in 0..sections.len() {
+            let section = sections.get_checked(idx)?;
+            if section.is_kind(kind) {
+                return Ok(Some(self.backing.get_data(section.offset(), section.len())?));
             }
-            let variant_slice = &self.variants()?[iuuid.idx()];
-            unsafe {
-                let data = self.backing.get_data(variant_slice.offset(),
-                                                 variant_slice.len())?;
-                let count = variant_slice.len() / mem::size_of::<IndexItem>();
-                return Ok(Some(slice::from_raw_parts(
-                    mem::transmute(data.as_ptr()),
-                    count
-                )));
+        }
+        Ok(None)
+    }
+
+    fn get_section_table<T: Stored>(&self, kind: SectionKind) -> Result<Option<Table<T>>> {
+        if let Some(data) = self.get_section_data(kind)? {
+            if data.len() % T::stored_size(self.header.version) != 0 {
+                return Err(ErrorKind::BadMemDb.into());
+            }
+            Ok(Some(Table::new(data, self.header.version)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn object_name_index(&self) -> Result<Option<Table<ObjectNameIndexItem>>> {
+        self.get_section_table(SectionKind::ObjectNames)
+    }
+
+    fn uuid_object_names(&self) -> Result<Option<Table<ObjectNameIndexItem>>> {
+        self.get_section_table(SectionKind::UuidObjectNames)
+    }
+
+    fn name_index(&self) -> Result<Option<Table<NameIndexItem>>> {
+        self.get_section_table(SectionKind::SymbolNames)
+    }
+
+    fn checksums(&self) -> Result<Option<Table<ChecksumEntry>>> {
+        self.get_section_table(SectionKind::Checksums)
+    }
+
+    fn uuid_sdk_index(&self) -> Result<Option<Table<UuidSdkItem>>> {
+        self.get_section_table(SectionKind::UuidSdks)
+    }
+
+    /// Returns the table of a variant from a section with slices per variant.
+    fn get_variant_table<T: Stored>(&self, kind: SectionKind, variant_idx: usize)
+        -> Result<Option<Table<T>>>
+    {
+        let slices = self.get_section_table::<StoredSlice>(kind)?;
+        if let Some(slice) = slices.and_then(|x| x.get(variant_idx)) {
+            if slice.len() > 0 {
+                return Ok(Some(Table::new(self.backing.get_data(slice.offset(), slice.len())?,
+                                          self.header.version)));
             }
         }
         Ok(None)
     }
 
+    fn validate_variant_tables<T: Stored>(&self, kind: SectionKind) -> Result<bool> {
+        let slices = match self.get_section_table::<StoredSlice>(kind)? {
+            Some(slices) => slices,
+            None => { return Ok(false); }
+        };
+        for idx in 0..slices.len() {
+            let slice = slices.get_checked(idx)?;
+            if slice.len() % T::stored_size(self.header.version) != 0 {
+                return Err(ErrorKind::BadMemDb.into());
+            }
+            self.backing.get_data(slice.offset(), slice.len())?;
+        }
+        Ok(true)
+    }
+
+    fn get_line_table(&self, variant_idx: usize) -> Result<Option<Table<LineItem>>> {
+        self.get_variant_table(SectionKind::LineTables, variant_idx)
+    }
+
+    /// Reads a NUL terminated file name from the line files section.
+    fn get_line_file(&self, offset: u32) -> Result<&str> {
+        let data = self.get_section_data(SectionKind::LineFiles)?.ok_or_else(|| {
+            Error::from(ErrorKind::BadMemDb)
+        })?;
+        let bytes = data.get(offset as usize..).ok_or_else(|| {
+            Error::from(ErrorKind::BadMemDb)
+        })?;
+        let len = bytes.iter().position(|&x| x == 0).ok_or_else(|| {
+            Error::from(ErrorKind::BadMemDb)
+        })?;
+        Ok(from_utf8(&bytes[..len])?)
+    }
+
     #[inline(always)]
-    fn symbols(&self) -> Result<&[StoredSlice]> {
-        let head = self.backing.header()?;
-        self.backing.get_slice(head.symbols_start as usize, head.symbols_count as usize)
+    fn symbols(&self) -> Result<Table<StoredSlice>> {
+        let head = &self.header;
+        self.backing.get_table(head.symbols_start as usize, head.symbols_count as usize,
+                               head.version)
     }
 
     #[inline(always)]
-    fn object_names(&self) -> Result<&[StoredSlice]> {
-        let head = self.backing.header()?;
-        self.backing.get_slice(head.object_names_start as usize,
-                               head.object_names_count as usize)
+    fn object_names(&self) -> Result<Table<StoredSlice>> {
+        let head = &self.header;
+        self.backing.get_table(head.object_names_start as usize,
+                               head.object_names_count as usize, head.version)
+    }
+
+    fn get_string_block(&self, offset: usize) -> Result<Arc<Vec<u8>>> {
+        {
+            let mut cache = self.block_cache.lock().unwrap();
+            if let Some(pos) = cache.iter().position(|&(off, _)| off == offset) {
+                let item = cache.remove(pos);
+                let rv = item.1.clone();
+                cache.insert(0, item);
+                return Ok(rv);
+            }
+        }
+
+        let len = LittleEndian::read_u32(self.backing.get_data(offset, 4)?) as usize;
+        let compressed = self.backing.get_data(offset.wrapping_add(4), len)?;
+        let mut block = vec![];
+        BrotliDecoder::new(compressed).read_to_end(&mut block)
+            .map_err(|_| Error::from(ErrorKind::BadMemDb))?;
+        let rv = Arc::new(block);
+
+        let mut cache = self.block_cache.lock().unwrap();
+        cache.insert(0, (offset, rv.clone()));
+        cache.truncate(BLOCK_CACHE_SIZE);
+        Ok(rv)
     }
 
     #[inline(always)]
     fn get_string(&'a self, slice: &StoredSlice) -> Result<Cow<'a, str>> {
-        let bytes = self.backing.get_data(slice.offset(), slice.len())?;
         if slice.is_compressed() {
-            panic!("We do not support compression");
+            let block = self.get_string_block(slice.offset())?;
+            if slice.len() > block.len() {
+                return Err(ErrorKind::BadMemDb.into());
+            }
+            let bytes = &block[slice.len()..];
+            let end = bytes.iter().position(|&x| x == 0).ok_or_else(|| {
+                Error::from(ErrorKind::BadMemDb)
+            })?;
+            Ok(Cow::Owned(from_utf8(&bytes[..end])?.to_string()))
         } else {
+            let bytes = self.backing.get_data(slice.offset(), slice.len())?;
             Ok(Cow::Borrowed(from_utf8(bytes)?))
         }
     }
 
-    fn get_object_name(&'a self, src_id: u16) -> Result<Cow<'a, str>> {
-        self.get_string(&self.object_names()?[src_id as usize])
+    fn get_object_name(&'a self, src_id: u32) -> Result<Cow<'a, str>> {
+        self.get_string(&self.object_names()?.get_checked(src_id as usize)?)
+    }
+
+    fn get_symbol_name(&'a self, sym_id: u32) -> Result<Cow<'a, str>> {
+        self.get_string(&self.symbols()?.get_checked(sym_id as usize)?)
     }
 
     fn get_symbol(&'a self, sym_id: Option<u32>) -> Result<Option<Cow<'a, str>>> {
         if let Some(sym_id) = sym_id {
-            Ok(Some(self.get_string(&self.symbols()?[sym_id as usize])?))
+            Ok(Some(self.get_symbol_name(sym_id)?))
         } else {
             Ok(None)
         }
@@ -343,9 +1144,43 @@ impl<'a> MemDb<'a> {
                 object_name: self.get_object_name(ii.src_id())?,
                 symbol: symbol,
                 addr: ii.addr(),
+                filename: None,
+                lineno: None,
+                section_kind: None,
             }))
         } else {
             Ok(None)
         }
     }
+
+    fn index_item_to_entry(&'a self, ii: &IndexItem) -> Result<IndexEntry<'a>> {
+        Ok(IndexEntry {
+            object_name: self.get_object_name(ii.src_id())?,
+            symbol: self.get_symbol(ii.sym_id())?,
+            addr: ii.addr(),
+        })
+    }
+
+    fn name_index_item_to_symbol(&'a self, item: &NameIndexItem, symbol: Cow<'a, str>)
+        -> Result<NamedSymbol<'a>>
+    {
+        let iuuid = self.uuids()?.get_checked(item.uuid_idx())?;
+        let index = self.get_variant_index(iuuid.idx())?;
+        let ii = index.get_checked(item.item_idx())?;
+        let next_addr = (item.item_idx() + 1..index.len())
+            .filter_map(|idx| index.get(idx))
+            .map(|x| x.addr())
+            .find(|&addr| addr > ii.addr());
+        let mut parts = self.get_cstr(item.tagged_object_name())?.rsplitn(2, ':');
+        let arch = parts.next().unwrap_or("");
+        let object_name = parts.next().unwrap_or("");
+        Ok(NamedSymbol {
+            object_uuid: *iuuid.uuid(),
+            object_name: object_name,
+            arch: arch,
+            symbol: symbol,
+            addr: ii.addr(),
+            size: next_addr.map(|addr| addr - ii.addr()),
+        })
+    }
 }
@@ -0,0 +1,496 @@
+//! Helpers for writing memdb files with bounded memory usage
+//!
+//! In low memory mode the builder does not keep symbols, variant indexes,
+//! line tables and code sections in memory.  They are written to temporary
+//! files while objects are processed and merged when the memdb is flushed:
+//! the symbols are sorted externally which assigns the symbol IDs in name
+//! order, the IDs are then patched into the spilled variant indexes.  The
+//! line tables and code sections are read back one variant at a time.
+use std::io::{self, Read, Write, Seek, SeekFrom, BufReader, BufWriter};
+use std::fs::File;
+use std::mem;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use tempfile::tempfile;
+use memmap::{Mmap, Protection};
+use brotli2::write::BrotliEncoder;
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::types::{IndexItem, StoredSlice, Stored, MEMDB_VERSION};
+use super::super::{Result, ErrorKind};
+use super::super::dsym::STRIPPED_SYMBOL;
+
+
+/// Roughly the amount of symbol data that is sorted in memory at once.
+const SORT_RUN_SIZE: usize = 64 * 1024 * 1024;
+
+/// Strings are compressed in blocks of roughly this size.
+pub const STRING_BLOCK_SIZE: usize = 64 * 1024;
+
+/// An occurrence of a symbol in the index of a variant
+struct SymbolRef {
+    symbol: String,
+    variant_idx: u32,
+    item_idx: u32,
+}
+
+/// A symbol read from one of the sorted runs
+struct RunItem {
+    symbol_ref: SymbolRef,
+    run_idx: usize,
+}
+
+/// Sorts symbol occurrences by writing sorted runs to temporary files
+struct SymbolSorter {
+    buffer: Vec<SymbolRef>,
+    buffer_size: usize,
+    runs: Vec<File>,
+}
+
+/// Iterates over the merged runs of a `SymbolSorter`
+struct SortedSymbols {
+    readers: Vec<BufReader<File>>,
+    heap: BinaryHeap<RunItem>,
+}
+
+/// An item of the spilled symbol name index
+pub struct NameRef {
+    pub sym_id: u32,
+    pub variant_idx: u32,
+    pub item_idx: u32,
+}
+
+/// The result of merging spilled data
+pub struct SpillResult {
+    /// The variant indexes with patched symbol IDs
+    pub variants: File,
+    /// The offsets and lengths of the variant indexes in `variants`
+    pub variant_slices: Vec<(usize, usize)>,
+    /// The compressed string blocks of the symbols
+    pub strings: File,
+    /// The slices of the symbols relative to the start of `strings`
+    pub string_slices: File,
+    /// The `NameRef`s of the symbol name index sorted by symbol name
+    pub name_index: File,
+    /// The number of unique symbols
+    pub symbol_count: usize,
+}
+
+/// Holds the variant indexes and symbols of a memdb that is being written
+pub struct Spill {
+    variants: BufWriter<File>,
+    variants_len: usize,
+    variant_slices: Vec<(usize, usize)>,
+    symbols: SymbolSorter,
+}
+
+/// Tables with an optional entry per variant such as the line tables
+///
+/// When spilled only the number of items of each table is kept in memory.
+pub struct VariantTables<T> {
+    tables: Vec<Option<Vec<T>>>,
+    spill: Option<BufWriter<File>>,
+    counts: Vec<Option<usize>>,
+}
+
+/// Brotli compresses a block of NUL terminated strings.
+pub fn compress_string_block(block: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = BrotliEncoder::new(vec![], 9);
+    encoder.write_all(block)?;
+    Ok(encoder.finish()?)
+}
+
+/// Calls a function for each item stored in a file.
+pub fn for_each_stored<T: Stored, F: FnMut(T) -> Result<()>>(f: &mut File, mut func: F)
+    -> Result<()>
+{
+    let size = T::stored_size(MEMDB_VERSION);
+    let mut reader = BufReader::new(f);
+    let mut buf = vec![0; size];
+    loop {
+        match reader.read_exact(&mut buf[..]) {
+            Ok(()) => { func(T::decode(&buf[..], MEMDB_VERSION))?; }
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(());
+            }
+            Err(err) => { return Err(err.into()); }
+        }
+    }
+}
+
+impl Stored for NameRef {
+
+    fn stored_size(_version: u32) -> usize {
+        12
+    }
+
+    fn decode(data: &[u8], _version: u32) -> NameRef {
+        NameRef {
+            sym_id: LittleEndian::read_u32(&data[0..4]),
+            variant_idx: LittleEndian::read_u32(&data[4..8]),
+            item_idx: LittleEndian::read_u32(&data[8..12]),
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32::<LittleEndian>(self.sym_id)?;
+        w.write_u32::<LittleEndian>(self.variant_idx)?;
+        w.write_u32::<LittleEndian>(self.item_idx)
+    }
+}
+
+impl SymbolRef {
+    fn key(&self) -> (&str, u32, u32) {
+        (&self.symbol, self.variant_idx, self.item_idx)
+    }
+}
+
+impl PartialEq for RunItem {
+    fn eq(&self, other: &RunItem) -> bool {
+        self.symbol_ref.key() == other.symbol_ref.key()
+    }
+}
+
+impl Eq for RunItem {}
+
+impl PartialOrd for RunItem {
+    fn partial_cmp(&self, other: &RunItem) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RunItem {
+    fn cmp(&self, other: &RunItem) -> Ordering {
+        // reversed so that the binary heap yields the smallest item first
+        other.symbol_ref.key().cmp(&self.symbol_ref.key())
+    }
+}
+
+fn write_symbol_ref<W: Write>(w: &mut W, symbol_ref: &SymbolRef) -> io::Result<()> {
+    w.write_u32::<LittleEndian>(symbol_ref.symbol.len() as u32)?;
+    w.write_all(symbol_ref.symbol.as_bytes())?;
+    w.write_u32::<LittleEndian>(symbol_ref.variant_idx)?;
+    w.write_u32::<LittleEndian>(symbol_ref.item_idx)
+}
+
+fn read_symbol_ref<R: Read>(r: &mut R) -> Result<Option<SymbolRef>> {
+    let len = match r.read_u32::<LittleEndian>() {
+        Ok(len) => len as usize,
+        Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+            return Ok(None);
+        }
+        Err(err) => { return Err(err.into()); }
+    };
+    let mut symbol = vec![0; len];
+    r.read_exact(&mut symbol[..])?;
+    Ok(Some(SymbolRef {
+        symbol: String::from_utf8(symbol)?,
+        variant_idx: r.read_u32::<LittleEndian>()?,
+        item_idx: r.read_u32::<LittleEndian>()?,
+    }))
+}
+
+impl SymbolSorter {
+
+    fn new() -> SymbolSorter {
+        SymbolSorter {
+            buffer: vec![],
+            buffer_size: 0,
+            runs: vec![],
+        }
+    }
+
+    fn push(&mut self, symbol: String, variant_idx: u32, item_idx: u32) -> Result<()> {
+        self.buffer_size += symbol.len() + mem::size_of::<SymbolRef>();
+        self.buffer.push(SymbolRef {
+            symbol: symbol,
+            variant_idx: variant_idx,
+            item_idx: item_idx,
+        });
+        if self.buffer_size >= SORT_RUN_SIZE {
+            self.write_run()?;
+        }
+        Ok(())
+    }
+
+    fn write_run(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.buffer.sort_by(|a, b| a.key().cmp(&b.key()));
+        let mut f = tempfile()?;
+        {
+            let mut w = BufWriter::new(&mut f);
+            for symbol_ref in self.buffer.iter() {
+                write_symbol_ref(&mut w, symbol_ref)?;
+            }
+            w.flush()?;
+        }
+        f.seek(SeekFrom::Start(0))?;
+        self.runs.push(f);
+        self.buffer.clear();
+        self.buffer_size = 0;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<SortedSymbols> {
+        self.write_run()?;
+        let mut readers: Vec<_> = self.runs.into_iter().map(BufReader::new).collect();
+        let mut heap = BinaryHeap::new();
+        for (run_idx, reader) in readers.iter_mut().enumerate() {
+            if let Some(symbol_ref) = read_symbol_ref(reader)? {
+                heap.push(RunItem { symbol_ref: symbol_ref, run_idx: run_idx });
+            }
+        }
+        Ok(SortedSymbols {
+            readers: readers,
+            heap: heap,
+        })
+    }
+}
+
+impl Iterator for SortedSymbols {
+    type Item = Result<SymbolRef>;
+
+    fn next(&mut self) -> Option<Result<SymbolRef>> {
+        let item = try_opt!(self.heap.pop());
+        match read_symbol_ref(&mut self.readers[item.run_idx]) {
+            Ok(Some(symbol_ref)) => {
+                self.heap.push(RunItem { symbol_ref: symbol_ref, run_idx: item.run_idx });
+            }
+            Ok(None) => {}
+            Err(err) => { return Some(Err(err)); }
+        }
+        Some(Ok(item.symbol_ref))
+    }
+}
+
+/// Writes NUL terminated strings into compressed blocks
+struct StringTableWriter {
+    strings: BufWriter<File>,
+    slices: BufWriter<File>,
+    offset: usize,
+    block: Vec<u8>,
+    inner_offsets: Vec<usize>,
+}
+
+impl StringTableWriter {
+
+    fn new() -> Result<StringTableWriter> {
+        Ok(StringTableWriter {
+            strings: BufWriter::new(tempfile()?),
+            slices: BufWriter::new(tempfile()?),
+            offset: 0,
+            block: vec![],
+            inner_offsets: vec![],
+        })
+    }
+
+    fn add(&mut self, s: &str) -> Result<()> {
+        self.inner_offsets.push(self.block.len());
+        self.block.extend_from_slice(s.as_bytes());
+        self.block.push(0);
+        if self.block.len() >= STRING_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> Result<()> {
+        if self.inner_offsets.is_empty() {
+            return Ok(());
+        }
+        let compressed = compress_string_block(&self.block[..])?;
+        self.strings.write_u32::<LittleEndian>(compressed.len() as u32)?;
+        self.strings.write_all(&compressed[..])?;
+        for &inner_offset in self.inner_offsets.iter() {
+            StoredSlice::new(self.offset, inner_offset, true).encode(&mut self.slices)?;
+        }
+        self.offset += compressed.len() + 4;
+        self.block.clear();
+        self.inner_offsets.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(File, File)> {
+        self.write_block()?;
+        Ok((finish_buf_writer(self.strings)?, finish_buf_writer(self.slices)?))
+    }
+}
+
+/// Flushes a buffered temporary file and rewinds it for reading.
+fn finish_buf_writer(w: BufWriter<File>) -> Result<File> {
+    let mut f = w.into_inner().map_err(|err| {
+        io::Error::new(err.error().kind(), "could not flush temporary file")
+    })?;
+    f.seek(SeekFrom::Start(0))?;
+    Ok(f)
+}
+
+impl<T: Stored> VariantTables<T> {
+
+    /// Creates tables that are kept in memory
+    pub fn new() -> VariantTables<T> {
+        VariantTables {
+            tables: vec![],
+            spill: None,
+            counts: vec![],
+        }
+    }
+
+    /// Creates tables that are backed by a temporary file
+    pub fn spilled() -> Result<VariantTables<T>> {
+        Ok(VariantTables {
+            tables: vec![],
+            spill: Some(BufWriter::new(tempfile()?)),
+            counts: vec![],
+        })
+    }
+
+    /// Adds the table of the next variant.
+    pub fn push(&mut self, table: Option<Vec<T>>) -> Result<()> {
+        self.counts.push(table.as_ref().map(|x| x.len()));
+        if let Some(ref mut w) = self.spill {
+            for item in table.iter().flat_map(|x| x.iter()) {
+                item.encode(w)?;
+            }
+        } else {
+            self.tables.push(table);
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if any variant has a table.
+    pub fn has_tables(&self) -> bool {
+        self.counts.iter().any(|x| x.is_some())
+    }
+
+    /// Calls a function with the table of each variant in the order they
+    /// were added.  Variants without a table get an empty one.
+    pub fn for_each<F: FnMut(&[T]) -> Result<()>>(self, mut func: F) -> Result<()> {
+        let w = match self.spill {
+            Some(w) => w,
+            None => {
+                for table in self.tables.iter() {
+                    func(table.as_ref().map(|x| &x[..]).unwrap_or(&[]))?;
+                }
+                return Ok(());
+            }
+        };
+        let mut reader = BufReader::new(finish_buf_writer(w)?);
+        let mut buf = vec![0; T::stored_size(MEMDB_VERSION)];
+        let mut table = vec![];
+        for count in self.counts.iter() {
+            table.clear();
+            for _ in 0..count.unwrap_or(0) {
+                reader.read_exact(&mut buf[..])?;
+                table.push(T::decode(&buf[..], MEMDB_VERSION));
+            }
+            func(&table[..])?;
+        }
+        Ok(())
+    }
+}
+
+impl Spill {
+
+    /// Creates a new spill backed by temporary files
+    pub fn new() -> Result<Spill> {
+        Ok(Spill {
+            variants: BufWriter::new(tempfile()?),
+            variants_len: 0,
+            variant_slices: vec![],
+            symbols: SymbolSorter::new(),
+        })
+    }
+
+    /// Adds the index of a variant as `(addr, src_id, symbol)` entries
+    pub fn push_variant(&mut self, entries: Vec<(u64, u32, Option<String>)>) -> Result<()> {
+        let variant_idx = self.variant_slices.len() as u32;
+        let offset = self.variants_len;
+        for (item_idx, (addr, src_id, sym)) in entries.into_iter().enumerate() {
+            // the symbol id is patched in once all symbols are known
+            IndexItem::new(addr, src_id, sym.as_ref().map(|_| 0))
+                .encode(&mut self.variants)?;
+            self.variants_len += IndexItem::stored_size(MEMDB_VERSION);
+            if let Some(sym) = sym {
+                self.symbols.push(sym, variant_idx, item_idx as u32)?;
+            }
+        }
+        self.variant_slices.push((offset, self.variants_len - offset));
+        Ok(())
+    }
+
+    /// Assigns the symbol IDs and writes out the string table.
+    ///
+    /// The object name IDs of the spilled items are replaced with the ones
+    /// from `src_ids` which is indexed by the IDs they were pushed with.
+    pub fn finish(self, src_ids: &[u32]) -> Result<SpillResult> {
+        let variants = finish_buf_writer(self.variants)?;
+        let mut mmap = if self.variants_len > 0 {
+            Some(Mmap::open(&variants, Protection::ReadWrite)?)
+        } else {
+            None
+        };
+
+        // the object name ID is stored in front of the symbol ID
+        let item_size = IndexItem::stored_size(MEMDB_VERSION);
+        if let Some(ref mut mmap) = mmap {
+            let data = unsafe { mmap.as_mut_slice() };
+            let mut pos = item_size - 8;
+            while pos < self.variants_len {
+                let src_id = LittleEndian::read_u32(&data[pos..pos + 4]);
+                LittleEndian::write_u32(&mut data[pos..pos + 4], src_ids[src_id as usize]);
+                pos += item_size;
+            }
+        }
+        let mut strings = StringTableWriter::new()?;
+        let mut name_index = BufWriter::new(tempfile()?);
+        let mut symbol_count = 0;
+        let mut last_symbol: Option<String> = None;
+
+        // the symbol ID is stored in the last four bytes of an index item
+        let sym_id_offset = IndexItem::stored_size(MEMDB_VERSION) - 4;
+
+        for symbol_ref_rv in self.symbols.finish()? {
+            let symbol_ref = symbol_ref_rv?;
+            if last_symbol.as_ref() != Some(&symbol_ref.symbol) {
+                // the largest id is reserved for the end marker
+                if symbol_count >= !0u32 as usize {
+                    return Err(ErrorKind::MemDbLimitExceeded("symbols").into());
+                }
+                strings.add(&symbol_ref.symbol)?;
+                symbol_count += 1;
+                last_symbol = Some(symbol_ref.symbol.clone());
+            }
+            let sym_id = (symbol_count - 1) as u32;
+            let (offset, _) = self.variant_slices[symbol_ref.variant_idx as usize];
+            let pos = offset + symbol_ref.item_idx as usize *
+                IndexItem::stored_size(MEMDB_VERSION) + sym_id_offset;
+            let data = unsafe { mmap.as_mut().unwrap().as_mut_slice() };
+            LittleEndian::write_u32(&mut data[pos..pos + 4], sym_id);
+            if symbol_ref.symbol == STRIPPED_SYMBOL {
+                continue;
+            }
+            NameRef {
+                sym_id: sym_id,
+                variant_idx: symbol_ref.variant_idx,
+                item_idx: symbol_ref.item_idx,
+            }.encode(&mut name_index)?;
+        }
+
+        if let Some(ref mut mmap) = mmap {
+            mmap.flush()?;
+        }
+        let (strings, string_slices) = strings.finish()?;
+        Ok(SpillResult {
+            variants: variants,
+            variant_slices: self.variant_slices,
+            strings: strings,
+            string_slices: string_slices,
+            name_index: finish_buf_writer(name_index)?,
+            symbol_count: symbol_count,
+        })
+    }
+}
@@ -23,7 +23,13 @@ use super::super::sdk::SdkInfo;
 use super::super::s3::S3;
 use super::super::utils::{copy_with_progress, HumanDuration,
                           IgnorePatterns, Rev};
-use super::super::{Result, ResultExt, ErrorKind};
+use super::super::{Result, ResultExt, Error, ErrorKind};
+
+/// How often a download is attempted if the file fails verification.
+const DOWNLOAD_ATTEMPTS: usize = 2;
+
+/// How many damaged files are kept around in the quarantine folder.
+const QUARANTINE_LIMIT: usize = 4;
 
 /// Helper for synching
 pub struct SyncOptions {
@@ -221,7 +227,89 @@ impl MemDbStash {
         Ok(SdkSyncState { sdks: sdks, revision: None })
     }
 
+    fn get_quarantine_dir(&self) -> PathBuf {
+        self.path.join("quarantine")
+    }
+
+    /// Moves a damaged memdb file out of the way so that it's not served.
+    fn quarantine_file(&self, path: &Path) -> Result<()> {
+        if !path.is_file() {
+            return Ok(());
+        }
+        let quarantine_dir = self.get_quarantine_dir();
+        fs::create_dir_all(&quarantine_dir)?;
+        let filename = format!("{}.{}", path.file_name().unwrap().to_string_lossy(),
+                               UTC::now().timestamp());
+        fs::rename(path, quarantine_dir.join(filename))?;
+        self.prune_quarantine()
+    }
+
+    /// Deletes all but the most recently quarantined files.
+    fn prune_quarantine(&self) -> Result<()> {
+        let mut files = vec![];
+        for entry_rv in fs::read_dir(self.get_quarantine_dir())? {
+            let entry = entry_rv?;
+            let filename = entry.file_name().to_string_lossy().into_owned();
+            // quarantined files end in the timestamp they were moved at
+            let timestamp = filename.rsplit('.').next()
+                .and_then(|x| x.parse::<i64>().ok()).unwrap_or(0);
+            files.push((timestamp, filename, entry.path()));
+        }
+        files.sort();
+        let excess = files.len().saturating_sub(QUARANTINE_LIMIT);
+        for &(_, _, ref path) in files[..excess].iter() {
+            debug!("removing quarantined file {}", path.display());
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Quarantines the file of a local SDK and forgets about it so that
+    /// the next sync fetches it again.
+    fn quarantine_sdk(&self, info: &SdkInfo) -> Result<()> {
+        warn!("quarantining damaged memdb {}", info);
+        self.memdbs.write().unwrap().remove(info);
+        let mut local_state = (*self.get_local_state()?).clone();
+        local_state.remove_sdk(info);
+        self.save_local_state(&local_state)?;
+        self.quarantine_file(&self.path.join(info.memdb_filename()))
+    }
+
+    /// Downloads an SDK and moves it into place once it verified.
+    ///
+    /// The previous file stays untouched until then so that memdbs which
+    /// are still mapped in are never overwritten.
     fn update_sdk(&self, sdk: &RemoteSdk, options: &SyncOptions) -> Result<()> {
+        let path = self.path.join(sdk.info().memdb_filename());
+        let tmp_path = self.path.join(format!("{}.download", sdk.info().memdb_filename()));
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let rv = self.download_sdk(sdk, &tmp_path, options)
+                .and_then(|_| verify_memdb_file(&tmp_path));
+            match rv {
+                Ok(_) => {
+                    fs::rename(&tmp_path, &path)?;
+                    return Ok(());
+                }
+                Err(err) => {
+                    if !is_damaged_memdb(&err) {
+                        fs::remove_file(&tmp_path).ok();
+                        return Err(err);
+                    }
+                    warn!("downloaded memdb {} failed verification: {}", sdk.info(), err);
+                    self.quarantine_file(&tmp_path)?;
+                    if attempt >= DOWNLOAD_ATTEMPTS {
+                        return Err(err).chain_err(|| "Could not fetch an intact memdb");
+                    }
+                }
+            }
+        }
+    }
+
+    fn download_sdk(&self, sdk: &RemoteSdk, path: &Path, options: &SyncOptions)
+        -> Result<()>
+    {
         // XXX: the progress bar here can stall out because we currently
         // need to buffer the download into memory in the s3 code :(
         let progress = if options.user_facing {
@@ -235,7 +323,7 @@ impl MemDbStash {
         let started = UTC::now();
         println!("{} {}", style("Updating").green(), sdk.info());
         let mut src = self.s3.download_sdk(sdk)?;
-        let dst = fs::File::create(self.path.join(sdk.info().memdb_filename()))?;
+        let dst = fs::File::create(path)?;
         let mut dst = XzDecoder::new(dst);
         copy_with_progress(&progress, &mut src, &mut dst)?;
         progress.finish_and_clear();
@@ -345,19 +433,30 @@ impl MemDbStash {
             if !self.sdk_is_ignored(sdk_info) {
                 let mut changed_something = false;
                 let sdk = remote_state.get_sdk(sdk_info).unwrap();
-                if let Some(local_sdk) = local_state.get_sdk(sdk_info) {
-                    if local_sdk != sdk {
-                        self.update_sdk(&sdk, &options)?;
-                        self.memdbs.write().unwrap().remove(sdk_info);
-                        changed_something = true;
-                    } else if options.user_facing {
-                        println!("{} {}", style("Unchanged").cyan(), sdk_info);
-                    } else {
-                        debug!("unchanged sdk {}", sdk_info);
+                let needs_update = match local_state.get_sdk(sdk_info) {
+                    Some(local_sdk) => local_sdk != sdk,
+                    None => true,
+                };
+                if needs_update {
+                    // a failed SDK keeps its previous file (if any) and is
+                    // tried again on the next sync.
+                    match self.update_sdk(&sdk, &options) {
+                        Ok(()) => {
+                            self.memdbs.write().unwrap().remove(sdk_info);
+                            changed_something = true;
+                        }
+                        Err(err) => {
+                            if options.user_facing {
+                                println!("{} {}: {}", style("Failed").red(), sdk_info, err);
+                            } else {
+                                warn!("could not update sdk {}: {}", sdk_info, err);
+                            }
+                        }
                     }
+                } else if options.user_facing {
+                    println!("{} {}", style("Unchanged").cyan(), sdk_info);
                 } else {
-                    self.update_sdk(&sdk, &options)?;
-                    changed_something = true;
+                    debug!("unchanged sdk {}", sdk_info);
                 }
                 if changed_something {
                     changed = true;
@@ -398,6 +497,26 @@ impl MemDbStash {
         Ok(())
     }
 
+    /// Verifies the checksums of a local SDK.
+    ///
+    /// Returns `false` if the file does not contain checksums.  Damaged
+    /// files are quarantined and will be fetched again on the next sync
+    /// while other errors (eg: IO errors) leave the file alone.
+    pub fn verify_sdk(&self, info: &SdkInfo) -> Result<bool> {
+        if self.get_local_state()?.get_sdk(info).is_none() {
+            return Err(ErrorKind::UnknownSdk.into());
+        }
+        match verify_memdb_file(&self.path.join(info.memdb_filename())) {
+            Ok(rv) => Ok(rv),
+            Err(err) => {
+                if is_damaged_memdb(&err) {
+                    self.quarantine_sdk(info)?;
+                }
+                Err(err)
+            }
+        }
+    }
+
     /// Looks up an memdb by an SDK info if it's available.
     ///
     /// This returns a memdb wrapped in an arc as internally the system
@@ -417,7 +536,16 @@ impl MemDbStash {
         // we might start to consider things that are not available yet or
         // not available any longer.
         if local_state.get_sdk(&info).is_some() {
-            let memdb = MemDb::from_path(self.path.join(&info.memdb_filename()))?;
+            let memdb = match MemDb::from_path(self.path.join(&info.memdb_filename())) {
+                Ok(memdb) => memdb,
+                Err(err) => {
+                    // damaged files are not served but fetched again
+                    if is_damaged_memdb(&err) {
+                        self.quarantine_sdk(info)?;
+                    }
+                    return Err(err);
+                }
+            };
             self.memdbs.write().unwrap().insert(info.clone(), Arc::new(memdb));
             if let Some(arc) = self.memdbs.read().unwrap().get(info) {
                 return Ok(arc.clone());
@@ -454,3 +582,15 @@ impl MemDbStash {
         Ok(rv.into_iter().take(10).map(|(_, info)| info).collect())
     }
 }
+
+fn verify_memdb_file(path: &Path) -> Result<bool> {
+    MemDb::from_path(path)?.verify()
+}
+
+/// Checks if an error means that the file itself is broken.
+fn is_damaged_memdb(err: &Error) -> bool {
+    match *err.kind() {
+        ErrorKind::BadMemDb | ErrorKind::MemDbChecksumMismatch => true,
+        _ => false,
+    }
+}
@@ -1,13 +1,26 @@
 //! Exposes types related to memdb files
+use std::io::{self, Write};
 use std::str::from_utf8;
 
 use uuid::Uuid;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
 
+use super::super::{Result, ErrorKind};
 use super::super::sdk::SdkInfo;
 
 
+/// The version of memdb files written by this library
+///
+/// Version 3 added compressed symbol names and the section table, version
+/// 4 widened the index.  Readers of version 2 files cannot decompress
+/// symbol names so compressed files are never written as version 2.
+pub const MEMDB_VERSION: u32 = 4;
+
 /// The stored memdb file header
-#[repr(C, packed)]
+///
+/// Version 2 files end the header after `symbols_count`, the section
+/// table only exists from version 3 onwards.  Files before version 4 use
+/// narrower fields in the UUID, symbol and name indexes.
 #[derive(Default, Copy, Clone)]
 pub struct MemDbHeader {
     pub version: u32,
@@ -22,10 +35,87 @@ pub struct MemDbHeader {
     pub object_names_count: u32,
     pub symbols_start: u32,
     pub symbols_count: u32,
+    pub sections_start: u32,
+    pub sections_count: u32,
+}
+
+/// The kinds of optional sections in a memdb file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SectionKind {
+    /// All symbols of all variants sorted by symbol name
+    SymbolNames = 1,
+    /// The `name:arch` object names sorted by name
+    ObjectNames = 2,
+    /// MD5 checksums of the regions of the file
+    Checksums = 3,
+    /// The NUL terminated IDs of the SDKs a merged file was built from
+    SourceSdks = 4,
+    /// Maps variants to the SDKs they came from sorted by variant
+    UuidSdks = 5,
+    /// NUL terminated key and value pairs describing how the file was built
+    Metadata = 6,
+    /// The NUL terminated source file names of the line tables
+    LineFiles = 7,
+    /// A slice of `LineItem`s for every variant in variant order
+    LineTables = 8,
+    /// A slice of `CodeSectionItem`s for every variant in variant order
+    CodeSections = 9,
+    /// The first `name:arch` object name of every variant in UUID order
+    UuidObjectNames = 10,
+}
+
+/// The kinds of executable sections symbols are found in
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CodeSectionKind {
+    Text = 0,
+    Stubs = 1,
+    StubHelper = 2,
+    AuthStubs = 3,
+    ObjcStubs = 4,
+    Other = 5,
+}
+
+/// Points to an optional section in the memdb file
+pub struct SectionEntry {
+    kind: u32,
+    offset: u32,
+    len: u32,
+}
+
+/// The checksum of a region of the memdb file
+///
+/// The header and the section table are not covered by checksums, they
+/// are validated when the file is loaded.
+pub struct ChecksumEntry {
+    offset: u32,
+    len: u32,
+    digest: [u8; 16],
+}
+
+/// Records that a variant was found in a source SDK
+pub struct UuidSdkItem {
+    uuid_idx: u32,
+    sdk_idx: u32,
+}
+
+/// A row of a line table
+///
+/// The file is the offset of the file name in the line files section.  A
+/// line of zero marks the end of a sequence.
+pub struct LineItem {
+    addr: u64,
+    file: u32,
+    line: u32,
+}
+
+/// An executable section of a variant
+pub struct CodeSectionItem {
+    addr: u64,
+    size: u32,
+    kind: u32,
 }
 
 /// Packed SDK information
-#[repr(C, packed)]
 #[derive(Default, Copy, Clone)]
 pub struct PackedSdkInfo {
     pub name: [u8; 8],
@@ -36,41 +126,92 @@ pub struct PackedSdkInfo {
 }
 
 /// A stored slice that points to a memory region in the memdb file
-#[repr(C, packed)]
+///
+/// For compressed strings the offset points to the start of a compressed
+/// string block and the length is the offset of the NUL terminated string
+/// within the decompressed block.
 pub struct StoredSlice {
     pub offset: u32,
     pub len: u32,
 }
 
+/// Implemented for items that are stored in a memdb file.
+///
+/// All fields are stored as little endian.  The layout of some items
+/// depends on the version of the file.
+pub trait Stored: Sized {
+    /// The size of a stored item in a file of the given version
+    fn stored_size(version: u32) -> usize;
+
+    /// Decodes an item stored in a file of the given version
+    ///
+    /// The data passed must be exactly `stored_size` bytes long.
+    fn decode(data: &[u8], version: u32) -> Self;
+
+    /// Encodes the item in the layout of the current version
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()>;
+}
+
 /// For the UUID index this points to a variant by index
-#[repr(C, packed)]
 pub struct IndexedUuid {
     pub uuid: Uuid,
-    pub idx: u16,
+    pub idx: u32,
 }
 
 /// A symbol in the index
-#[repr(C, packed)]
+///
+/// The items of a variant are sorted by address and a symbol ends where
+/// the next item starts.  Items without a symbol mark the end of a
+/// function of known size, of a code section or of the image.
 #[derive(Debug)]
 pub struct IndexItem {
-    addr_low: u32,
-    addr_high: u16,
-    src_id: u16,
+    addr: u64,
+    src_id: u32,
     sym_id: u32,
 }
 
+/// An item in the symbol name index
+pub struct NameIndexItem {
+    sym_id: u32,
+    uuid_idx: u32,
+    tagged_object_name: u32,
+    item_idx: u32,
+}
+
+/// An item in the object name index
+pub struct ObjectNameIndexItem {
+    tagged_object_name: u32,
+    uuid_idx: u32,
+}
+
 fn copy_str_to_slice(slice: &mut [u8], s: &str) {
     let bytes = s.as_bytes();
     (&mut slice[..bytes.len()]).copy_from_slice(bytes);
 }
 
-fn str_from_zero_slice(slice: &[u8]) -> &str {
-    from_utf8(slice).unwrap().trim_right_matches('\x00')
+fn check_str_fits(slice: &[u8], s: &str, what: &'static str) -> Result<()> {
+    if s.len() > slice.len() {
+        Err(ErrorKind::SdkInfoTooLong(what, slice.len()).into())
+    } else {
+        Ok(())
+    }
+}
+
+fn str_from_zero_slice(slice: &[u8]) -> Option<&str> {
+    from_utf8(slice).ok().map(|x| x.trim_right_matches('\x00'))
 }
 
 impl PackedSdkInfo {
 
-    pub fn set_from_sdk_info(&mut self, info: &SdkInfo) {
+    /// Checks if the name and build of an SDK info fit into the header.
+    pub fn check_sdk_info(info: &SdkInfo) -> Result<()> {
+        let packed = PackedSdkInfo::default();
+        check_str_fits(&packed.name[..], info.name(), "name")?;
+        check_str_fits(&packed.build[..], info.build().unwrap_or(""), "build")
+    }
+
+    pub fn set_from_sdk_info(&mut self, info: &SdkInfo) -> Result<()> {
+        PackedSdkInfo::check_sdk_info(info)?;
         self.version_major = info.version_major() as u16;
         self.version_minor = info.version_minor() as u16;
         self.version_patchlevel = info.version_patchlevel() as u16;
@@ -78,17 +219,81 @@ impl PackedSdkInfo {
         if let Some(build) = info.build() {
             copy_str_to_slice(&mut self.build[..], build);
         }
+        Ok(())
     }
 
-    pub fn to_sdk_info(&self) -> SdkInfo {
-        let build = str_from_zero_slice(&self.build[..]);
-        SdkInfo::new(
-            str_from_zero_slice(&self.name[..]),
+    /// Converts the packed info into an SDK info if it's valid
+    pub fn to_sdk_info(&self) -> Option<SdkInfo> {
+        let name = try_opt!(str_from_zero_slice(&self.name[..]));
+        let build = try_opt!(str_from_zero_slice(&self.build[..]));
+        Some(SdkInfo::new(
+            name,
             self.version_major as u32,
             self.version_minor as u32,
             self.version_patchlevel as u32,
             if build.is_empty() { None } else { Some(build) },
-        )
+        ))
+    }
+
+    fn decode(data: &[u8]) -> PackedSdkInfo {
+        let mut rv = PackedSdkInfo {
+            version_major: LittleEndian::read_u16(&data[8..10]),
+            version_minor: LittleEndian::read_u16(&data[10..12]),
+            version_patchlevel: LittleEndian::read_u16(&data[12..14]),
+            ..Default::default()
+        };
+        rv.name.copy_from_slice(&data[0..8]);
+        rv.build.copy_from_slice(&data[14..38]);
+        rv
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.name[..])?;
+        w.write_u16::<LittleEndian>(self.version_major)?;
+        w.write_u16::<LittleEndian>(self.version_minor)?;
+        w.write_u16::<LittleEndian>(self.version_patchlevel)?;
+        w.write_all(&self.build[..])
+    }
+}
+
+impl Stored for MemDbHeader {
+
+    fn stored_size(version: u32) -> usize {
+        if version == 2 { 82 } else { 90 }
+    }
+
+    fn decode(data: &[u8], version: u32) -> MemDbHeader {
+        let field = |idx: usize| LittleEndian::read_u32(&data[42 + idx * 4..46 + idx * 4]);
+        MemDbHeader {
+            version: LittleEndian::read_u32(&data[0..4]),
+            sdk_info: PackedSdkInfo::decode(&data[4..42]),
+            variants_start: field(0),
+            variants_count: field(1),
+            uuids_start: field(2),
+            uuids_count: field(3),
+            tagged_object_names_start: field(4),
+            tagged_object_names_end: field(5),
+            object_names_start: field(6),
+            object_names_count: field(7),
+            symbols_start: field(8),
+            symbols_count: field(9),
+            sections_start: if version == 2 { 0 } else { field(10) },
+            sections_count: if version == 2 { 0 } else { field(11) },
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32::<LittleEndian>(self.version)?;
+        self.sdk_info.encode(w)?;
+        for &field in &[self.variants_start, self.variants_count,
+                        self.uuids_start, self.uuids_count,
+                        self.tagged_object_names_start, self.tagged_object_names_end,
+                        self.object_names_start, self.object_names_count,
+                        self.symbols_start, self.symbols_count,
+                        self.sections_start, self.sections_count] {
+            w.write_u32::<LittleEndian>(field)?;
+        }
+        Ok(())
     }
 }
 
@@ -97,7 +302,7 @@ impl IndexedUuid {
     pub fn new(uuid: &Uuid, idx: usize) -> IndexedUuid {
         IndexedUuid {
             uuid: *uuid,
-            idx: idx as u16,
+            idx: idx as u32,
         }
     }
 
@@ -110,6 +315,409 @@ impl IndexedUuid {
     }
 }
 
+impl Stored for IndexedUuid {
+
+    fn stored_size(version: u32) -> usize {
+        if version < 4 { 18 } else { 20 }
+    }
+
+    fn decode(data: &[u8], version: u32) -> IndexedUuid {
+        IndexedUuid {
+            uuid: Uuid::from_bytes(&data[..16]).unwrap(),
+            idx: if version < 4 {
+                LittleEndian::read_u16(&data[16..18]) as u32
+            } else {
+                LittleEndian::read_u32(&data[16..20])
+            },
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(self.uuid.as_bytes())?;
+        w.write_u32::<LittleEndian>(self.idx)
+    }
+}
+
+impl SectionEntry {
+
+    /// Creates a new section entry
+    pub fn new(kind: SectionKind, offset: usize, len: usize) -> SectionEntry {
+        SectionEntry {
+            kind: kind as u32,
+            offset: offset as u32,
+            len: len as u32,
+        }
+    }
+
+    /// Checks if the section is of the given kind
+    pub fn is_kind(&self, kind: SectionKind) -> bool {
+        self.kind == kind as u32
+    }
+
+    /// The offset of the section in the file
+    pub fn offset(&self) -> usize {
+        self.offset as usize
+    }
+
+    /// The length of the section in bytes
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl Stored for SectionEntry {
+
+    fn stored_size(_version: u32) -> usize {
+        12
+    }
+
+    fn decode(data: &[u8], _version: u32) -> SectionEntry {
+        SectionEntry {
+            kind: LittleEndian::read_u32(&data[0..4]),
+            offset: LittleEndian::read_u32(&data[4..8]),
+            len: LittleEndian::read_u32(&data[8..12]),
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32::<LittleEndian>(self.kind)?;
+        w.write_u32::<LittleEndian>(self.offset)?;
+        w.write_u32::<LittleEndian>(self.len)
+    }
+}
+
+impl ChecksumEntry {
+
+    /// Creates a new checksum entry
+    pub fn new(offset: usize, len: usize, digest: [u8; 16]) -> ChecksumEntry {
+        ChecksumEntry {
+            offset: offset as u32,
+            len: len as u32,
+            digest: digest,
+        }
+    }
+
+    /// The offset of the checksummed region in the file
+    pub fn offset(&self) -> usize {
+        self.offset as usize
+    }
+
+    /// The length of the checksummed region in bytes
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// The MD5 digest of the region
+    pub fn digest(&self) -> &[u8; 16] {
+        &self.digest
+    }
+}
+
+impl Stored for ChecksumEntry {
+
+    fn stored_size(_version: u32) -> usize {
+        24
+    }
+
+    fn decode(data: &[u8], _version: u32) -> ChecksumEntry {
+        let mut digest = [0u8; 16];
+        digest.copy_from_slice(&data[8..24]);
+        ChecksumEntry {
+            offset: LittleEndian::read_u32(&data[0..4]),
+            len: LittleEndian::read_u32(&data[4..8]),
+            digest: digest,
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32::<LittleEndian>(self.offset)?;
+        w.write_u32::<LittleEndian>(self.len)?;
+        w.write_all(&self.digest[..])
+    }
+}
+
+impl UuidSdkItem {
+
+    /// Creates a new item mapping a variant to a source SDK
+    pub fn new(uuid_idx: usize, sdk_idx: usize) -> UuidSdkItem {
+        UuidSdkItem {
+            uuid_idx: uuid_idx as u32,
+            sdk_idx: sdk_idx as u32,
+        }
+    }
+
+    /// The index of the variant in the UUID index
+    pub fn uuid_idx(&self) -> usize {
+        self.uuid_idx as usize
+    }
+
+    /// The index of the SDK in the source SDKs
+    pub fn sdk_idx(&self) -> usize {
+        self.sdk_idx as usize
+    }
+}
+
+impl Stored for UuidSdkItem {
+
+    fn stored_size(_version: u32) -> usize {
+        8
+    }
+
+    fn decode(data: &[u8], _version: u32) -> UuidSdkItem {
+        UuidSdkItem {
+            uuid_idx: LittleEndian::read_u32(&data[0..4]),
+            sdk_idx: LittleEndian::read_u32(&data[4..8]),
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32::<LittleEndian>(self.uuid_idx)?;
+        w.write_u32::<LittleEndian>(self.sdk_idx)
+    }
+}
+
+impl CodeSectionKind {
+
+    /// Returns the kind of a Mach-O or ELF section by its name
+    pub fn from_section_name(name: &str) -> CodeSectionKind {
+        match name {
+            "__text" | ".text" => CodeSectionKind::Text,
+            "__stubs" | "__symbol_stub" | "__symbol_stub1" |
+            "__picsymbolstub4" | ".plt" => CodeSectionKind::Stubs,
+            "__stub_helper" => CodeSectionKind::StubHelper,
+            "__auth_stubs" => CodeSectionKind::AuthStubs,
+            "__objc_stubs" => CodeSectionKind::ObjcStubs,
+            _ => CodeSectionKind::Other,
+        }
+    }
+
+    /// The name of the kind as reported by the API
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CodeSectionKind::Text => "text",
+            CodeSectionKind::Stubs => "stubs",
+            CodeSectionKind::StubHelper => "stub_helper",
+            CodeSectionKind::AuthStubs => "auth_stubs",
+            CodeSectionKind::ObjcStubs => "objc_stubs",
+            CodeSectionKind::Other => "other",
+        }
+    }
+}
+
+impl CodeSectionItem {
+
+    /// Creates a new item for an executable section
+    pub fn new(addr: u64, size: u64, kind: CodeSectionKind) -> CodeSectionItem {
+        CodeSectionItem {
+            addr: addr,
+            size: size as u32,
+            kind: kind as u32,
+        }
+    }
+
+    /// The address of the section relative to the image
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// The size of the section
+    pub fn size(&self) -> u64 {
+        self.size as u64
+    }
+
+    /// The kind of the section
+    pub fn kind(&self) -> CodeSectionKind {
+        match self.kind {
+            0 => CodeSectionKind::Text,
+            1 => CodeSectionKind::Stubs,
+            2 => CodeSectionKind::StubHelper,
+            3 => CodeSectionKind::AuthStubs,
+            4 => CodeSectionKind::ObjcStubs,
+            _ => CodeSectionKind::Other,
+        }
+    }
+}
+
+impl Stored for CodeSectionItem {
+
+    fn stored_size(_version: u32) -> usize {
+        16
+    }
+
+    fn decode(data: &[u8], _version: u32) -> CodeSectionItem {
+        CodeSectionItem {
+            addr: LittleEndian::read_u64(&data[0..8]),
+            size: LittleEndian::read_u32(&data[8..12]),
+            kind: LittleEndian::read_u32(&data[12..16]),
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u64::<LittleEndian>(self.addr)?;
+        w.write_u32::<LittleEndian>(self.size)?;
+        w.write_u32::<LittleEndian>(self.kind)
+    }
+}
+
+impl LineItem {
+
+    /// Creates a new row for a line table
+    pub fn new(addr: u64, file: u32, line: u32) -> LineItem {
+        LineItem {
+            addr: addr,
+            file: file,
+            line: line,
+        }
+    }
+
+    /// The address relative to the image
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// The offset of the file name in the line files section
+    pub fn file(&self) -> u32 {
+        self.file
+    }
+
+    /// The line number or zero for the end of a sequence
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+}
+
+impl Stored for LineItem {
+
+    fn stored_size(_version: u32) -> usize {
+        16
+    }
+
+    fn decode(data: &[u8], _version: u32) -> LineItem {
+        LineItem {
+            addr: LittleEndian::read_u64(&data[0..8]),
+            file: LittleEndian::read_u32(&data[8..12]),
+            line: LittleEndian::read_u32(&data[12..16]),
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u64::<LittleEndian>(self.addr)?;
+        w.write_u32::<LittleEndian>(self.file)?;
+        w.write_u32::<LittleEndian>(self.line)
+    }
+}
+
+impl NameIndexItem {
+
+    /// Creates a new item for the name index
+    pub fn new(sym_id: u32, uuid_idx: usize, tagged_object_name: usize,
+               item_idx: usize) -> NameIndexItem {
+        NameIndexItem {
+            sym_id: sym_id,
+            uuid_idx: uuid_idx as u32,
+            tagged_object_name: tagged_object_name as u32,
+            item_idx: item_idx as u32,
+        }
+    }
+
+    /// The ID of the symbol
+    pub fn sym_id(&self) -> u32 {
+        self.sym_id
+    }
+
+    /// The index of the variant in the UUID index
+    pub fn uuid_idx(&self) -> usize {
+        self.uuid_idx as usize
+    }
+
+    /// The offset of the `name:arch` string of the object
+    pub fn tagged_object_name(&self) -> usize {
+        self.tagged_object_name as usize
+    }
+
+    /// The index of the symbol in the variant's symbol index
+    pub fn item_idx(&self) -> usize {
+        self.item_idx as usize
+    }
+}
+
+impl Stored for NameIndexItem {
+
+    fn stored_size(version: u32) -> usize {
+        if version < 4 { 14 } else { 16 }
+    }
+
+    fn decode(data: &[u8], version: u32) -> NameIndexItem {
+        if version < 4 {
+            // version 3 stores 16 bit variant indexes
+            NameIndexItem {
+                sym_id: LittleEndian::read_u32(&data[0..4]),
+                uuid_idx: LittleEndian::read_u16(&data[4..6]) as u32,
+                tagged_object_name: LittleEndian::read_u32(&data[6..10]),
+                item_idx: LittleEndian::read_u32(&data[10..14]),
+            }
+        } else {
+            NameIndexItem {
+                sym_id: LittleEndian::read_u32(&data[0..4]),
+                uuid_idx: LittleEndian::read_u32(&data[4..8]),
+                tagged_object_name: LittleEndian::read_u32(&data[8..12]),
+                item_idx: LittleEndian::read_u32(&data[12..16]),
+            }
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32::<LittleEndian>(self.sym_id)?;
+        w.write_u32::<LittleEndian>(self.uuid_idx)?;
+        w.write_u32::<LittleEndian>(self.tagged_object_name)?;
+        w.write_u32::<LittleEndian>(self.item_idx)
+    }
+}
+
+impl ObjectNameIndexItem {
+
+    /// Creates a new item for the object name index
+    pub fn new(tagged_object_name: usize, uuid_idx: usize) -> ObjectNameIndexItem {
+        ObjectNameIndexItem {
+            tagged_object_name: tagged_object_name as u32,
+            uuid_idx: uuid_idx as u32,
+        }
+    }
+
+    /// The offset of the `name:arch` string of the object
+    pub fn tagged_object_name(&self) -> usize {
+        self.tagged_object_name as usize
+    }
+
+    /// The index of the variant in the UUID index
+    pub fn uuid_idx(&self) -> usize {
+        self.uuid_idx as usize
+    }
+}
+
+impl Stored for ObjectNameIndexItem {
+
+    fn stored_size(version: u32) -> usize {
+        if version < 4 { 6 } else { 8 }
+    }
+
+    fn decode(data: &[u8], version: u32) -> ObjectNameIndexItem {
+        ObjectNameIndexItem {
+            tagged_object_name: LittleEndian::read_u32(&data[0..4]),
+            uuid_idx: if version < 4 {
+                LittleEndian::read_u16(&data[4..6]) as u32
+            } else {
+                LittleEndian::read_u32(&data[4..8])
+            },
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32::<LittleEndian>(self.tagged_object_name)?;
+        w.write_u32::<LittleEndian>(self.uuid_idx)
+    }
+}
+
 impl StoredSlice {
 
     /// Creates a new stored slice
@@ -129,22 +737,42 @@ impl StoredSlice {
     }
 
     /// Returns the length of the stored slice
+    ///
+    /// For compressed slices this is the offset in the decompressed block.
     pub fn len(&self) -> usize {
         (self.len as usize) & 0x7fffffff
     }
 
-    /// Indicates that the string is compressed (currently not used)
+    /// Indicates that the string is compressed
     pub fn is_compressed(&self) -> bool {
         self.len >> 31 != 0
     }
 }
 
+impl Stored for StoredSlice {
+
+    fn stored_size(_version: u32) -> usize {
+        8
+    }
+
+    fn decode(data: &[u8], _version: u32) -> StoredSlice {
+        StoredSlice {
+            offset: LittleEndian::read_u32(&data[0..4]),
+            len: LittleEndian::read_u32(&data[4..8]),
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u32::<LittleEndian>(self.offset)?;
+        w.write_u32::<LittleEndian>(self.len)
+    }
+}
+
 impl IndexItem {
     /// Creates a new indexed symbol in the index
-    pub fn new(addr: u64, src_id: u16, sym_id: Option<u32>) -> IndexItem {
+    pub fn new(addr: u64, src_id: u32, sym_id: Option<u32>) -> IndexItem {
         IndexItem {
-            addr_low: (addr & 0xffffffff) as u32,
-            addr_high: ((addr >> 32) &0xffff) as u16,
+            addr: addr,
             src_id: src_id,
             sym_id: sym_id.unwrap_or(!0),
         }
@@ -152,11 +780,11 @@ impl IndexItem {
 
     /// The address of the symbol
     pub fn addr(&self) -> u64 {
-        ((self.addr_high as u64) << 32) | (self.addr_low as u64)
+        self.addr
     }
 
     /// The ID of the source variant
-    pub fn src_id(&self) -> u16 {
+    pub fn src_id(&self) -> u32 {
         self.src_id
     }
 
@@ -169,3 +797,34 @@ impl IndexItem {
         }
     }
 }
+
+impl Stored for IndexItem {
+
+    fn stored_size(version: u32) -> usize {
+        if version < 4 { 12 } else { 16 }
+    }
+
+    fn decode(data: &[u8], version: u32) -> IndexItem {
+        if version < 4 {
+            // older versions store 48 bit addresses and 16 bit source ids
+            IndexItem {
+                addr: (LittleEndian::read_u32(&data[0..4]) as u64) |
+                    ((LittleEndian::read_u16(&data[4..6]) as u64) << 32),
+                src_id: LittleEndian::read_u16(&data[6..8]) as u32,
+                sym_id: LittleEndian::read_u32(&data[8..12]),
+            }
+        } else {
+            IndexItem {
+                addr: LittleEndian::read_u64(&data[0..8]),
+                src_id: LittleEndian::read_u32(&data[8..12]),
+                sym_id: LittleEndian::read_u32(&data[12..16]),
+            }
+        }
+    }
+
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        w.write_u64::<LittleEndian>(self.addr)?;
+        w.write_u32::<LittleEndian>(self.src_id)?;
+        w.write_u32::<LittleEndian>(self.sym_id)
+    }
+}
@@ -3,49 +3,351 @@
 //! A support folder with SDK debug symbols can be processed into a
 //! in-memory database format which is a flat file on the file system
 //! that gets mmaped into the process.
-use std::io::{Write, Seek, SeekFrom};
+use std::env;
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::path::Path;
 use std::fs::File;
 use std::mem;
-use std::slice;
+use std::u32;
 use std::cell::RefCell;
-use std::collections::{HashSet, HashMap};
+use std::thread;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::collections::{HashSet, HashMap, BTreeMap};
 
+use md5;
 use uuid::Uuid;
+use chrono::{UTC, TimeZone};
 use xz2::write::XzEncoder;
 use tempfile::tempfile;
 use console::{style, StyledObject};
 use indicatif::{ProgressBar, ProgressStyle};
+use byteorder::{ByteOrder, LittleEndian};
 
-use super::types::{IndexItem, StoredSlice, MemDbHeader, IndexedUuid};
-use super::super::Result;
-use super::super::sdk::{SdkInfo, DumpOptions, Objects};
-use super::super::dsym::{Object, Variant};
+use super::types::{IndexItem, StoredSlice, MemDbHeader, IndexedUuid, Stored,
+                   NameIndexItem, ObjectNameIndexItem, SectionEntry, SectionKind,
+                   ChecksumEntry, UuidSdkItem, LineItem, CodeSectionItem,
+                   CodeSectionKind, PackedSdkInfo, MEMDB_VERSION};
+use super::read::MemDb;
+use super::spill::{Spill, SpillResult, NameRef, VariantTables, STRING_BLOCK_SIZE,
+                   compress_string_block, for_each_stored};
+use super::super::{Result, Error, ErrorKind};
+use super::super::sdk::{SdkInfo, DumpOptions, Objects, RawObject};
+use super::super::constants::VERSION;
+use super::super::dsym::{Object, Variant, STRIPPED_SYMBOL};
+use super::super::breakpad::BreakpadModule;
 use super::super::utils::{file_size_format, copy_with_progress};
 
 
 struct MemDbBuilder<W> {
     writer: RefCell<W>,
     tempfile: Option<RefCell<File>>,
+    hasher: RefCell<md5::Context>,
     info: SdkInfo,
     symbols: Vec<String>,
     symbols_map: HashMap<String, u32>,
     object_names: Vec<String>,
-    object_names_map: HashMap<String, u16>,
+    object_names_map: HashMap<String, u32>,
     object_uuid_mapping: Vec<(String, Uuid)>,
     variant_uuids: Vec<IndexedUuid>,
     variant_uuids_seen: HashSet<Uuid>,
     variants: Vec<Vec<IndexItem>>,
+    spill: Option<Spill>,
+    record_sources: bool,
+    source_sdks: Vec<SdkInfo>,
+    uuid_sdks: Vec<(Uuid, u32)>,
+    metadata: BTreeMap<String, String>,
+    line_files: Vec<String>,
+    line_files_map: HashMap<String, u32>,
+    line_tables: VariantTables<LineItem>,
+    code_sections: VariantTables<CodeSectionItem>,
     symbol_count: usize,
     options: DumpOptions,
 }
 
 fn format_step(step: usize, opts: &DumpOptions) -> StyledObject<String> {
-    let steps = if opts.compress {
-        6
+    let mut steps = 5;
+    if opts.name_index {
+        steps += 1;
+    }
+    if opts.compress {
+        steps += 1;
+    }
+    style(format!("[{}/{}]", step, steps)).dim()
+}
+
+/// Sorts interned strings and returns their new ids indexed by the old ones
+fn sort_interned(strings: &mut Vec<String>) -> Vec<u32> {
+    let mut order: Vec<u32> = (0..strings.len() as u32).collect();
+    order.sort_by(|&a, &b| strings[a as usize].cmp(&strings[b as usize]));
+    let mut new_ids = vec![0u32; order.len()];
+    for (new_id, &old_id) in order.iter().enumerate() {
+        new_ids[old_id as usize] = new_id as u32;
+    }
+    let mut old = mem::replace(strings, vec![]);
+    *strings = order.iter()
+        .map(|&old_id| mem::replace(&mut old[old_id as usize], String::new()))
+        .collect();
+    new_ids
+}
+
+/// The symbols of an object variant extracted on a worker thread
+struct ExtractedVariant {
+    uuid: Uuid,
+    arch: String,
+    src: String,
+    index: Vec<(u64, Option<String>)>,
+    lines: Option<VariantLines>,
+    code_sections: Vec<CodeSectionItem>,
+}
+
+/// The line table of a variant
+///
+/// The rows are `(addr, file, line)` relative to the image where `file`
+/// indexes `files`.  A line of zero marks the end of a sequence.
+struct VariantLines {
+    files: Vec<String>,
+    rows: Vec<(u64, usize, u32)>,
+}
+
+/// Treats debug info that cannot be read like missing debug info.
+///
+/// The symbol table of the variant is still usable in that case so the
+/// variant is kept without line and function information.
+fn skip_bad_dwarf<T: Default>(rv: Result<T>, var: &Variant) -> Result<T> {
+    match rv {
+        Err(ref err) if is_bad_dwarf(err) => {
+            warn!("ignoring unreadable debug info of {} ({}): {}",
+                  var.uuid().map(|x| x.to_string()).unwrap_or_else(|| "<unknown>".into()),
+                  var.arch(), err);
+            Ok(Default::default())
+        }
+        rv => rv,
+    }
+}
+
+fn is_bad_dwarf(err: &Error) -> bool {
+    match *err.kind() {
+        ErrorKind::BadDwarf => true,
+        _ => false,
+    }
+}
+
+/// Builds the symbol index of a variant
+///
+/// The entries are `(addr, symbol)` relative to the image and sorted by
+/// address.  End markers have no symbol.
+fn extract_variant(obj: &Object, var: &Variant) -> Result<Vec<(u64, Option<String>)>> {
+    let mut symbols = obj.symbols(var.arch())?;
+    let function_starts: Vec<u64> = symbols.function_starts()?
+        .into_iter().map(|addr| addr - var.vmaddr()).collect();
+    let subprograms = skip_bad_dwarf(symbols.subprograms(), var)?;
+    let objc_methods = symbols.objc_methods();
+
+    // build symbol index
+    let mut index = vec![];
+    for (addr, sym) in symbols.iter() {
+        index.push((addr - var.vmaddr(), Some(sym.to_string())));
+    }
+
+    // functions that are missing from the symbol table are taken from the
+    // debug info if the object has any.
+    let mut known: HashSet<u64> = index.iter().map(|x| x.0).collect();
+    let mut function_ends = vec![];
+    for subprogram in subprograms {
+        if subprogram.low_pc() < var.vmaddr() {
+            continue;
+        }
+        let addr = subprogram.low_pc() - var.vmaddr();
+        if subprogram.high_pc() > subprogram.low_pc() {
+            function_ends.push((addr, subprogram.high_pc() - var.vmaddr()));
+        }
+        if known.insert(addr) {
+            index.push((addr, Some(subprogram.name().to_string())));
+        }
+    }
+
+    // Objective-C methods are often stripped but can be recovered from
+    // the runtime metadata.
+    for (addr, name) in objc_methods {
+        if addr < var.vmaddr() {
+            continue;
+        }
+        if known.insert(addr - var.vmaddr()) {
+            index.push((addr - var.vmaddr(), Some(name)));
+        }
+    }
+
+    // functions that are neither in the symbol table, the debug info nor
+    // the Objective-C metadata get a placeholder so that the previous symbol ends there.
+    for addr in function_starts {
+        if known.insert(addr) {
+            index.push((addr, Some(STRIPPED_SYMBOL.to_string())));
+        }
+    }
+
+    // functions from the debug info end at their high pc so that the
+    // padding after them does not resolve, unless the next function
+    // already starts there or earlier.
+    let mut starts: Vec<u64> = known.iter().cloned().collect();
+    starts.sort();
+    for (addr, end) in function_ends {
+        let next = match starts.binary_search(&addr) {
+            Ok(idx) => starts.get(idx + 1),
+            Err(idx) => starts.get(idx),
+        };
+        if next.map_or(true, |&next| end < next) && known.insert(end) {
+            index.push((end, None));
+        }
+    }
+
+    // symbols end with the section they are in and the image
+    for sect in var.code_sections() {
+        if sect.addr() < var.vmaddr() {
+            continue;
+        }
+        let end = sect.addr() + sect.size() - var.vmaddr();
+        if known.insert(end) {
+            index.push((end, None));
+        }
+    }
+    if var.vmsize() > 0 && known.insert(var.vmsize()) {
+        index.push((var.vmsize(), None));
+    }
+
+    index.sort_by_key(|item| item.0);
+
+    Ok(index)
+}
+
+/// Reads the line table of a variant from its DWARF debug info
+fn extract_lines(obj: &Object, var: &Variant) -> Result<Option<VariantLines>> {
+    let symbols = obj.symbols(var.arch())?;
+    let table = match skip_bad_dwarf(symbols.line_table(), var)? {
+        Some(table) => table,
+        None => { return Ok(None); }
+    };
+
+    // rows before the image belong to functions that were stripped.  Of
+    // rows with the same address only the last one is kept.
+    let mut rows: Vec<(u64, usize, u32)> = vec![];
+    for row in table.rows() {
+        if row.addr() < var.vmaddr() {
+            continue;
+        }
+        let addr = row.addr() - var.vmaddr();
+        if rows.last().map_or(false, |x| x.0 == addr) {
+            rows.pop();
+        }
+        rows.push((addr, row.file(), row.line()));
+    }
+    if rows.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(VariantLines {
+        files: table.files().to_vec(),
+        rows: rows,
+    }))
+}
+
+/// Returns the executable sections of a variant relative to the image
+fn extract_code_sections(var: &Variant) -> Vec<CodeSectionItem> {
+    let mut rv: Vec<_> = var.code_sections().iter()
+        .filter(|sect| sect.addr() >= var.vmaddr() && sect.size() > 0)
+        .map(|sect| CodeSectionItem::new(sect.addr() - var.vmaddr(), sect.size(),
+                                         CodeSectionKind::from_section_name(sect.name())))
+        .collect();
+    rv.sort_by_key(|item| item.addr());
+    rv
+}
+
+/// Builds the symbol index and line table of a Breakpad module
+///
+/// Breakpad addresses are already relative to the image.  Public symbols
+/// are only used where no function starts.
+fn extract_breakpad_module(module: &BreakpadModule)
+    -> (Vec<(u64, Option<String>)>, Option<VariantLines>)
+{
+    let mut index = vec![];
+    let mut known = HashSet::new();
+    for func in module.functions() {
+        if known.insert(func.addr()) {
+            index.push((func.addr(), Some(func.name().to_string())));
+        }
+    }
+    for &(addr, ref name) in module.publics() {
+        if known.insert(addr) {
+            index.push((addr, Some(name.clone())));
+        }
+    }
+    for func in module.functions() {
+        let end = func.addr() + func.size();
+        if func.size() > 0 && known.insert(end) {
+            index.push((end, None));
+        }
+    }
+    index.sort_by_key(|item| item.0);
+
+    let mut records: Vec<_> = module.functions().iter()
+        .flat_map(|func| func.lines().iter())
+        .collect();
+    records.sort_by_key(|record| record.addr());
+
+    // every record ends its sequence unless the next one starts right
+    // after it.
+    let mut files = vec![];
+    let mut file_ids = HashMap::new();
+    let mut rows: Vec<(u64, usize, u32)> = vec![];
+    for record in records {
+        let filename = match module.file(record.file()) {
+            Some(filename) => filename,
+            None => { continue; }
+        };
+        let file = *file_ids.entry(record.file()).or_insert_with(|| {
+            files.push(filename.to_string());
+            files.len() - 1
+        });
+        if rows.last().map_or(false, |x| x.0 == record.addr()) {
+            rows.pop();
+        }
+        rows.push((record.addr(), file, record.line()));
+        rows.push((record.addr() + record.size(), file, 0));
+    }
+    let lines = if rows.is_empty() {
+        None
     } else {
-        5
+        Some(VariantLines {
+            files: files,
+            rows: rows,
+        })
     };
-    style(format!("[{}/{}]", step, steps)).dim()
+
+    (index, lines)
+}
+
+/// Parses an object and extracts the symbols of all its variants
+///
+/// Files that are neither Mach-O nor ELF objects result in no variants.
+fn extract_object(filename: &str, raw: RawObject) -> Result<Vec<ExtractedVariant>> {
+    let obj = match raw.parse()? {
+        Some(obj) => obj,
+        None => { return Ok(vec![]); }
+    };
+    let mut rv = vec![];
+    for variant in obj.variants() {
+        let src = variant.name().unwrap_or(filename);
+        if let Some(uuid) = variant.uuid() {
+            rv.push(ExtractedVariant {
+                uuid: uuid,
+                arch: variant.arch().to_string(),
+                src: src.to_string(),
+                index: extract_variant(&obj, variant)?,
+                lines: extract_lines(&obj, variant)?,
+                code_sections: extract_code_sections(variant),
+            });
+        }
+    }
+    Ok(rv)
 }
 
 trait WriteSeek : Write + Seek {}
@@ -56,6 +358,8 @@ impl<W: Write + Seek> MemDbBuilder<W> {
     pub fn new(writer: W, info: &SdkInfo, opts: DumpOptions)
         -> Result<MemDbBuilder<W>>
     {
+        // fail before any work is done if the header cannot be written
+        PackedSdkInfo::check_sdk_info(info)?;
         let rv = MemDbBuilder {
             writer: RefCell::new(writer),
             tempfile: if opts.compress {
@@ -63,6 +367,7 @@ impl<W: Write + Seek> MemDbBuilder<W> {
             } else {
                 None
             },
+            hasher: RefCell::new(md5::Context::new()),
             info: info.clone(),
             symbols: vec![],
             symbols_map: HashMap::new(),
@@ -72,6 +377,27 @@ impl<W: Write + Seek> MemDbBuilder<W> {
             variant_uuids: vec![],
             variant_uuids_seen: HashSet::new(),
             variants: vec![],
+            spill: if opts.low_memory {
+                Some(Spill::new()?)
+            } else {
+                None
+            },
+            record_sources: false,
+            source_sdks: vec![],
+            uuid_sdks: vec![],
+            metadata: opts.metadata.iter().cloned().collect(),
+            line_files: vec![],
+            line_files_map: HashMap::new(),
+            line_tables: if opts.low_memory {
+                VariantTables::spilled()?
+            } else {
+                VariantTables::new()
+            },
+            code_sections: if opts.low_memory {
+                VariantTables::spilled()?
+            } else {
+                VariantTables::new()
+            },
             symbol_count: 0,
             options: opts,
         };
@@ -89,20 +415,39 @@ impl<W: Write + Seek> MemDbBuilder<W> {
     }
 
     fn write_bytes(&self, x: &[u8]) -> Result<usize> {
+        self.hasher.borrow_mut().consume(x);
         self.with_file(|w| {
             w.write_all(x)?;
             Ok(x.len())
         })
     }
 
-    fn write<T>(&self, x: &T) -> Result<usize> {
-        unsafe {
-            let bytes : *const u8 = mem::transmute(x);
-            let size = mem::size_of_val(x);
-            self.with_file(|w| {
-                w.write_all(slice::from_raw_parts(bytes, size))?;
-                Ok(size)
-            })
+    fn write<T: Stored>(&self, x: &T) -> Result<usize> {
+        let mut buf = Vec::with_capacity(T::stored_size(MEMDB_VERSION));
+        x.encode(&mut buf)?;
+        self.write_bytes(&buf[..])
+    }
+
+    /// Starts a checksummed region at the current position.
+    fn begin_checksum(&self) -> Result<usize> {
+        *self.hasher.borrow_mut() = md5::Context::new();
+        self.tell()
+    }
+
+    /// Ends a checksummed region that started at the given offset.
+    fn end_checksum(&self, start: usize) -> Result<ChecksumEntry> {
+        let hasher = mem::replace(&mut *self.hasher.borrow_mut(), md5::Context::new());
+        Ok(ChecksumEntry::new(start, self.tell()? - start, hasher.compute().0))
+    }
+
+    fn copy_file(&self, f: &mut File) -> Result<()> {
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let read = f.read(&mut buf[..])?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.write_bytes(&buf[..read])?;
         }
     }
 
@@ -114,95 +459,328 @@ impl<W: Write + Seek> MemDbBuilder<W> {
     }
 
     fn tell(&self) -> Result<usize> {
-        self.with_file(|w| {
-            Ok(w.seek(SeekFrom::Current(0))? as usize)
-        })
+        let pos = self.with_file(|w| w.seek(SeekFrom::Current(0)))?;
+        // all offsets in the file are stored as 32 bit integers
+        if pos > u32::MAX as u64 {
+            return Err(ErrorKind::MemDbLimitExceeded("bytes").into());
+        }
+        Ok(pos as usize)
     }
 
-    fn add_symbol(&mut self, sym: &str) -> u32 {
+    fn add_symbol(&mut self, sym: &str) -> Result<u32> {
         if let Some(&sym_id) = self.symbols_map.get(sym) {
-            return sym_id as u32;
+            return Ok(sym_id);
         }
+        // the largest id is reserved for the end marker
         let symbol_count = self.symbols.len();
+        if symbol_count >= u32::MAX as usize {
+            return Err(ErrorKind::MemDbLimitExceeded("symbols").into());
+        }
         self.symbols.push(sym.to_string());
         self.symbols_map.insert(sym.to_string(), symbol_count as u32);
-        symbol_count as u32
+        Ok(symbol_count as u32)
     }
 
-    fn add_object_name(&mut self, src: &str) -> u16 {
+    fn add_object_name(&mut self, src: &str) -> Result<u32> {
         if let Some(&src_id) = self.object_names_map.get(src) {
-            return src_id as u16;
+            return Ok(src_id);
         }
         let object_count = self.object_names.len();
+        if object_count > u32::MAX as usize {
+            return Err(ErrorKind::MemDbLimitExceeded("objects").into());
+        }
         self.object_names.push(src.to_string());
-        self.object_names_map.insert(src.to_string(), object_count as u16);
-        object_count as u16
+        self.object_names_map.insert(src.to_string(), object_count as u32);
+        Ok(object_count as u32)
     }
 
-    pub fn write_object(&mut self, obj: &Object, filename: Option<&str>) -> Result<()> {
-        for variant in obj.variants() {
-            let src = variant.name().or(filename).unwrap();
-            if let Some(uuid) = variant.uuid() {
-                self.write_object_variant(&obj, &variant, &uuid, src)?;
+    fn add_line_file(&mut self, file: &str) -> Result<u32> {
+        if let Some(&file_id) = self.line_files_map.get(file) {
+            return Ok(file_id);
+        }
+        let file_count = self.line_files.len();
+        if file_count > u32::MAX as usize {
+            return Err(ErrorKind::MemDbLimitExceeded("source files").into());
+        }
+        self.line_files.push(file.to_string());
+        self.line_files_map.insert(file.to_string(), file_count as u32);
+        Ok(file_count as u32)
+    }
+
+    fn add_source_sdk(&mut self, info: &SdkInfo) -> u32 {
+        if let Some(sdk_idx) = self.source_sdks.iter().position(|x| x == info) {
+            return sdk_idx as u32;
+        }
+        self.source_sdks.push(info.clone());
+        (self.source_sdks.len() - 1) as u32
+    }
+
+    /// Registers the symbol index, line table and code sections of a variant
+    ///
+    /// The entries are `(addr, src_id, symbol)` sorted by address.  In low
+    /// memory mode they are spilled to disk right away, as are the line
+    /// table and code sections.
+    fn push_variant(&mut self, uuid: &Uuid, entries: Vec<(u64, u32, Option<String>)>,
+                    lines: Option<VariantLines>, code_sections: Vec<CodeSectionItem>)
+        -> Result<()>
+    {
+        if self.variant_uuids.len() > u32::MAX as usize {
+            return Err(ErrorKind::MemDbLimitExceeded("variants").into());
+        }
+        self.symbol_count += entries.iter().filter(|x| x.2.is_some()).count();
+        let variant_idx = self.variant_uuids.len();
+        if self.options.low_memory {
+            self.spill.as_mut().unwrap().push_variant(entries)?;
+        } else {
+            let mut index = vec![];
+            for (addr, src_id, sym) in entries {
+                let sym_id = match sym {
+                    Some(sym) => Some(self.add_symbol(&sym)?),
+                    None => None,
+                };
+                index.push(IndexItem::new(addr, src_id, sym_id));
             }
+            self.variants.push(index);
         }
+        let line_table = match lines {
+            Some(lines) => {
+                let mut file_ids = vec![];
+                for file in lines.files.iter() {
+                    file_ids.push(self.add_line_file(file)?);
+                }
+                Some(lines.rows.into_iter().map(|(addr, file, line)| {
+                    LineItem::new(addr, file_ids[file], line)
+                }).collect())
+            }
+            None => None,
+        };
+        self.line_tables.push(line_table)?;
+        self.code_sections.push(if code_sections.is_empty() {
+            None
+        } else {
+            Some(code_sections)
+        })?;
+        self.variant_uuids.push(IndexedUuid::new(uuid, variant_idx));
         Ok(())
     }
 
-    fn write_object_variant(&mut self, obj: &Object, var: &Variant,
-                            uuid: &Uuid, src: &str) -> Result<bool> {
-        self.object_uuid_mapping.push((
-            format!("{}:{}", src, var.arch()),
-            *uuid
-        ));
+    /// Adds the variants of another memdb file.
+    ///
+    /// Objects with a `name:arch` tagged name in `replaced` are skipped.
+    /// Variants that were already added are skipped as well but if sources
+    /// are recorded the SDKs they came from are added.
+    pub fn write_memdb(&mut self, memdb: &MemDb, replaced: &HashSet<String>) -> Result<()> {
+        let mut uuids = vec![];
+        for (tagged_object, uuid) in memdb.list_tagged_objects()? {
+            if replaced.contains(&tagged_object) {
+                continue;
+            }
+            self.object_uuid_mapping.push((tagged_object, uuid));
+            uuids.push(uuid);
+        }
+        uuids.sort();
+        uuids.dedup();
+
+        for uuid in uuids {
+            if self.record_sources {
+                for info in memdb.sdks_for_uuid(&uuid)? {
+                    let sdk_idx = self.add_source_sdk(&info);
+                    self.uuid_sdks.push((uuid, sdk_idx));
+                }
+            }
 
-        if self.variant_uuids_seen.contains(uuid) {
-            return Ok(false);
+            if self.variant_uuids_seen.contains(&uuid) {
+                continue;
+            }
+            self.variant_uuids_seen.insert(uuid);
+
+            let mut index = vec![];
+            for entry_rv in memdb.iter_index(&uuid)? {
+                let entry = entry_rv?;
+                let src_id = self.add_object_name(entry.object_name())?;
+                index.push((entry.addr(), src_id, entry.symbol().map(|x| x.to_string())));
+            }
+
+            let mut files = vec![];
+            let mut files_map = HashMap::new();
+            let mut rows = vec![];
+            for (addr, filename, line) in memdb.get_lines(&uuid)? {
+                let file = *files_map.entry(filename).or_insert_with(|| {
+                    files.push(filename.to_string());
+                    files.len() - 1
+                });
+                rows.push((addr, file, line));
+            }
+            let lines = if rows.is_empty() {
+                None
+            } else {
+                Some(VariantLines {
+                    files: files,
+                    rows: rows,
+                })
+            };
+            let code_sections = memdb.get_code_sections(&uuid)?;
+            self.push_variant(&uuid, index, lines, code_sections)?;
         }
-        self.variant_uuids_seen.insert(*uuid);
 
-        let mut symbols = obj.symbols(var.arch())?;
-        let src_id = self.add_object_name(src);
+        Ok(())
+    }
+
+    pub fn write_object(&mut self, obj: &Object, filename: Option<&str>) -> Result<()> {
+        for variant in obj.variants() {
+            let src = variant.name().or(filename).unwrap();
+            if let Some(uuid) = variant.uuid() {
+                if self.add_object_variant(&uuid, variant.arch(), src) {
+                    let index = extract_variant(obj, variant)?;
+                    let lines = extract_lines(obj, variant)?;
+                    let code_sections = extract_code_sections(variant);
+                    self.push_object_variant(&uuid, src, index, lines, code_sections)?;
+                }
+            }
+        }
+        Ok(())
+    }
 
-        // build symbol index
-        let mut index = vec![];
-        for (addr, sym) in symbols.iter() {
-            let sym_id = self.add_symbol(sym);
-            index.push(IndexItem::new(addr - var.vmaddr(), src_id, Some(sym_id)));
-            self.symbol_count += 1;
+    /// Adds variants that were extracted with `extract_object`
+    fn write_extracted_object(&mut self, variants: Vec<ExtractedVariant>) -> Result<()> {
+        for variant in variants {
+            if self.add_object_variant(&variant.uuid, &variant.arch, &variant.src) {
+                self.push_object_variant(&variant.uuid, &variant.src, variant.index,
+                                         variant.lines, variant.code_sections)?;
+            }
         }
+        Ok(())
+    }
 
-        // write an end marker if we know the image size
-        if var.vmsize() > 0 {
-            index.push(IndexItem::new(var.vmsize(), src_id, None));
-            self.symbol_count += 1;
+    /// Adds the module of a Breakpad symbol file
+    ///
+    /// The module is named after the name in its `MODULE` record.
+    pub fn write_breakpad_module(&mut self, module: &BreakpadModule) -> Result<()> {
+        if self.add_object_variant(&module.uuid(), module.arch(), module.name()) {
+            let (index, lines) = extract_breakpad_module(module);
+            self.push_object_variant(&module.uuid(), module.name(), index, lines, vec![])?;
         }
+        Ok(())
+    }
 
-        index.sort_by_key(|item| item.addr());
+    /// Records an object variant and returns `true` if its symbols are needed
+    fn add_object_variant(&mut self, uuid: &Uuid, arch: &str, src: &str) -> bool {
+        self.object_uuid_mapping.push((format!("{}:{}", src, arch), *uuid));
+        self.variant_uuids_seen.insert(*uuid)
+    }
 
-        // register variant and uuid
-        self.variant_uuids.push(IndexedUuid::new(uuid, self.variants.len()));
-        self.variants.push(index);
+    fn push_object_variant(&mut self, uuid: &Uuid, src: &str, index: Vec<(u64, Option<String>)>,
+                           lines: Option<VariantLines>, code_sections: Vec<CodeSectionItem>)
+        -> Result<()>
+    {
+        let src_id = self.add_object_name(src)?;
+        let entries = index.into_iter().map(|(addr, sym)| (addr, src_id, sym)).collect();
+        self.push_variant(uuid, entries, lines, code_sections)
+    }
 
-        Ok(true)
+    fn write_string_block(&self, block: &[u8], inner_offsets: &[usize],
+                          slices: &mut Vec<StoredSlice>) -> Result<()> {
+        let offset = self.tell()?;
+        let compressed = compress_string_block(block)?;
+        let mut len = [0u8; 4];
+        LittleEndian::write_u32(&mut len, compressed.len() as u32);
+        self.write_bytes(&len[..])?;
+        self.write_bytes(&compressed[..])?;
+        for &inner_offset in inner_offsets {
+            slices.push(StoredSlice::new(offset, inner_offset, true));
+        }
+        Ok(())
     }
 
-    fn make_string_slices(&self, strings: &[String], _try_compress: bool) -> Result<Vec<StoredSlice>> {
+    fn make_string_slices(&self, strings: &[String], try_compress: bool) -> Result<Vec<StoredSlice>> {
         let mut slices = vec![];
         let pb = ProgressBar::new(strings.len() as u64);
         pb.set_style(ProgressStyle::default_bar()
             .template("{wide_msg:.dim}\n{wide_bar} {pos:>5}/{len}"));
+
+        // compressed strings are written as NUL terminated strings into
+        // blocks which are then brotli compressed as a whole.  The block
+        // is prefixed with the length of the compressed data.
+        let mut block = vec![];
+        let mut inner_offsets = vec![];
         for string in strings.iter() {
-            let offset = self.tell()?;
             pb.set_message(&string);
-            let len = self.write_bytes(string.as_bytes())?;
-            slices.push(StoredSlice::new(offset, len, false));
+            if try_compress {
+                inner_offsets.push(block.len());
+                block.extend_from_slice(string.as_bytes());
+                block.push(0);
+                if block.len() >= STRING_BLOCK_SIZE {
+                    self.write_string_block(&block[..], &inner_offsets[..], &mut slices)?;
+                    block.clear();
+                    inner_offsets.clear();
+                }
+            } else {
+                let offset = self.tell()?;
+                let len = self.write_bytes(string.as_bytes())?;
+                slices.push(StoredSlice::new(offset, len, false));
+            }
             pb.inc(1);
         }
+        if !inner_offsets.is_empty() {
+            self.write_string_block(&block[..], &inner_offsets[..], &mut slices)?;
+        }
         pb.finish_and_clear();
         Ok(slices)
     }
 
+    fn write_spilled_name_index(&self, spilled: &mut SpillResult,
+                                tagged_object_names: &HashMap<Uuid, usize>)
+        -> Result<SectionEntry>
+    {
+        // the spilled index refers to variants in the order they were added
+        let mut variant_uuid_idx = vec![0; self.variant_uuids.len()];
+        for (uuid_idx, indexed_uuid) in self.variant_uuids.iter().enumerate() {
+            variant_uuid_idx[indexed_uuid.idx()] = uuid_idx;
+        }
+
+        let offset = self.tell()?;
+        for_each_stored(&mut spilled.name_index, |name_ref: NameRef| {
+            let uuid_idx = variant_uuid_idx[name_ref.variant_idx as usize];
+            let uuid = self.variant_uuids[uuid_idx].uuid();
+            self.write(&NameIndexItem::new(name_ref.sym_id, uuid_idx,
+                                           tagged_object_names[uuid],
+                                           name_ref.item_idx as usize))?;
+            Ok(())
+        })?;
+        Ok(SectionEntry::new(SectionKind::SymbolNames, offset, self.tell()? - offset))
+    }
+
+    fn write_name_index(&self, tagged_object_names: &HashMap<Uuid, usize>)
+        -> Result<SectionEntry>
+    {
+        let mut items = vec![];
+        for (uuid_idx, indexed_uuid) in self.variant_uuids.iter().enumerate() {
+            let tagged_object_name = tagged_object_names[indexed_uuid.uuid()];
+            for (item_idx, item) in self.variants[indexed_uuid.idx()].iter().enumerate() {
+                if let Some(sym_id) = item.sym_id() {
+                    // placeholders for stripped functions cannot be
+                    // looked up by name.
+                    if self.symbols[sym_id as usize] == STRIPPED_SYMBOL {
+                        continue;
+                    }
+                    items.push(NameIndexItem::new(sym_id, uuid_idx,
+                                                  tagged_object_name, item_idx));
+                }
+            }
+        }
+        // symbols are sorted by name so the ids give the order of the index
+        items.sort_by_key(|item| item.sym_id());
+
+        let pb = ProgressBar::new(items.len() as u64);
+        let offset = self.tell()?;
+        for item in items.iter() {
+            self.write(item)?;
+            pb.inc(1);
+        }
+        pb.finish_and_clear();
+        Ok(SectionEntry::new(SectionKind::SymbolNames, offset, self.tell()? - offset))
+    }
+
     fn write_slices(&self, slices: &[StoredSlice], start: &mut u32, len: &mut u32) -> Result<()> {
         *start = self.tell()? as u32;
         let pb = ProgressBar::new(slices.len() as u64);
@@ -218,60 +796,288 @@ impl<W: Write + Seek> MemDbBuilder<W> {
     pub fn flush(&mut self) -> Result<()> {
         println!("      Found {} symbols", style(self.symbol_count).cyan());
         let mut header = MemDbHeader { ..Default::default() };
-        header.version = 2;
-        header.sdk_info.set_from_sdk_info(&self.info);
+        header.version = MEMDB_VERSION;
+        header.sdk_info.set_from_sdk_info(&self.info)?;
 
         println!("{} Writing metadata", format_step(2, &self.options));
+        // every region written from here is checksummed so that damaged
+        // files can be detected.
+        let mut checksums = vec![];
+        let checksum_start = self.begin_checksum()?;
+
+        // interned names are sorted so that their ids do not depend on the
+        // order in which objects were added.  In low memory mode the symbols
+        // are sorted and assigned their ids now.  Everything else is then
+        // copied over from the spilled files.
+        let src_ids = sort_interned(&mut self.object_names);
+        self.object_names_map.clear();
+        let mut spilled = match self.spill.take() {
+            Some(spill) => Some(spill.finish(&src_ids)?),
+            None => None,
+        };
+        if spilled.is_none() {
+            let sym_ids = sort_interned(&mut self.symbols);
+            self.symbols_map.clear();
+            for variant in self.variants.iter_mut() {
+                for item in variant.iter_mut() {
+                    *item = IndexItem::new(item.addr(), src_ids[item.src_id() as usize],
+                                           item.sym_id().map(|x| sym_ids[x as usize]));
+                }
+            }
+        }
+
         // start by writing out the index of the variants and record the slices.
         let mut slices = vec![];
-        let pb = ProgressBar::new(self.variants.iter().map(|x| x.iter().count()).sum::<usize>() as u64);
-        pb.set_style(ProgressStyle::default_bar()
-            .template("{wide_msg:.dim}\n{wide_bar} {pos:>5}/{len}"));
-        for variant in self.variants.iter() {
-            let offset = self.tell()?;
-            for index_item in variant {
-                pb.set_message(&self.object_uuid_mapping[index_item.src_id() as usize].0);
-                self.write(index_item)?;
-                pb.inc(1);
+        if let Some(ref mut spilled) = spilled {
+            let base = self.tell()?;
+            self.copy_file(&mut spilled.variants)?;
+            for &(offset, len) in spilled.variant_slices.iter() {
+                slices.push(StoredSlice::new(base + offset, len, false));
+            }
+        } else {
+            let pb = ProgressBar::new(self.variants.iter().map(|x| x.iter().count()).sum::<usize>() as u64);
+            pb.set_style(ProgressStyle::default_bar()
+                .template("{wide_msg:.dim}\n{wide_bar} {pos:>5}/{len}"));
+            for variant in self.variants.iter() {
+                let offset = self.tell()?;
+                for index_item in variant {
+                    pb.set_message(&self.object_names[index_item.src_id() as usize]);
+                    self.write(index_item)?;
+                    pb.inc(1);
+                }
+                slices.push(StoredSlice::new(offset, (self.tell()? - offset), false));
             }
-            slices.push(StoredSlice::new(offset, (self.tell()? - offset), false));
+            pb.finish_and_clear();
         }
-        pb.finish_and_clear();
         self.write_slices(&slices[..], &mut header.variants_start,
                           &mut header.variants_count)?;
+        checksums.push(self.end_checksum(checksum_start)?);
 
         // next write out the UUIDs.  Since these are fixed length we do not
         // need to use slices here.
+        let checksum_start = self.begin_checksum()?;
         header.uuids_start = self.tell()? as u32;
         header.uuids_count = self.variant_uuids.len() as u32;
         self.variant_uuids.sort_by_key(|x| x.uuid);
         for indexed_uuid in self.variant_uuids.iter() {
             self.write(indexed_uuid)?;
         }
+        checksums.push(self.end_checksum(checksum_start)?);
 
         // next we write out the name + arch -> uuid index mapping.  We also sort
         // this by uuid so that the index matches up.
+        let checksum_start = self.begin_checksum()?;
         header.tagged_object_names_start = self.tell()? as u32;
-        self.object_uuid_mapping.sort_by_key(|&(_, b)| b);
-        for &(ref tagged_object, _) in self.object_uuid_mapping.iter() {
+        self.object_uuid_mapping.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+        self.object_uuid_mapping.dedup();
+        let mut tagged_object_names = HashMap::new();
+        let mut object_name_index = vec![];
+        for &(ref tagged_object, uuid) in self.object_uuid_mapping.iter() {
+            let offset = self.tell()?;
+            tagged_object_names.entry(uuid).or_insert(offset);
+            let uuid_idx = self.variant_uuids.binary_search_by_key(&uuid, |x| x.uuid).unwrap();
+            object_name_index.push((tagged_object, offset, uuid_idx));
             self.write_bytes(format!("{}\x00", tagged_object).as_bytes())?;
         }
         header.tagged_object_names_end = self.tell()? as u32;
+        checksums.push(self.end_checksum(checksum_start)?);
+
+        // the tagged object names are also indexed by name so that finding
+        // the UUID of an object does not require a scan.
+        let mut sections = vec![];
+        object_name_index.sort_by(|a, b| a.0.cmp(b.0));
+        object_name_index.dedup_by_key(|x| x.0);
+        let offset = self.begin_checksum()?;
+        for &(_, tagged_offset, uuid_idx) in object_name_index.iter() {
+            self.write(&ObjectNameIndexItem::new(tagged_offset, uuid_idx))?;
+        }
+        sections.push(SectionEntry::new(SectionKind::ObjectNames, offset,
+                                        self.tell()? - offset));
+        checksums.push(self.end_checksum(offset)?);
+
+        // every UUID also points to its first name so that finding the
+        // name of a UUID does not require a scan either.
+        let offset = self.begin_checksum()?;
+        for (uuid_idx, indexed_uuid) in self.variant_uuids.iter().enumerate() {
+            let tagged_offset = tagged_object_names[indexed_uuid.uuid()];
+            self.write(&ObjectNameIndexItem::new(tagged_offset, uuid_idx))?;
+        }
+        sections.push(SectionEntry::new(SectionKind::UuidObjectNames, offset,
+                                        self.tell()? - offset));
+        checksums.push(self.end_checksum(offset)?);
 
         // now write out all the object name sources
+        let checksum_start = self.begin_checksum()?;
         let slices = self.make_string_slices(&self.object_names[..], true)?;
         self.write_slices(&slices[..], &mut header.object_names_start,
                           &mut header.object_names_count)?;
+        checksums.push(self.end_checksum(checksum_start)?);
 
         println!("{} Writing symbol strings", format_step(3, &self.options));
 
         // now write out all the symbols
-        let slices = self.make_string_slices(&self.symbols[..], true)?;
-        println!("{} Writing symbol index", format_step(4, &self.options));
-        self.write_slices(&slices[..], &mut header.symbols_start,
-                          &mut header.symbols_count)?;
+        let checksum_start = self.begin_checksum()?;
+        if let Some(ref mut spilled) = spilled {
+            let base = self.tell()?;
+            self.copy_file(&mut spilled.strings)?;
+            println!("{} Writing symbol index", format_step(4, &self.options));
+            header.symbols_start = self.tell()? as u32;
+            for_each_stored(&mut spilled.string_slices, |slice: StoredSlice| {
+                self.write(&StoredSlice::new(base + slice.offset(), slice.len(),
+                                             slice.is_compressed()))?;
+                Ok(())
+            })?;
+            header.symbols_count = spilled.symbol_count as u32;
+        } else {
+            let slices = self.make_string_slices(&self.symbols[..], true)?;
+            println!("{} Writing symbol index", format_step(4, &self.options));
+            self.write_slices(&slices[..], &mut header.symbols_start,
+                              &mut header.symbols_count)?;
+        }
+        checksums.push(self.end_checksum(checksum_start)?);
+
+        let mut step = 5;
+        if self.options.name_index {
+            println!("{} Writing symbol name index", format_step(step, &self.options));
+            let checksum_start = self.begin_checksum()?;
+            sections.push(if let Some(ref mut spilled) = spilled {
+                self.write_spilled_name_index(spilled, &tagged_object_names)?
+            } else {
+                self.write_name_index(&tagged_object_names)?
+            });
+            checksums.push(self.end_checksum(checksum_start)?);
+            step += 1;
+        }
+
+        // merged files record which SDKs each variant came from.  Variants
+        // that were added from objects come from the SDK of the file itself.
+        if !self.uuid_sdks.is_empty() {
+            let recorded: HashSet<Uuid> = self.uuid_sdks.iter().map(|x| x.0).collect();
+            let missing: Vec<Uuid> = self.variant_uuids.iter()
+                .map(|x| x.uuid)
+                .filter(|uuid| !recorded.contains(uuid))
+                .collect();
+            if !missing.is_empty() {
+                let info = self.info.clone();
+                let sdk_idx = self.add_source_sdk(&info);
+                for uuid in missing {
+                    self.uuid_sdks.push((uuid, sdk_idx));
+                }
+            }
+
+            let offset = self.begin_checksum()?;
+            for info in self.source_sdks.iter() {
+                self.write_bytes(format!("{}\x00", info.sdk_id()).as_bytes())?;
+            }
+            sections.push(SectionEntry::new(SectionKind::SourceSdks, offset,
+                                            self.tell()? - offset));
+            checksums.push(self.end_checksum(offset)?);
 
-        println!("{} Writing headers", format_step(5, &self.options));
+            let offset = self.begin_checksum()?;
+            self.uuid_sdks.sort();
+            self.uuid_sdks.dedup();
+            for &(uuid, sdk_idx) in self.uuid_sdks.iter() {
+                let uuid_idx = self.variant_uuids.binary_search_by_key(&uuid, |x| x.uuid).unwrap();
+                self.write(&UuidSdkItem::new(uuid_idx, sdk_idx as usize))?;
+            }
+            sections.push(SectionEntry::new(SectionKind::UuidSdks, offset,
+                                            self.tell()? - offset));
+            checksums.push(self.end_checksum(offset)?);
+        }
+
+        // line tables are only written if any object had debug info.  Rows
+        // refer to the file names by their offset in the line files.
+        if self.line_tables.has_tables() {
+            let file_ids = sort_interned(&mut self.line_files);
+            self.line_files_map.clear();
+            let offset = self.begin_checksum()?;
+            let mut file_offsets = vec![];
+            for file in self.line_files.iter() {
+                file_offsets.push((self.tell()? - offset) as u32);
+                self.write_bytes(format!("{}\x00", file).as_bytes())?;
+            }
+            sections.push(SectionEntry::new(SectionKind::LineFiles, offset,
+                                            self.tell()? - offset));
+            checksums.push(self.end_checksum(offset)?);
+
+            let checksum_start = self.begin_checksum()?;
+            let mut line_slices = vec![];
+            let line_tables = mem::replace(&mut self.line_tables, VariantTables::new());
+            line_tables.for_each(|line_table| {
+                let offset = self.tell()?;
+                for item in line_table.iter() {
+                    let file = file_offsets[file_ids[item.file() as usize] as usize];
+                    self.write(&LineItem::new(item.addr(), file, item.line()))?;
+                }
+                line_slices.push(StoredSlice::new(offset, self.tell()? - offset, false));
+                Ok(())
+            })?;
+            checksums.push(self.end_checksum(checksum_start)?);
+
+            let offset = self.begin_checksum()?;
+            for slice in line_slices.iter() {
+                self.write(slice)?;
+            }
+            sections.push(SectionEntry::new(SectionKind::LineTables, offset,
+                                            self.tell()? - offset));
+            checksums.push(self.end_checksum(offset)?);
+        }
+
+        // code sections are only known for objects converted by versions
+        // that record them.
+        if self.code_sections.has_tables() {
+            let checksum_start = self.begin_checksum()?;
+            let mut section_slices = vec![];
+            let code_sections = mem::replace(&mut self.code_sections, VariantTables::new());
+            code_sections.for_each(|items| {
+                let offset = self.tell()?;
+                for item in items.iter() {
+                    self.write(item)?;
+                }
+                section_slices.push(StoredSlice::new(offset, self.tell()? - offset, false));
+                Ok(())
+            })?;
+            checksums.push(self.end_checksum(checksum_start)?);
+
+            let offset = self.begin_checksum()?;
+            for slice in section_slices.iter() {
+                self.write(slice)?;
+            }
+            sections.push(SectionEntry::new(SectionKind::CodeSections, offset,
+                                            self.tell()? - offset));
+            checksums.push(self.end_checksum(offset)?);
+        }
+
+        self.metadata.insert("converter_version".into(), VERSION.into());
+        // the creation time is only recorded if it is given with the
+        // `SOURCE_DATE_EPOCH` convention so that conversions are reproducible.
+        if let Some(created) = env::var("SOURCE_DATE_EPOCH").ok()
+                .and_then(|x| x.parse().ok()) {
+            self.metadata.insert("created".into(), UTC.timestamp(created, 0).to_rfc3339());
+        }
+        let offset = self.begin_checksum()?;
+        for (key, value) in self.metadata.iter() {
+            self.write_bytes(format!("{}\x00{}\x00", key, value).as_bytes())?;
+        }
+        sections.push(SectionEntry::new(SectionKind::Metadata, offset,
+                                        self.tell()? - offset));
+        checksums.push(self.end_checksum(offset)?);
+
+        let offset = self.tell()?;
+        for checksum in checksums.iter() {
+            self.write(checksum)?;
+        }
+        sections.push(SectionEntry::new(SectionKind::Checksums, offset,
+                                        self.tell()? - offset));
+
+        header.sections_start = self.tell()? as u32;
+        header.sections_count = sections.len() as u32;
+        for section in sections.iter() {
+            self.write(section)?;
+        }
+
+        println!("{} Writing headers", format_step(step, &self.options));
+        step += 1;
 
         let file_size = self.tell()?;
 
@@ -284,7 +1090,7 @@ impl<W: Write + Seek> MemDbBuilder<W> {
 
         // compress if necessary
         if self.options.compress {
-            println!("{} Compressing", format_step(6, &self.options));
+            println!("{} Compressing", format_step(step, &self.options));
             let pb = ProgressBar::new(file_size as u64);
             pb.set_style(ProgressStyle::default_bar()
                 .template("{wide_bar} {bytes}/{total_bytes}"));
@@ -309,22 +1115,197 @@ impl<W: Write + Seek> MemDbBuilder<W> {
     }
 }
 
+/// Parses objects on a pool of worker threads
+///
+/// Files are read from the source on the calling thread and parsed by the
+/// workers.  The results are added to the builder in the order of the
+/// source so the output does not depend on the number of threads.  A
+/// parser that panics fails the conversion instead of losing the object.
+fn write_objects_parallel<W: Write + Seek>(builder: &mut MemDbBuilder<W>,
+                                           objects: &mut Objects, threads: usize,
+                                           pb: &ProgressBar)
+    -> Result<()>
+{
+    let (job_tx, job_rx) = mpsc::channel::<(usize, String, RawObject)>();
+    let (result_tx, result_rx) = mpsc::channel();
+    let job_rx = Arc::new(Mutex::new(job_rx));
+    let mut workers = vec![];
+    for _ in 0..threads {
+        let job_rx = job_rx.clone();
+        let result_tx = result_tx.clone();
+        workers.push(thread::spawn(move || {
+            loop {
+                let job = job_rx.lock().unwrap().recv();
+                let (seq, filename, raw) = match job {
+                    Ok(job) => job,
+                    Err(_) => { break; }
+                };
+                let rv = panic::catch_unwind(AssertUnwindSafe(|| {
+                    extract_object(&filename, raw)
+                })).unwrap_or_else(|_| {
+                    Err(Error::from(format!("object parser panicked on {}", filename)))
+                });
+                if result_tx.send((seq, rv)).is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+    drop(result_tx);
+
+    // only read a limited number of files ahead of the builder so that
+    // the results waiting to be added stay small.
+    let max_pending = threads * 4;
+    let mut jobs = BTreeMap::new();
+    let mut results = BTreeMap::new();
+    let mut next_seq = 0;
+    let mut write_seq = 0;
+    let mut exhausted = false;
+    loop {
+        while !exhausted && next_seq - write_seq < max_pending {
+            match objects.next_raw() {
+                Some(raw_res) => {
+                    let (offset, filename, raw) = raw_res?;
+                    jobs.insert(next_seq, (offset, filename.clone()));
+                    job_tx.send((next_seq, filename, raw)).map_err(|_| {
+                        Error::from("object worker failed")
+                    })?;
+                    next_seq += 1;
+                }
+                None => {
+                    exhausted = true;
+                }
+            }
+        }
+        if write_seq == next_seq {
+            break;
+        }
+
+        let (seq, result) = result_rx.recv().map_err(|_| {
+            Error::from("object worker failed")
+        })?;
+        results.insert(seq, result);
+        while let Some(result) = results.remove(&write_seq) {
+            let (offset, filename) = jobs.remove(&write_seq).unwrap();
+            pb.set_message(&filename);
+            builder.write_extracted_object(result?)?;
+            pb.inc(offset as u64);
+            write_seq += 1;
+        }
+    }
+
+    drop(job_tx);
+    for worker in workers {
+        worker.join().ok();
+    }
+    Ok(())
+}
+
 /// Dumps objects into a writer
 pub fn dump_memdb<W: Write + Seek>(writer: W, info: &SdkInfo,
-                                   opts: DumpOptions, objects: Objects)
+                                   opts: DumpOptions, mut objects: Objects)
     -> Result<()>
 {
     println!("{} Processing {} files", format_step(1, &opts),
              style(objects.file_count()).cyan());
+    let threads = opts.threads;
     let mut builder = MemDbBuilder::new(writer, info, opts)?;
     let pb = ProgressBar::new(objects.file_count() as u64);
     pb.set_style(ProgressStyle::default_bar()
         .template("{wide_msg:.dim}\n{wide_bar} {pos:>5}/{len}"));
-    for obj_res in objects {
-        let (offset, filename, obj) = obj_res?;
-        pb.set_message(&filename);
-        builder.write_object(&obj, Some(&filename))?;
-        pb.inc(offset as u64);
+    if threads > 1 {
+        write_objects_parallel(&mut builder, &mut objects, threads, &pb)?;
+    } else {
+        for obj_res in objects {
+            let (offset, filename, obj) = obj_res?;
+            pb.set_message(&filename);
+            builder.write_object(&obj, Some(&filename))?;
+            pb.inc(offset as u64);
+        }
+    }
+    pb.finish_and_clear();
+    builder.flush()?;
+    Ok(())
+}
+
+/// Merges several memdb files into a writer
+///
+/// Symbols and object names are shared between the inputs and each
+/// variant is only written once.  The SDKs the variants came from are
+/// recorded in the output.
+pub fn merge_memdbs<W: Write + Seek>(writer: W, info: &SdkInfo,
+                                     opts: DumpOptions, memdbs: &[MemDb])
+    -> Result<()>
+{
+    println!("{} Reading {} memdbs", format_step(1, &opts),
+             style(memdbs.len()).cyan());
+    let mut builder = MemDbBuilder::new(writer, info, opts)?;
+    builder.record_sources = true;
+    let replaced = HashSet::new();
+    let pb = ProgressBar::new(memdbs.len() as u64);
+    pb.set_style(ProgressStyle::default_bar()
+        .template("{wide_msg:.dim}\n{wide_bar} {pos:>5}/{len}"));
+    for memdb in memdbs {
+        pb.set_message(&memdb.info().to_string());
+        builder.write_memdb(memdb, &replaced)?;
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+    builder.flush()?;
+    Ok(())
+}
+
+/// Writes a copy of a memdb file with objects added or replaced
+///
+/// Variants of the given objects replace the variants with the same
+/// `name:arch` tagged name in the memdb.  All other variants are copied.
+/// The objects are given together with the name to use for variants that
+/// do not have one.
+pub fn update_memdb<W: Write + Seek>(writer: W, memdb: &MemDb, opts: DumpOptions,
+                                     objects: &[(String, Object)])
+    -> Result<()>
+{
+    println!("{} Processing {} files", format_step(1, &opts),
+             style(objects.len()).cyan());
+    let mut builder = MemDbBuilder::new(writer, memdb.info(), opts)?;
+    builder.record_sources = memdb.is_merged()?;
+    for (key, value) in memdb.metadata()? {
+        builder.metadata.entry(key).or_insert(value);
+    }
+
+    let mut replaced = HashSet::new();
+    for &(ref filename, ref obj) in objects.iter() {
+        for variant in obj.variants() {
+            let src = variant.name().unwrap_or(filename);
+            replaced.insert(format!("{}:{}", src, variant.arch()));
+        }
+        builder.write_object(obj, Some(filename))?;
+    }
+
+    builder.write_memdb(memdb, &replaced)?;
+    builder.flush()?;
+    Ok(())
+}
+
+/// Writes a memdb file from Breakpad symbol files
+///
+/// Every file is read when it is added so only one module is kept in
+/// memory at a time.  Modules are identified by their debug id.
+pub fn import_breakpad<W: Write + Seek, P: AsRef<Path>>(writer: W, info: &SdkInfo,
+                                                        opts: DumpOptions, paths: &[P])
+    -> Result<()>
+{
+    println!("{} Processing {} files", format_step(1, &opts),
+             style(paths.len()).cyan());
+    let mut builder = MemDbBuilder::new(writer, info, opts)?;
+    let pb = ProgressBar::new(paths.len() as u64);
+    pb.set_style(ProgressStyle::default_bar()
+        .template("{wide_msg:.dim}\n{wide_bar} {pos:>5}/{len}"));
+    for path in paths {
+        pb.set_message(&path.as_ref().display().to_string());
+        let module = BreakpadModule::from_path(path)?;
+        builder.write_breakpad_module(&module)?;
+        pb.inc(1);
     }
     pb.finish_and_clear();
     builder.flush()?;
@@ -1,27 +1,38 @@
 //! Provides SDK Information
 use std::fs;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::cmp::Ordering;
 use std::io::{Read, Write, Seek};
 use std::path::{Path, PathBuf};
 
+use md5;
 use zip;
+use num_cpus;
 use walkdir;
 use regex::Regex;
 use mach_object::Error as MachError;
 
 use super::{Result, Error, ErrorKind};
 use super::dsym::Object;
+use super::shared_cache::{SharedCache, SharedCacheImage, is_shared_cache};
 use super::memdb::write::dump_memdb;
 
 
 enum ObjectIterSource {
     Zip {
         archive: zip::ZipArchive<fs::File>,
+        order: Vec<usize>,
         idx: usize,
     },
     Dir {
         path: PathBuf,
         dir_iter: walkdir::Iter,
+    },
+    SharedCache {
+        path: PathBuf,
+        images: Vec<SharedCacheImage>,
+        idx: usize,
     }
 }
 
@@ -29,37 +40,74 @@ enum ObjectIterSource {
 #[derive(Clone)]
 pub struct DumpOptions {
     pub compress: bool,
+    pub name_index: bool,
+    pub low_memory: bool,
+    pub metadata: Vec<(String, String)>,
+    pub threads: usize,
 }
 
 impl Default for DumpOptions {
     fn default() -> DumpOptions {
         DumpOptions {
             compress: false,
+            name_index: true,
+            low_memory: false,
+            metadata: vec![],
+            threads: num_cpus::get(),
         }
     }
 }
 
+/// The folders Xcode keeps device support files in and their SDK names
+pub const DEVICE_SUPPORT_FOLDERS: &'static [(&'static str, &'static str)] = &[
+    ("iOS DeviceSupport", "iOS"),
+    ("tvOS DeviceSupport", "tvOS"),
+    ("watchOS DeviceSupport", "watchOS"),
+    ("macOS DeviceSupport", "macOS"),
+    ("visionOS DeviceSupport", "visionOS"),
+    ("xrOS DeviceSupport", "visionOS"),
+];
+
 fn get_sdk_name_from_folder(folder: &str) -> Option<&'static str> {
-    match folder {
-        "iOS DeviceSupport" => Some("iOS"),
-        "tvOS DeviceSupport" => Some("tvOS"),
-        _ => None,
-    }
+    DEVICE_SUPPORT_FOLDERS.iter()
+        .find(|&&(folder_name, _)| folder_name == folder)
+        .map(|&(_, name)| name)
 }
 
 /// Information of the SDK
-#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord, Deserialize, Serialize, Hash)]
+///
+/// The device model and architecture are only known for SDKs read from
+/// device support folders that are named after them.  They are not part
+/// of the SDK id and are ignored when comparing and hashing SDK infos, so
+/// folders that only differ in them convert to the same memdb file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SdkInfo {
     name: String,
     version_major: u32,
     version_minor: u32,
     version_patchlevel: u32,
     build: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    model: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    arch: Option<String>,
 }
 
 /// Iterates over all objects in an SDK
+///
+/// The images of dyld shared caches in SDK folders are yielded as if they
+/// were standalone objects.
 pub struct Objects {
     source: ObjectIterSource,
+    shared_cache: Option<ObjectIterSource>,
+}
+
+/// An object file of an SDK that was not parsed yet
+pub enum RawObject {
+    Buf(Vec<u8>),
+    Path(PathBuf),
+    /// An image at an offset in a dyld shared cache
+    SharedCacheImage(PathBuf, u64),
 }
 
 /// Helper struct to process an SDK from the FS or a ZIP
@@ -82,6 +130,32 @@ impl<'a> fmt::Display for Version {
     }
 }
 
+impl PartialEq for SdkInfo {
+    fn eq(&self, other: &SdkInfo) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SdkInfo {}
+
+impl PartialOrd for SdkInfo {
+    fn partial_cmp(&self, other: &SdkInfo) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SdkInfo {
+    fn cmp(&self, other: &SdkInfo) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Hash for SdkInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
 impl fmt::Display for SdkInfo {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{} {} ({})", self.name, self.version(), self.build().unwrap_or("UNKNOWN"))
@@ -101,6 +175,8 @@ impl SdkInfo {
             version_minor: version_minor,
             version_patchlevel: version_patchlevel,
             build: build.map(|x| x.to_string()),
+            model: None,
+            arch: None,
         }
     }
 
@@ -116,11 +192,16 @@ impl SdkInfo {
     /// If the parse cannot be parsed for an SDK info `None` is returned.
     pub fn from_path<P: AsRef<Path>>(path: P) -> Option<SdkInfo> {
         lazy_static! {
+            // newer Xcode versions add the device model in front
+            // (`iPhone15,2 17.1 (21B80)`) or the architecture at the end
+            // (`17.1 (21B80) arm64e`).
             static ref SDK_FILENAME_RE: Regex = Regex::new(r"(?x)
                 ^
+                    (?:([a-zA-Z]+[0-9]+,[0-9]+)\s+)?
                     (\d+)\.(\d+)(?:\.(\d+))?
                     \s+
                     \(([a-zA-Z0-9]+)\)
+                    (?:\s+([a-zA-Z0-9_]+))?
                     (?:\.zip)?
                 $
             ").unwrap();
@@ -148,16 +229,19 @@ impl SdkInfo {
 
         let folder = try_opt!(p.parent().and_then(|x| x.file_name()).and_then(|x| x.to_str()));
         let caps = try_opt!(SDK_FILENAME_RE.captures(filename));
-        Some(SdkInfo::new(
+        let mut rv = SdkInfo::new(
             try_opt!(get_sdk_name_from_folder(folder)),
-            try_opt!(caps.get(1).unwrap().as_str().parse().ok()),
             try_opt!(caps.get(2).unwrap().as_str().parse().ok()),
-            try_opt!(caps.get(3).map(|x| x.as_str()).unwrap_or("0").parse().ok()),
-            caps.get(4).map(|x| x.as_str()),
-        ))
+            try_opt!(caps.get(3).unwrap().as_str().parse().ok()),
+            try_opt!(caps.get(4).map(|x| x.as_str()).unwrap_or("0").parse().ok()),
+            caps.get(5).map(|x| x.as_str()),
+        );
+        rv.model = caps.get(1).map(|x| x.as_str().to_string());
+        rv.arch = caps.get(6).map(|x| x.as_str().to_string());
+        Some(rv)
     }
 
-    /// The SDK name (iOS, tvOS etc.)
+    /// The SDK name (iOS, tvOS, watchOS, macOS, visionOS etc.)
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -187,6 +271,22 @@ impl SdkInfo {
         self.build.as_ref().map(|x| &**x)
     }
 
+    /// The device model the SDK was copied from (eg: `iPhone15,2`)
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_ref().map(|x| &**x)
+    }
+
+    /// The architecture hint of the SDK (eg: `arm64e`)
+    pub fn arch(&self) -> Option<&str> {
+        self.arch.as_ref().map(|x| &**x)
+    }
+
+    /// The fields that make up the SDK id
+    fn key(&self) -> (&str, u32, u32, u32, Option<&str>) {
+        (&self.name, self.version_major, self.version_minor, self.version_patchlevel,
+         self.build())
+    }
+
     fn make_id(&self, suffix: &str) -> String {
         if let Some(ref build) = self.build {
             format!("{}_{}.{}.{}_{}{}",
@@ -234,23 +334,144 @@ impl SdkInfo {
     }
 }
 
+impl RawObject {
+    /// Parses the object
+    ///
+    /// Files that are neither Mach-O nor ELF objects are skipped and `None` is
+    /// returned for them.  This includes ELF files we cannot read (eg: big
+    /// endian or truncated ones).
+    pub fn parse(self) -> Result<Option<Object<'static>>> {
+        let rv = match self {
+            RawObject::Buf(buf) => Object::from_vec(buf),
+            RawObject::Path(path) => Object::from_path(path),
+            RawObject::SharedCacheImage(path, offset) => Object::from_path_at(path, offset),
+        };
+        match rv {
+            Ok(obj) => Ok(Some(obj)),
+            Err(err) => {
+                match *err.kind() {
+                    ErrorKind::MachO(MachError::LoadError(_)) => Ok(None),
+                    ErrorKind::BadElf => {
+                        warn!("skipping unreadable ELF file: {}", err);
+                        Ok(None)
+                    }
+                    _ => Err(err),
+                }
+            }
+        }
+    }
+}
+
+/// Checks if a file is a dyld shared cache.
+///
+/// Only files named like shared caches are checked for the magic.
+fn is_shared_cache_file(path: &Path) -> Result<bool> {
+    let is_cache_name = path.file_name()
+        .and_then(|x| x.to_str())
+        .map_or(false, |x| x.starts_with("dyld_shared_cache_"));
+    if !is_cache_name {
+        return Ok(false);
+    }
+    let mut magic = vec![];
+    fs::File::open(path)?.take(16).read_to_end(&mut magic)?;
+    Ok(is_shared_cache(&magic))
+}
+
 impl ObjectIterSource {
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<ObjectIterSource> {
         let md = fs::metadata(path.as_ref())?;
-        if md.is_file() {
+        if md.is_file() && is_shared_cache_file(path.as_ref())? {
+            ObjectIterSource::from_shared_cache(path.as_ref())
+        } else if md.is_file() {
             let f = fs::File::open(path.as_ref())?;
-            let zip = zip::ZipArchive::new(f)?;
+            let mut zip = zip::ZipArchive::new(f)?;
+            // files are visited by name so that the output does not depend
+            // on how the archive was created.
+            let mut names = vec![];
+            for idx in 0..zip.len() {
+                names.push((zip.by_index(idx)?.name().to_string(), idx));
+            }
+            names.sort();
             Ok(ObjectIterSource::Zip {
                 archive: zip,
+                order: names.into_iter().map(|x| x.1).collect(),
                 idx: 0,
             })
         } else {
             Ok(ObjectIterSource::Dir {
                 path: path.as_ref().to_path_buf(),
-                dir_iter: walkdir::WalkDir::new(path.as_ref()).into_iter(),
+                dir_iter: walkdir::WalkDir::new(path.as_ref())
+                    .sort_by(|a, b| a.cmp(b))
+                    .into_iter(),
             })
         }
     }
+
+    /// Creates a source for the images of a dyld shared cache
+    pub fn from_shared_cache(path: &Path) -> Result<ObjectIterSource> {
+        let cache = SharedCache::from_path(path)?;
+        if cache.skipped_images() > 0 {
+            warn!("skipping {} of {} images in {} which are in other files of a split cache",
+                  cache.skipped_images(), cache.skipped_images() + cache.images().len(),
+                  path.display());
+        }
+        Ok(ObjectIterSource::SharedCache {
+            path: path.to_path_buf(),
+            images: cache.images().to_vec(),
+            idx: 0,
+        })
+    }
+
+    /// Returns the next file in the source without parsing it
+    ///
+    /// Along with the file this returns the number of entries in the
+    /// source that were consumed and the name of the object.
+    fn next_raw(&mut self) -> Option<Result<(usize, String, RawObject)>> {
+        let mut offset = 0;
+        loop {
+            offset += 1;
+            match *self {
+                ObjectIterSource::Zip { ref mut archive, ref order, ref mut idx } => {
+                    if *idx >= order.len() {
+                        break;
+                    }
+                    let mut f = iter_try!(archive.by_index(order[*idx]));
+                    *idx += 1;
+                    let mut buf : Vec<u8> = vec![];
+                    if iter_try!(f.read_to_end(&mut buf)) > 0 {
+                        let name = format!("/{}", strip_archive_file_prefix(f.name()));
+                        return Some(Ok((offset, name, RawObject::Buf(buf))));
+                    }
+                }
+                ObjectIterSource::Dir { ref path, ref mut dir_iter } => {
+                    if let Some(dent_res) = dir_iter.next() {
+                        let dent = iter_try!(dent_res);
+                        let md = iter_try!(dent.metadata());
+                        if md.is_file() && md.len() > 0 {
+                            let prefix = path.join("Symbols");
+                            let rp = dent.path().strip_prefix(&prefix)
+                                .unwrap_or(dent.path());
+                            let name = format!("/{}", rp.display());
+                            return Some(Ok((offset, name,
+                                            RawObject::Path(dent.path().to_path_buf()))));
+                        }
+                    } else {
+                        break;
+                    }
+                }
+                ObjectIterSource::SharedCache { ref path, ref images, ref mut idx } => {
+                    if *idx >= images.len() {
+                        break;
+                    }
+                    let image = &images[*idx];
+                    *idx += 1;
+                    return Some(Ok((offset, image.path().to_string(),
+                                    RawObject::SharedCacheImage(path.clone(), image.offset()))));
+                }
+            }
+        }
+        None
+    }
 }
 
 fn strip_archive_file_prefix(path: &str) -> &str {
@@ -286,6 +507,37 @@ impl Objects {
             ObjectIterSource::Dir { ref path, .. } => {
                 walkdir::WalkDir::new(path).into_iter().count()
             }
+            ObjectIterSource::SharedCache { ref images, .. } => {
+                images.len()
+            }
+        }
+    }
+
+    /// Returns the next file in the source without parsing it
+    ///
+    /// Along with the file this returns the number of entries in the
+    /// source that were consumed and the name of the object.  Shared
+    /// caches count as a single entry.
+    pub fn next_raw(&mut self) -> Option<Result<(usize, String, RawObject)>> {
+        let mut offset = 0;
+        loop {
+            if let Some(ref mut shared_cache) = self.shared_cache {
+                if let Some(rv) = shared_cache.next_raw() {
+                    let (_, name, raw) = iter_try!(rv);
+                    return Some(Ok((offset, name, raw)));
+                }
+            }
+            self.shared_cache = None;
+
+            let (raw_offset, name, raw) = iter_try!(try_opt!(self.source.next_raw()));
+            offset += raw_offset;
+            if let RawObject::Path(ref path) = raw {
+                if iter_try!(is_shared_cache_file(path)) {
+                    self.shared_cache = Some(iter_try!(ObjectIterSource::from_shared_cache(path)));
+                    continue;
+                }
+            }
+            return Some(Ok((offset, name, raw)));
         }
     }
 }
@@ -295,56 +547,11 @@ impl<'a> Iterator for Objects {
 
     fn next(&mut self) -> Option<Result<(usize, String, Object<'static>)>> {
         let mut offset = 0;
-
-        macro_rules! try_return_obj {
-            ($expr:expr, $name:expr) => {
-                match $expr {
-                    Ok(rv) => {
-                        return Some(Ok((offset, format!("/{}", $name), rv)));
-                    }
-                    Err(err) => {
-                        if let &ErrorKind::MachO(ref mach_err) = err.kind() {
-                            if let &MachError::LoadError(_) = mach_err {
-                                continue;
-                            }
-                        }
-                        return Some(Err(err.into()));
-                    }
-                }
-            }
-        }
-
-        loop {
-            offset += 1;
-            match self.source {
-                ObjectIterSource::Zip { ref mut archive, ref mut idx } => {
-                    if *idx >= archive.len() {
-                        break;
-                    }
-                    let mut f = iter_try!(archive.by_index(*idx));
-                    *idx += 1;
-                    let mut buf : Vec<u8> = vec![];
-                    if iter_try!(f.read_to_end(&mut buf)) > 0 {
-                        try_return_obj!(Object::from_vec(buf),
-                            strip_archive_file_prefix(f.name()));
-                    }
-                }
-                ObjectIterSource::Dir { ref path, ref mut dir_iter } => {
-                    if let Some(dent_res) = dir_iter.next() {
-                        let dent = iter_try!(dent_res);
-                        let md = iter_try!(dent.metadata());
-                        if md.is_file() && md.len() > 0 {
-                            let prefix = path.join("Symbols");
-                            let rp = dent.path().strip_prefix(&prefix)
-                                .unwrap_or(dent.path());
-                            try_return_obj!(
-                                Object::from_path(dent.path()),
-                                rp.display());
-                        }
-                    } else {
-                        break;
-                    }
-                }
+        while let Some(raw_res) = self.next_raw() {
+            let (raw_offset, filename, raw) = iter_try!(raw_res);
+            offset += raw_offset;
+            if let Some(obj) = iter_try!(raw.parse()) {
+                return Some(Ok((offset, filename, obj)));
             }
         }
         None
@@ -373,13 +580,45 @@ impl Sdk {
     pub fn objects<'a>(&'a self) -> Result<Objects> {
         Ok(Objects {
             source: ObjectIterSource::from_path(&self.path)?,
+            shared_cache: None,
         })
     }
 
+    /// Returns the MD5 hash of the zip file the SDK is read from
+    pub fn source_md5(&self) -> Result<Option<String>> {
+        if !fs::metadata(&self.path)?.is_file() {
+            return Ok(None);
+        }
+        let mut f = fs::File::open(&self.path)?;
+        let mut ctx = md5::Context::new();
+        let mut buf = [0u8; 65536];
+        loop {
+            let n = f.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            ctx.consume(&buf[..n]);
+        }
+        Ok(Some(ctx.compute().0.iter().map(|x| format!("{:02x}", x)).collect()))
+    }
+
     /// Writes a memdb file for the SDK
     ///
-    /// This can then be later read with the `MemDb` type.
+    /// This can then be later read with the `MemDb` type.  The hash of
+    /// the source zip as well as the device model and architecture from
+    /// the folder name are recorded in the metadata of the file.
     pub fn dump_memdb<W: Write + Seek>(&self, writer: W, opts: DumpOptions) -> Result<()> {
+        let mut opts = opts;
+        if let Some(digest) = self.source_md5()? {
+            opts.metadata.push(("source_md5".into(), digest));
+        }
+        // these go first so that explicitly given metadata wins
+        if let Some(model) = self.info.model() {
+            opts.metadata.insert(0, ("model".into(), model.to_string()));
+        }
+        if let Some(arch) = self.info.arch() {
+            opts.metadata.insert(0, ("arch".into(), arch.to_string()));
+        }
         dump_memdb(writer, self.info(), opts, self.objects()?)?;
         Ok(())
     }
@@ -0,0 +1,181 @@
+//! dyld Shared Cache Support
+//!
+//! Newer SDKs ship the system libraries combined into a single
+//! `dyld_shared_cache_<arch>` file.  This module lists the images in such
+//! a cache so that they can be processed like standalone Mach-O objects.
+//! Caches that are split over several files are not supported, images that
+//! live in the other files of such a cache are skipped and counted.
+use std::path::Path;
+use std::str::from_utf8;
+
+use memmap;
+use uuid::Uuid;
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::{Result, ErrorKind};
+
+const LC_UUID: u32 = 0x1b;
+const MH_MAGIC: u32 = 0xfeedface;
+const MH_MAGIC_64: u32 = 0xfeedfacf;
+
+/// An image in a dyld shared cache
+#[derive(Debug, Clone)]
+pub struct SharedCacheImage {
+    path: String,
+    uuid: Option<Uuid>,
+    addr: u64,
+    offset: u64,
+}
+
+/// The images of a dyld shared cache
+pub struct SharedCache {
+    images: Vec<SharedCacheImage>,
+    skipped_images: usize,
+}
+
+/// Checks if the data starts like a dyld shared cache.
+pub fn is_shared_cache(data: &[u8]) -> bool {
+    data.starts_with(b"dyld_v1 ")
+}
+
+fn get_data(data: &[u8], offset: u64, len: usize) -> Result<&[u8]> {
+    let offset = offset as usize;
+    match offset.checked_add(len) {
+        Some(end) if end <= data.len() => Ok(&data[offset..end]),
+        _ => Err(ErrorKind::BadSharedCache.into()),
+    }
+}
+
+fn read_u32(data: &[u8], offset: u64) -> Result<u32> {
+    Ok(LittleEndian::read_u32(get_data(data, offset, 4)?))
+}
+
+fn read_u64(data: &[u8], offset: u64) -> Result<u64> {
+    Ok(read_u32(data, offset)? as u64 | (read_u32(data, offset + 4)? as u64) << 32)
+}
+
+fn read_cstr(data: &[u8], offset: u64) -> Result<&str> {
+    let rest = data.get(offset as usize..).ok_or_else(|| ErrorKind::BadSharedCache)?;
+    let len = rest.iter().position(|&x| x == 0).ok_or_else(|| ErrorKind::BadSharedCache)?;
+    Ok(from_utf8(&rest[..len])?)
+}
+
+/// Reads the UUID from the load commands of the image at `offset`.
+fn read_image_uuid(data: &[u8], offset: u64) -> Result<Option<Uuid>> {
+    let header_size = match read_u32(data, offset)? {
+        MH_MAGIC => 28,
+        MH_MAGIC_64 => 32,
+        _ => { return Err(ErrorKind::BadSharedCache.into()); }
+    };
+    let ncmds = read_u32(data, offset + 16)?;
+    let mut cmd_offset = offset + header_size;
+    for _ in 0..ncmds {
+        let cmd = read_u32(data, cmd_offset)?;
+        let cmdsize = read_u32(data, cmd_offset + 4)? as u64;
+        if cmd == LC_UUID {
+            return Ok(Uuid::from_bytes(get_data(data, cmd_offset + 8, 16)?).ok());
+        }
+        if cmdsize < 8 {
+            return Err(ErrorKind::BadSharedCache.into());
+        }
+        cmd_offset += cmdsize;
+    }
+    Ok(None)
+}
+
+impl SharedCache {
+    /// Parses the image list of a shared cache.
+    pub fn parse(data: &[u8]) -> Result<SharedCache> {
+        if !is_shared_cache(data) {
+            return Err(ErrorKind::BadSharedCache.into());
+        }
+
+        // mappings are `(address, size, file offset)` followed by the
+        // protection flags.
+        let mapping_offset = read_u32(data, 16)? as u64;
+        let mapping_count = read_u32(data, 20)? as u64;
+        let mut mappings = vec![];
+        for idx in 0..mapping_count {
+            let offset = mapping_offset + idx * 32;
+            mappings.push((read_u64(data, offset)?, read_u64(data, offset + 8)?,
+                           read_u64(data, offset + 16)?));
+        }
+
+        // newer caches moved the image list further back in the header.
+        // The mapping offset tells us how large the header is.
+        let (mut images_offset, mut images_count) = (read_u32(data, 24)?, read_u32(data, 28)?);
+        if images_offset == 0 && mapping_offset >= 0x1c8 {
+            images_offset = read_u32(data, 0x1c0)?;
+            images_count = read_u32(data, 0x1c4)?;
+        }
+
+        let mut images = vec![];
+        let mut skipped_images = 0;
+        for idx in 0..images_count as u64 {
+            let offset = images_offset as u64 + idx * 32;
+            let addr = read_u64(data, offset)?;
+            let path = read_cstr(data, read_u32(data, offset + 24)? as u64)?;
+
+            // images whose header is not mapped from this file live in
+            // another part of a split cache.
+            let file_offset = match mappings.iter().find(|m| addr >= m.0 && addr - m.0 < m.1) {
+                Some(&(map_addr, _, map_offset)) => map_offset + (addr - map_addr),
+                None => {
+                    skipped_images += 1;
+                    continue;
+                }
+            };
+
+            images.push(SharedCacheImage {
+                path: path.to_string(),
+                uuid: read_image_uuid(data, file_offset)?,
+                addr: addr,
+                offset: file_offset,
+            });
+        }
+
+        Ok(SharedCache {
+            images: images,
+            skipped_images: skipped_images,
+        })
+    }
+
+    /// Parses the image list of a shared cache from a file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<SharedCache> {
+        let mmap = memmap::Mmap::open_path(path, memmap::Protection::Read)?;
+        SharedCache::parse(unsafe { mmap.as_slice() })
+    }
+
+    /// Returns the images in the cache
+    pub fn images(&self) -> &[SharedCacheImage] {
+        &self.images
+    }
+
+    /// Returns the number of images that are in other files of a split
+    /// cache and are therefore missing from `images`.
+    pub fn skipped_images(&self) -> usize {
+        self.skipped_images
+    }
+}
+
+impl SharedCacheImage {
+    /// The install name of the image
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The UUID of the image if it has one
+    pub fn uuid(&self) -> Option<Uuid> {
+        self.uuid
+    }
+
+    /// The address the image is loaded at
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// The offset of the mach header in the cache file
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
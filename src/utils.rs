@@ -301,22 +301,32 @@ pub fn get_systemd_fd() -> Result<Option<RawFd>> {
 /// A quick binary search by key.
 pub fn binsearch_by_key<'a, T, B, F>(slice: &'a [T], item: B, mut f: F) -> Option<&'a T>
     where B: Ord, F: FnMut(&T) -> B
+{
+    binsearch_idx_by_key(slice.len(), item, |idx| f(&slice[idx]))
+        .map(|idx| &slice[idx])
+}
+
+/// A quick binary search by key over anything that can be indexed.
+///
+/// Returns the index of the last item with a key smaller or equal to
+/// the given one.
+pub fn binsearch_idx_by_key<B, F>(len: usize, item: B, mut f: F) -> Option<usize>
+    where B: Ord, F: FnMut(usize) -> B
 {
     let mut low = 0;
-    let mut high = slice.len();
+    let mut high = len;
 
     while low < high {
         let mid = (low + high) / 2;
-        let cur_item = &slice[mid as usize];
-        if item < f(cur_item) {
+        if item < f(mid) {
             high = mid;
         } else {
             low = mid + 1;
         }
     }
 
-    if low > 0 && low <= slice.len() {
-        Some(&slice[low - 1])
+    if low > 0 && low <= len {
+        Some(low - 1)
     } else {
         None
     }
@@ -0,0 +1,54 @@
+//! Helpers for building binary fixtures shared by the tests
+#![allow(dead_code)]
+
+/// Appends a little endian `u16`.
+pub fn push_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.push(value as u8);
+    buf.push((value >> 8) as u8);
+}
+
+/// Appends a little endian `u32`.
+pub fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    push_u16(buf, value as u16);
+    push_u16(buf, (value >> 16) as u16);
+}
+
+/// Appends a little endian `u64`.
+pub fn push_u64(buf: &mut Vec<u8>, value: u64) {
+    push_u32(buf, value as u32);
+    push_u32(buf, (value >> 32) as u32);
+}
+
+/// Appends a name padded to 16 bytes as used in Mach-O load commands.
+pub fn push_name(buf: &mut Vec<u8>, name: &str) {
+    let start = buf.len();
+    buf.extend_from_slice(name.as_bytes());
+    buf.resize(start + 16, 0);
+}
+
+/// Appends an `LC_SEGMENT_64` command.
+///
+/// Sections are given as `(name, addr, size, flags)` and stored at the
+/// same offset from `fileoff` as from `vmaddr`.
+pub fn push_segment(buf: &mut Vec<u8>, name: &str, vmaddr: u64, fileoff: u64, size: u64,
+                    sections: &[(&str, u64, u64, u32)]) {
+    push_u32(buf, 0x19);
+    push_u32(buf, 72 + 80 * sections.len() as u32);
+    push_name(buf, name);
+    for &value in &[vmaddr, size, fileoff, size] {
+        push_u64(buf, value);
+    }
+    for &value in &[7, 5, sections.len() as u32, 0] {
+        push_u32(buf, value);
+    }
+    for &(sectname, addr, size, flags) in sections {
+        push_name(buf, sectname);
+        push_name(buf, name);
+        push_u64(buf, addr);
+        push_u64(buf, size);
+        push_u32(buf, (fileoff + addr - vmaddr) as u32);
+        for &value in &[0, 0, 0, flags, 0, 0, 0] {
+            push_u32(buf, value);
+        }
+    }
+}
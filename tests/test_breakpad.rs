@@ -0,0 +1,138 @@
+extern crate libsymbolserver;
+extern crate tempdir;
+extern crate uuid;
+
+use std::fs;
+use std::io::{Read, Write};
+
+use tempdir::TempDir;
+use uuid::Uuid;
+
+use libsymbolserver::breakpad::{BreakpadModule, export_memdb, parse_debug_id};
+use libsymbolserver::memdb::read::MemDb;
+use libsymbolserver::memdb::write::import_breakpad;
+use libsymbolserver::sdk::{SdkInfo, DumpOptions};
+
+const SYM_FILE: &'static str = "\
+MODULE Linux arm64 0302010005040706080A0B0C0D0E0F000 libfoo.so
+INFO CODE_ID 000102030405060708090A0B0C0D0E0F10111213
+FILE 0 /src/foo.c
+FILE 1 /src/bar.c
+INLINE_ORIGIN 0 inlined
+FUNC 1000 20 0 foo
+INLINE 0 12 0 0 1004 8
+1000 10 12 0
+1010 10 13 0
+FUNC m 1040 30 0 bar(int)
+1040 30 7 1
+PUBLIC 1100 0 baz
+STACK CFI INIT 1000 20 .cfa: sp 0 +
+";
+
+#[test]
+fn test_parse() {
+    let module = BreakpadModule::parse(SYM_FILE).unwrap();
+    assert_eq!(module.os(), "Linux");
+    assert_eq!(module.arch(), "arm64");
+    assert_eq!(module.name(), "libfoo.so");
+    assert_eq!(module.uuid(), "03020100-0504-0706-080a-0b0c0d0e0f00".parse::<Uuid>().unwrap());
+    assert_eq!(module.file(1), Some("/src/bar.c"));
+
+    let functions = module.functions();
+    assert_eq!(functions.len(), 2);
+    assert_eq!(functions[1].addr(), 0x1040);
+    assert_eq!(functions[1].size(), 0x30);
+    assert_eq!(functions[1].name(), "bar(int)");
+    assert_eq!(functions[0].lines().len(), 2);
+    assert_eq!(functions[0].lines()[1].line(), 13);
+    assert_eq!(module.publics(), &[(0x1100, "baz".to_string())]);
+}
+
+#[test]
+fn test_bad_files() {
+    assert!(BreakpadModule::parse("").is_err());
+    assert!(BreakpadModule::parse("MODULE Linux arm64 xyz libfoo.so").is_err());
+    assert!(BreakpadModule::parse("\
+MODULE Linux arm64 0302010005040706080A0B0C0D0E0F000 libfoo.so
+1000 10 12 0
+").is_err());
+}
+
+#[test]
+fn test_import() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let sym_path = tmp.path().join("libfoo.sym");
+    fs::File::create(&sym_path).unwrap().write_all(SYM_FILE.as_bytes()).unwrap();
+
+    let info = SdkInfo::new("Android", 8, 1, 0, Some("OPM1"));
+    let memdb_path = tmp.path().join(info.memdb_filename());
+    let f = fs::File::create(&memdb_path).unwrap();
+    import_breakpad(f, &info, DumpOptions::default(), &[&sym_path]).unwrap();
+
+    let memdb = MemDb::from_path(&memdb_path).unwrap();
+    let uuid: Uuid = "03020100-0504-0706-080a-0b0c0d0e0f00".parse().unwrap();
+    let sym = memdb.lookup_by_uuid(&uuid, 0x1014).unwrap();
+    assert_eq!(sym.symbol(), "foo");
+    assert_eq!(sym.object_name(), "libfoo.so");
+    assert_eq!(sym.filename(), Some("/src/foo.c"));
+    assert_eq!(sym.lineno(), Some(13));
+
+    let sym = memdb.lookup_by_object_name("libfoo.so", "arm64", 0x1104).unwrap();
+    assert_eq!(sym.symbol(), "baz");
+
+    // the gap between the functions has no symbol
+    assert!(memdb.lookup_by_uuid(&uuid, 0x1030).is_none());
+}
+
+#[test]
+fn test_export() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let sym_path = tmp.path().join("libfoo.sym");
+    fs::File::create(&sym_path).unwrap().write_all(SYM_FILE.as_bytes()).unwrap();
+    let info = SdkInfo::new("Android", 8, 1, 0, Some("OPM1"));
+    let memdb_path = tmp.path().join(info.memdb_filename());
+    let f = fs::File::create(&memdb_path).unwrap();
+    import_breakpad(f, &info, DumpOptions::default(), &[&sym_path]).unwrap();
+
+    let memdb = MemDb::from_path(&memdb_path).unwrap();
+    let export_path = tmp.path().join("export");
+    assert_eq!(export_memdb(&memdb, &export_path).unwrap(), 1);
+
+    let mut exported = String::new();
+    fs::File::open(export_path.join("libfoo.so/0302010005040706080A0B0C0D0E0F000/libfoo.so.sym"))
+        .unwrap().read_to_string(&mut exported).unwrap();
+    assert_eq!(exported, "\
+MODULE Linux arm64 0302010005040706080A0B0C0D0E0F000 libfoo.so
+FUNC 1000 20 0 foo
+FUNC 1040 30 0 bar(int)
+PUBLIC 1100 0 baz
+");
+
+    // the exported file can be imported again
+    let module = BreakpadModule::parse(&exported).unwrap();
+    assert_eq!(module.uuid(), memdb.find_uuid("libfoo.so", "arm64").unwrap().unwrap());
+    assert_eq!(memdb.get_tagged_object_name(&module.uuid()).unwrap(),
+               Some("libfoo.so:arm64".to_string()));
+}
+
+#[test]
+fn test_parse_debug_id() {
+    let uuid: Uuid = "03020100-0504-0706-080a-0b0c0d0e0f00".parse().unwrap();
+    assert_eq!(parse_debug_id("0302010005040706080A0B0C0D0E0F000"), Some(uuid));
+    assert_eq!(parse_debug_id("0302010005040706080a0b0c0d0e0f001a"), Some(uuid));
+    assert_eq!(parse_debug_id("0302010005040706"), None);
+    assert_eq!(parse_debug_id("libfoo.so"), None);
+}
+
+#[test]
+fn test_import_long_sdk_info() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let sym_path = tmp.path().join("libfoo.sym");
+    fs::File::create(&sym_path).unwrap().write_all(SYM_FILE.as_bytes()).unwrap();
+
+    for info in &[SdkInfo::new("AndroidThings", 8, 1, 0, Some("OPM1")),
+                  SdkInfo::new("Android", 8, 1, 0, Some("OPM1.171019.011.A1.B2.C3.D4"))] {
+        let f = fs::File::create(tmp.path().join("out.memdb")).unwrap();
+        assert!(import_breakpad(f, info, DumpOptions::default(), &[&sym_path]).is_err());
+    }
+}
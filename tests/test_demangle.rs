@@ -0,0 +1,63 @@
+extern crate libsymbolserver;
+
+use libsymbolserver::demangle::demangle;
+
+fn assert_demangles(fixtures: &[(&str, &str)]) {
+    for &(mangled, demangled) in fixtures {
+        assert_eq!(demangle(mangled).as_ref().map(|x| x.as_str()), Some(demangled),
+                   "failed to demangle {}", mangled);
+    }
+}
+
+#[test]
+fn test_cpp() {
+    assert_demangles(&[
+        ("__ZN3foo3barEv", "foo::bar()"),
+        ("_ZN3foo3barEv", "foo::bar()"),
+        ("__ZNK3foo3bazEPKc", "foo::baz(char const*) const"),
+        ("__ZN3foo3barE", "foo::bar"),
+        ("__ZN3FooC1Ev", "Foo::Foo()"),
+        ("__ZN3FooD2Ev", "Foo::~Foo()"),
+        ("__ZN3FooplERKS_", "Foo::operator+(Foo const&)"),
+        ("__ZNSt6vectorIiSaIiEE9push_backERKi",
+         "std::vector<int, std::allocator<int> >::push_back(int const&)"),
+        ("__Z1fIiEvT_", "void f<int>(int)"),
+        ("__Z5applyPFviEi", "apply(void (*)(int), int)"),
+        ("__ZTV3Foo", "vtable for Foo"),
+        ("__ZTIN3foo3BarE", "typeinfo for foo::Bar"),
+    ]);
+}
+
+#[test]
+fn test_swift() {
+    assert_demangles(&[
+        ("_$s4main3fooyyF", "main.foo() -> ()"),
+        ("$s4main3fooyyF", "main.foo() -> ()"),
+        ("_$s4main3add1a1bS2i_SitF", "main.add(a: Swift.Int, b: Swift.Int) -> Swift.Int"),
+        ("_$s4main3fooSiSgSSF", "main.foo(Swift.String) -> Swift.Int?"),
+        ("_$s4main3FooC3bazyySSKF", "main.Foo.baz(Swift.String) throws -> ()"),
+        ("_$s4main3FooV3barSivg", "main.Foo.bar.getter : Swift.Int"),
+        ("_$s4main3FooVACycfC", "main.Foo.init() -> main.Foo"),
+        ("_$s4main3FooV1xACSi_tcfC", "main.Foo.init(x: Swift.Int) -> main.Foo"),
+        ("_$s4main3FooC5countSaySiGyFZ", "static main.Foo.count() -> Swift.Array<Swift.Int>"),
+    ]);
+}
+
+#[test]
+fn test_not_mangled() {
+    assert_eq!(demangle("___CFFromMacKoreanLen"), None);
+    assert_eq!(demangle("<redacted>"), None);
+    assert_eq!(demangle("__ZN3foo"), None);
+    assert_eq!(demangle("_$s4main3foo"), None);
+}
+
+#[test]
+fn test_bogus_symbols() {
+    assert_eq!(demangle("$s4main3fooA99999999999a"), None);
+    assert_eq!(demangle("$s4main3fooS99999999999iyF"), None);
+    let nested = format!("__Z1fI{}{}Evv", "J".repeat(10000), "E".repeat(10000));
+    assert_eq!(demangle(&nested), None);
+
+    // every level repeats the generic type of the level before 41 times
+    assert_eq!(demangle("$s4main3FooVSayA40cCGSayA40dDGSayA40eEGSayA40fFGSayA40gGG"), None);
+}
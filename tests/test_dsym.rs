@@ -1,8 +1,12 @@
 extern crate libsymbolserver;
 extern crate uuid;
 
+mod common;
+
 use libsymbolserver::dsym::Object;
 
+use common::{push_u32, push_u64, push_segment};
+
 const DEMO_DYLIB: &'static [u8] = include_bytes!("res/libKoreanConverter.dylib");
 
 #[test]
@@ -48,3 +52,108 @@ fn test_symbols() {
         assert_eq!(count, 15);
     }
 }
+
+const OBJC_BASE: u64 = 0x100000000;
+
+fn write_at(buf: &mut Vec<u8>, offset: u64, values: &[u64]) {
+    let mut data = vec![];
+    for &value in values {
+        push_u64(&mut data, value);
+    }
+    let offset = offset as usize;
+    buf[offset..offset + data.len()].copy_from_slice(&data);
+}
+
+/// An arm64 dylib with the class `Foo`
+///
+/// The instance method `-[Foo bar]` is in a method list with pointers and
+/// the class method `+[Foo baz]` in a relative method list.  With chained
+/// fixups the pointers are stored as `DYLD_CHAINED_PTR_64_OFFSET`.
+fn objc_dylib(chained: bool) -> Vec<u8> {
+    let ptr = |addr: u64| if chained { addr - OBJC_BASE } else { addr };
+    let mut buf = vec![];
+    let (ncmds, cmds_size) = if chained { (4, 664) } else { (3, 648) };
+    for &value in &[0xfeedfacf, 0x100000c, 0, 6, ncmds, cmds_size, 0, 0] {
+        push_u32(&mut buf, value);
+    }
+    push_segment(&mut buf, "__TEXT", OBJC_BASE, 0, 0x800, &[
+        ("__text", OBJC_BASE + 0x400, 0x100, 0x80000400),
+        ("__objc_methname", OBJC_BASE + 0x500, 0x100, 2),
+    ]);
+    push_segment(&mut buf, "__DATA", OBJC_BASE + 0x800, 0x800, 0x800, &[
+        ("__objc_classlist", OBJC_BASE + 0x800, 8, 0),
+        ("__objc_selrefs", OBJC_BASE + 0x808, 8, 0),
+        ("__objc_data", OBJC_BASE + 0x840, 0x50, 0),
+        ("__objc_const", OBJC_BASE + 0x900, 0x100, 0),
+    ]);
+    push_u32(&mut buf, 0x1b);
+    push_u32(&mut buf, 24);
+    buf.extend((0..16).map(|x| x as u8));
+    if chained {
+        for &value in &[0x80000034, 16, 0xc00, 0x48] {
+            push_u32(&mut buf, value);
+        }
+    }
+    buf.resize(0x1000, 0);
+
+    buf[0x500..0x50c].copy_from_slice(b"Foo\x00bar\x00baz\x00");
+    // class list, selector reference, class and metaclass
+    write_at(&mut buf, 0x800, &[ptr(OBJC_BASE + 0x840), ptr(OBJC_BASE + 0x508)]);
+    write_at(&mut buf, 0x840, &[ptr(OBJC_BASE + 0x868), 0, 0, 0, ptr(OBJC_BASE + 0x900)]);
+    write_at(&mut buf, 0x868, &[0, 0, 0, 0, ptr(OBJC_BASE + 0x980)]);
+    // read-only data with the name and the methods
+    write_at(&mut buf, 0x900, &[0, 0, 0, ptr(OBJC_BASE + 0x500), ptr(OBJC_BASE + 0x940)]);
+    write_at(&mut buf, 0x980, &[0, 0, 0, ptr(OBJC_BASE + 0x500), ptr(OBJC_BASE + 0x9c0)]);
+
+    // method list with name, types and implementation pointers
+    let mut list = vec![];
+    push_u32(&mut list, 24);
+    push_u32(&mut list, 1);
+    for &value in &[ptr(OBJC_BASE + 0x504), 0, ptr(OBJC_BASE + 0x400)] {
+        push_u64(&mut list, value);
+    }
+    buf[0x940..0x940 + list.len()].copy_from_slice(&list);
+
+    // relative method list with offsets to the selector reference and
+    // the implementation
+    let mut list = vec![];
+    for &value in &[0x8000000c, 1, (0x808i32 - 0x9c8) as u32, 0, (0x480i32 - 0x9d0) as u32] {
+        push_u32(&mut list, value);
+    }
+    buf[0x9c0..0x9c0 + list.len()].copy_from_slice(&list);
+
+    if chained {
+        // header, segment starts and the starts of the data segment
+        let mut fixups = vec![];
+        for &value in &[0, 0x20, 0x40, 0x40, 0, 1, 0, 0, 2, 0, 0x10, 0, 24] {
+            push_u32(&mut fixups, value);
+        }
+        push_u32(&mut fixups, 0x4000 | (6 << 16));
+        push_u64(&mut fixups, 0x800);
+        push_u32(&mut fixups, 0);
+        push_u32(&mut fixups, 1);
+        buf[0xc00..0xc00 + fixups.len()].copy_from_slice(&fixups);
+    }
+    buf
+}
+
+#[test]
+fn test_objc_methods() {
+    for &chained in &[false, true] {
+        let data = objc_dylib(chained);
+        let obj = Object::from_slice(&data).unwrap();
+        let symbols = obj.symbols("arm64").unwrap();
+        assert_eq!(symbols.objc_methods(), vec![
+            (OBJC_BASE + 0x400, "-[Foo bar]".to_string()),
+            (OBJC_BASE + 0x480, "+[Foo baz]".to_string()),
+        ]);
+    }
+}
+
+#[test]
+fn test_objc_methods_unknown_pointer_format() {
+    let mut data = objc_dylib(true);
+    data[0xc36] = 0x7f;
+    let obj = Object::from_slice(&data).unwrap();
+    assert!(obj.symbols("arm64").unwrap().objc_methods().is_empty());
+}
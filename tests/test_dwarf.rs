@@ -0,0 +1,197 @@
+extern crate libsymbolserver;
+extern crate tempdir;
+extern crate uuid;
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+
+use tempdir::TempDir;
+use uuid::Uuid;
+
+use libsymbolserver::dwarf::{LineTable, Subprogram};
+use libsymbolserver::memdb::read::MemDb;
+use libsymbolserver::memdb::types::CodeSectionKind;
+use libsymbolserver::sdk::{Sdk, DumpOptions};
+
+use common::{push_u32, push_u64, push_segment};
+
+const UUID: &'static str = "00010203-0405-0607-0809-0a0b0c0d0e0f";
+
+/// A DWARF 2 line program for `src/main.c` starting at 0x1000
+fn line_program() -> Vec<u8> {
+    // min_inst_len, default_is_stmt, line_base, line_range, opcode_base
+    // and the standard opcode lengths
+    let mut header = vec![1, 1, 0xfb, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+    header.extend_from_slice(b"src\x00\x00main.c\x00\x01\x00\x00\x00");
+    let program = vec![
+        // set_address 0x1000
+        0, 9, 2, 0x00, 0x10, 0, 0, 0, 0, 0, 0,
+        // advance_line 9, copy
+        3, 9, 1,
+        // special opcode: address + 4, line + 1
+        75,
+        // advance_pc 4, end_sequence
+        2, 4, 0, 1, 1,
+    ];
+
+    let mut unit = vec![2, 0];
+    push_u32(&mut unit, header.len() as u32);
+    unit.extend(header);
+    unit.extend(program);
+
+    let mut rv = vec![];
+    push_u32(&mut rv, unit.len() as u32);
+    rv.extend(unit);
+    rv
+}
+
+/// Debug info with the functions `main` at 0x1000 and `helper` at 0x1040
+///
+/// Returns the `__debug_abbrev`, `__debug_str` and `__debug_info` sections.
+/// The name of `main` is in the string section.
+fn debug_info() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    // compile unit with children, subprogram with a name from the string
+    // section and subprogram with an inline name and a length
+    let abbrev = vec![
+        1, 0x11, 1, 0, 0,
+        2, 0x2e, 0, 0x03, 0x0e, 0x11, 0x01, 0x12, 0x01, 0, 0,
+        3, 0x2e, 0, 0x03, 0x08, 0x11, 0x01, 0x12, 0x06, 0, 0,
+        0,
+    ];
+    let strings = b"\x00main\x00".to_vec();
+
+    let mut unit = vec![2, 0];
+    push_u32(&mut unit, 0);
+    unit.extend_from_slice(&[8, 1, 2]);
+    push_u32(&mut unit, 1);
+    push_u64(&mut unit, 0x1000);
+    push_u64(&mut unit, 0x1008);
+    unit.push(3);
+    unit.extend_from_slice(b"helper\x00");
+    push_u64(&mut unit, 0x1040);
+    push_u32(&mut unit, 0x10);
+    unit.push(0);
+
+    let mut info = vec![];
+    push_u32(&mut info, unit.len() as u32);
+    info.extend(unit);
+    (abbrev, strings, info)
+}
+
+/// A dSYM for an arm64 image whose code is described by the debug info
+fn dsym() -> Vec<u8> {
+    let (abbrev, strings, info) = debug_info();
+    let lines = line_program();
+    let mut dwarf_sections = vec![];
+    let mut dwarf = vec![];
+    for &(name, data) in &[("__debug_abbrev", &abbrev), ("__debug_str", &strings),
+                               ("__debug_info", &info), ("__debug_line", &lines)] {
+        dwarf_sections.push((name, 0x2000 + dwarf.len() as u64, data.len() as u64, 0));
+        dwarf.extend_from_slice(data);
+    }
+
+    let mut buf = vec![];
+    for &value in &[0xfeedfacf, 0x100000c, 0, 0xa, 3, 568, 0, 0] {
+        push_u32(&mut buf, value);
+    }
+    push_segment(&mut buf, "__TEXT", 0, 0, 0x2000, &[
+        ("__text", 0x1000, 0x100, 0x80000400),
+    ]);
+    push_segment(&mut buf, "__DWARF", 0x2000, 0x2000, dwarf.len() as u64, &dwarf_sections);
+    push_u32(&mut buf, 0x1b);
+    push_u32(&mut buf, 24);
+    buf.extend_from_slice(UUID.parse::<Uuid>().unwrap().as_bytes());
+    buf.resize(0x2000, 0);
+    buf.extend(dwarf);
+    buf
+}
+
+#[test]
+fn test_subprograms() {
+    let (abbrev, strings, info) = debug_info();
+    let subprograms = Subprogram::parse_all(&info, &abbrev, &strings).unwrap();
+    let functions: Vec<_> = subprograms.iter()
+        .map(|x| (x.name(), x.low_pc(), x.high_pc()))
+        .collect();
+    assert_eq!(functions, vec![("main", 0x1000, 0x1008), ("helper", 0x1040, 0x1050)]);
+
+    // strings outside of the string section
+    assert!(Subprogram::parse_all(&info, &abbrev, b"").is_err());
+}
+
+/// Converts an SDK with the dSYM into a memdb
+fn dump_dsym(tmp: &TempDir, opts: DumpOptions) -> MemDb<'static> {
+    let sdk_path = tmp.path().join("iOS DeviceSupport").join("10.2 (14C92)");
+    let dsym_path = sdk_path.join("Symbols").join("usr/lib/libmain.dylib");
+    fs::create_dir_all(dsym_path.parent().unwrap()).unwrap();
+    fs::File::create(&dsym_path).unwrap().write_all(&dsym()).unwrap();
+    let mut buf = std::io::Cursor::new(vec![]);
+    Sdk::new(&sdk_path).unwrap().dump_memdb(&mut buf, opts).unwrap();
+    MemDb::from_vec(buf.into_inner()).unwrap()
+}
+
+#[test]
+fn test_dump_memdb() {
+    // in low memory mode the line tables and code sections are spilled
+    for &low_memory in &[false, true] {
+        let tmp = TempDir::new("symbolserver-test").unwrap();
+        let memdb = dump_dsym(&tmp, DumpOptions {
+            low_memory: low_memory,
+            ..Default::default()
+        });
+        assert_eq!(memdb.verify().unwrap(), true);
+
+        let uuid: Uuid = UUID.parse().unwrap();
+        let sym = memdb.lookup_by_uuid(&uuid, 0x1002).unwrap();
+        assert_eq!(sym.symbol(), "main");
+        assert_eq!(sym.filename(), Some("src/main.c"));
+        assert_eq!(sym.lineno(), Some(10));
+        assert_eq!(sym.section_kind(), Some(CodeSectionKind::Text));
+        let sym = memdb.lookup_by_uuid(&uuid, 0x1006).unwrap();
+        assert_eq!(sym.lineno(), Some(11));
+
+        // functions after the end of the line program have no line
+        let sym = memdb.lookup_by_uuid(&uuid, 0x1044).unwrap();
+        assert_eq!(sym.symbol(), "helper");
+        assert_eq!(sym.filename(), None);
+        assert_eq!(sym.lineno(), None);
+    }
+}
+
+#[test]
+fn test_lookup_in_padding() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let memdb = dump_dsym(&tmp, DumpOptions::default());
+
+    // `main` ends at 0x1008 and `helper` only starts at 0x1040
+    let uuid: Uuid = UUID.parse().unwrap();
+    assert_eq!(memdb.lookup_by_uuid(&uuid, 0x1004).unwrap().symbol(), "main");
+    assert!(memdb.lookup_by_uuid(&uuid, 0x1008).is_none());
+    assert!(memdb.lookup_by_uuid(&uuid, 0x1020).is_none());
+    assert_eq!(memdb.lookup_by_uuid(&uuid, 0x1040).unwrap().symbol(), "helper");
+
+    // the rest of the section after `helper` is padding as well
+    assert!(memdb.lookup_by_uuid(&uuid, 0x1080).is_none());
+}
+
+#[test]
+fn test_line_program() {
+    let table = LineTable::parse(&line_program()).unwrap();
+    assert_eq!(table.files(), &["src/main.c".to_string()]);
+    let rows: Vec<_> = table.rows().iter()
+        .map(|row| (row.addr(), &table.files()[row.file()][..], row.line()))
+        .collect();
+    assert_eq!(rows, vec![
+        (0x1000, "src/main.c", 10),
+        (0x1004, "src/main.c", 11),
+        (0x1008, "src/main.c", 0),
+    ]);
+}
+
+#[test]
+fn test_truncated_line_program() {
+    let data = line_program();
+    assert!(LineTable::parse(&data[..20]).is_err());
+}
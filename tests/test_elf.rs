@@ -0,0 +1,136 @@
+extern crate libsymbolserver;
+extern crate uuid;
+
+mod common;
+
+use libsymbolserver::dsym::Object;
+use libsymbolserver::elf::{ElfFile, is_elf};
+use libsymbolserver::sdk::RawObject;
+
+use common::{push_u16, push_u32, push_u64};
+
+fn push_section(buf: &mut Vec<u8>, name: u32, kind: u32, flags: u64, addr: u64,
+                offset: u64, size: u64, link: u32, entsize: u64) {
+    push_u32(buf, name);
+    push_u32(buf, kind);
+    push_u64(buf, flags);
+    push_u64(buf, addr);
+    push_u64(buf, offset);
+    push_u64(buf, size);
+    push_u32(buf, link);
+    push_u32(buf, 0);
+    push_u64(buf, 0);
+    push_u64(buf, entsize);
+}
+
+fn push_symbol(buf: &mut Vec<u8>, name: u32, info: u8, shndx: u16, value: u64) {
+    push_u32(buf, name);
+    buf.push(info);
+    buf.push(0);
+    push_u16(buf, shndx);
+    push_u64(buf, value);
+    push_u64(buf, 0);
+}
+
+/// An arm64 shared library with the functions `foo` and `bar`
+fn shared_library() -> Vec<u8> {
+    let mut buf = b"\x7fELF\x02\x01\x01\x00".to_vec();
+    buf.resize(16, 0);
+    // type, machine, version, entry, program and section headers
+    push_u16(&mut buf, 3);
+    push_u16(&mut buf, 183);
+    push_u32(&mut buf, 1);
+    push_u64(&mut buf, 0);
+    push_u64(&mut buf, 0x40);
+    push_u64(&mut buf, 0x300);
+    push_u32(&mut buf, 0);
+    for &value in &[64, 56, 2, 64, 6, 5] {
+        push_u16(&mut buf, value);
+    }
+
+    // PT_LOAD covering the text and PT_NOTE with the build id
+    for &(kind, flags, offset, size) in &[(1, 5, 0, 0x200), (4, 4, 0xb0, 36)] {
+        push_u32(&mut buf, kind);
+        push_u32(&mut buf, flags);
+        push_u64(&mut buf, offset);
+        push_u64(&mut buf, offset);
+        push_u64(&mut buf, offset);
+        push_u64(&mut buf, size);
+        push_u64(&mut buf, size);
+        push_u64(&mut buf, 0);
+    }
+    push_u32(&mut buf, 4);
+    push_u32(&mut buf, 20);
+    push_u32(&mut buf, 3);
+    buf.extend_from_slice(b"GNU\x00");
+    buf.extend((0..20).map(|x| x as u8));
+    buf.resize(0x200, 0);
+
+    push_symbol(&mut buf, 0, 0, 0, 0);
+    push_symbol(&mut buf, 1, 0x12, 1, 0x100);
+    push_symbol(&mut buf, 5, 0x12, 1, 0x180);
+    push_symbol(&mut buf, 9, 0x11, 1, 0x1f0);
+    buf.extend_from_slice(b"\x00foo\x00bar\x00data\x00");
+    buf.resize(0x270, 0);
+    buf.extend_from_slice(b"\x00.text\x00.note.gnu.build-id\x00.symtab\x00.strtab\x00.shstrtab\x00");
+    buf.resize(0x300, 0);
+
+    push_section(&mut buf, 0, 0, 0, 0, 0, 0, 0, 0);
+    push_section(&mut buf, 1, 1, 6, 0x100, 0x100, 0x100, 0, 0);
+    push_section(&mut buf, 7, 7, 2, 0xb0, 0xb0, 36, 0, 0);
+    push_section(&mut buf, 26, 2, 0, 0, 0x200, 96, 4, 24);
+    push_section(&mut buf, 34, 3, 0, 0, 0x260, 14, 0, 0);
+    push_section(&mut buf, 42, 3, 0, 0, 0x270, 52, 0, 0);
+    buf
+}
+
+#[test]
+fn test_variants() {
+    let data = shared_library();
+    assert!(is_elf(&data));
+
+    let obj = Object::from_slice(&data).unwrap();
+    let variants = obj.variants();
+    assert_eq!(variants.len(), 1);
+    assert_eq!(variants[0].arch(), "arm64");
+    assert_eq!(variants[0].name(), None);
+    assert_eq!(variants[0].uuid(), Some("03020100-0504-0706-0809-0a0b0c0d0e0f".parse().unwrap()));
+    assert_eq!(variants[0].vmaddr(), 0);
+    assert_eq!(variants[0].vmsize(), 0x200);
+
+    let sections = variants[0].code_sections();
+    assert_eq!(sections.len(), 1);
+    assert_eq!(sections[0].name(), ".text");
+    assert_eq!(sections[0].addr(), 0x100);
+    assert_eq!(sections[0].size(), 0x100);
+}
+
+#[test]
+fn test_symbols() {
+    let data = shared_library();
+    let obj = Object::from_slice(&data).unwrap();
+    let mut symbols = obj.symbols("arm64").unwrap();
+    assert!(symbols.function_starts().unwrap().is_empty());
+    assert!(symbols.objc_methods().is_empty());
+    let syms: Vec<_> = symbols.iter().collect();
+    assert_eq!(syms, vec![(0x100, "foo"), (0x180, "bar")]);
+    assert!(obj.symbols("x86_64").is_err());
+}
+
+#[test]
+fn test_truncated() {
+    let data = shared_library();
+    assert!(ElfFile::parse(&data[..0x310]).is_err());
+    assert!(ElfFile::parse(b"\x7fELF").is_err());
+}
+
+#[test]
+fn test_unreadable_skipped() {
+    let data = shared_library();
+    assert!(RawObject::Buf(data[..0x310].to_vec()).parse().unwrap().is_none());
+
+    let mut big_endian = data.clone();
+    big_endian[5] = 2;
+    assert!(RawObject::Buf(big_endian).parse().unwrap().is_none());
+    assert!(RawObject::Buf(data).parse().unwrap().is_some());
+}
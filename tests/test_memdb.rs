@@ -0,0 +1,524 @@
+extern crate libsymbolserver;
+extern crate tempdir;
+extern crate uuid;
+extern crate md5;
+
+mod common;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use tempdir::TempDir;
+use uuid::Uuid;
+
+use libsymbolserver::sdk::{Sdk, SdkInfo, DumpOptions};
+use libsymbolserver::memdb::read::{MemDb, NameMatch};
+use libsymbolserver::memdb::write::{merge_memdbs, update_memdb, import_breakpad};
+use libsymbolserver::dsym::{Object, STRIPPED_SYMBOL};
+use libsymbolserver::breakpad::export_memdb;
+use libsymbolserver::memdb::diff::diff_memdbs;
+use libsymbolserver::memdb::types::{CodeSectionKind, MEMDB_VERSION};
+
+use common::{push_u32, push_u64, push_segment};
+
+const DEMO_DYLIB: &'static [u8] = include_bytes!("res/libKoreanConverter.dylib");
+const DEMO_DYLIB_NAME: &'static str = "/System/Library/CoreServices/Encodings/libKoreanConverter.dylib";
+
+fn convert_fixture(tmp: &TempDir) -> PathBuf {
+    convert_fixture_as(tmp, "10.2 (14C92)")
+}
+
+fn convert_fixture_as(tmp: &TempDir, sdk_folder: &str) -> PathBuf {
+    convert_fixture_with(tmp, sdk_folder, DumpOptions::default())
+}
+
+fn convert_fixture_with(tmp: &TempDir, sdk_folder: &str, opts: DumpOptions) -> PathBuf {
+    let sdk_path = tmp.path().join("iOS DeviceSupport").join(sdk_folder);
+    let dylib_path = sdk_path.join("Symbols").join(&DEMO_DYLIB_NAME[1..]);
+    fs::create_dir_all(dylib_path.parent().unwrap()).unwrap();
+    fs::File::create(&dylib_path).unwrap().write_all(DEMO_DYLIB).unwrap();
+
+    let sdk = Sdk::new(&sdk_path).unwrap();
+    let memdb_path = tmp.path().join(sdk.info().memdb_filename());
+    let f = fs::File::create(&memdb_path).unwrap();
+    sdk.dump_memdb(f, opts).unwrap();
+    memdb_path
+}
+
+#[test]
+fn test_lookup_roundtrip() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let memdb = MemDb::from_path(convert_fixture(&tmp)).unwrap();
+    assert_eq!(memdb.info().name(), "iOS");
+
+    let uuid: Uuid = "fe6d76d4-8c3a-3a9a-9f63-f4a475501f1b".parse().unwrap();
+    let sym = memdb.lookup_by_uuid(&uuid, 6804482832 - 6804459520).unwrap();
+    assert_eq!(sym.symbol(), "___CFFromMacKoreanLen");
+    assert_eq!(sym.object_name(), DEMO_DYLIB_NAME);
+
+    let sym = memdb.lookup_by_object_name(DEMO_DYLIB_NAME, "armv7s",
+                                          744692588 - 744677376).unwrap();
+    assert_eq!(sym.symbol(), "___CFFromMacKoreanLen");
+}
+
+#[test]
+fn test_compressed_symbols_version() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let mut buf = vec![];
+    fs::File::open(convert_fixture(&tmp)).unwrap().read_to_end(&mut buf).unwrap();
+
+    // symbol names are always compressed which version 2 readers reject
+    let version = buf[0] as u32 | (buf[1] as u32) << 8 | (buf[2] as u32) << 16 |
+        (buf[3] as u32) << 24;
+    assert_eq!(version, MEMDB_VERSION);
+    assert!(version > 2);
+}
+
+#[test]
+fn test_lookup_past_end_of_image() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let memdb = MemDb::from_path(convert_fixture(&tmp)).unwrap();
+
+    let uuid: Uuid = "fe6d76d4-8c3a-3a9a-9f63-f4a475501f1b".parse().unwrap();
+    let sym = memdb.lookup_by_uuid(&uuid, 6804482832 - 6804459520 + 4).unwrap();
+    assert_eq!(sym.symbol(), "___CFFromMacKoreanLen");
+    assert_eq!(sym.addr(), 6804482832 - 6804459520);
+    assert!(memdb.lookup_by_uuid(&uuid, 143360 + 16).is_none());
+}
+
+#[test]
+fn test_code_sections() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let memdb = MemDb::from_path(convert_fixture(&tmp)).unwrap();
+
+    let uuid: Uuid = "fe6d76d4-8c3a-3a9a-9f63-f4a475501f1b".parse().unwrap();
+    let sections = memdb.get_code_sections(&uuid).unwrap();
+    assert!(sections.iter().any(|x| x.kind() == CodeSectionKind::Text));
+
+    let sym = memdb.lookup_by_uuid(&uuid, 6804482832 - 6804459520).unwrap();
+    assert_eq!(sym.section_kind(), Some(CodeSectionKind::Text));
+
+    // stubs have no symbols but are still reported with their section
+    let stubs = sections.iter().find(|x| x.kind() == CodeSectionKind::Stubs).unwrap();
+    let sym = memdb.lookup_by_uuid(&uuid, stubs.addr() + 4).unwrap();
+    assert_eq!(sym.section_kind(), Some(CodeSectionKind::Stubs));
+    assert_eq!(sym.symbol(), STRIPPED_SYMBOL);
+    assert_eq!(sym.addr(), stubs.addr());
+    assert_eq!(sym.object_name(), DEMO_DYLIB_NAME);
+}
+
+#[test]
+fn test_iter_symbols() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let memdb = MemDb::from_path(convert_fixture(&tmp)).unwrap();
+
+    let uuid: Uuid = "383fbe5b-e16e-362f-8937-ed303ab58e72".parse().unwrap();
+    let symbols: Vec<_> = memdb.iter_symbols(&uuid).unwrap()
+        .map(|x| x.unwrap().symbol().to_string()).collect();
+    assert_eq!(symbols.len(), 15);
+    assert!(symbols.iter().any(|x| x == "___CFFromMacKoreanLen"));
+}
+
+#[test]
+fn test_lookup_by_name() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let memdb = MemDb::from_path(convert_fixture(&tmp)).unwrap();
+
+    let mut hits: Vec<_> = memdb.lookup_by_name("___CFFromMacKoreanLen", NameMatch::Exact)
+        .unwrap().map(|x| x.unwrap()).collect();
+    hits.sort_by_key(|x| x.arch().to_string());
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].arch(), "arm64");
+    assert_eq!(hits[0].object_name(), DEMO_DYLIB_NAME);
+    assert_eq!(hits[0].object_uuid(), "fe6d76d4-8c3a-3a9a-9f63-f4a475501f1b".parse().unwrap());
+    assert_eq!(hits[0].addr(), 6804482832 - 6804459520);
+    assert!(hits[0].size().unwrap() > 0);
+    assert_eq!(hits[1].arch(), "armv7s");
+    assert_eq!(hits[1].addr(), 744692588 - 744677376);
+
+    let hits: Vec<_> = memdb.lookup_by_name("___CFFromMacKorean", NameMatch::Prefix)
+        .unwrap().map(|x| x.unwrap()).collect();
+    assert!(hits.len() >= 2);
+    assert!(hits.iter().all(|x| x.symbol().starts_with("___CFFromMacKorean")));
+
+    assert_eq!(memdb.lookup_by_name("___CFFromMacKorean", NameMatch::Exact)
+        .unwrap().count(), 0);
+}
+
+const STRIPPED_UUID: &'static str = "00010203-0405-0607-0809-0a0b0c0d0e0f";
+
+/// An arm64 dylib with the function `_foo` and a stripped function
+///
+/// The stripped function at 0x480 is only known from `LC_FUNCTION_STARTS`.
+fn stripped_dylib() -> Vec<u8> {
+    stripped_dylib_with_uuid(STRIPPED_UUID)
+}
+
+fn stripped_dylib_with_uuid(uuid: &str) -> Vec<u8> {
+    let mut buf = vec![];
+    for &value in &[0xfeedfacf, 0x100000c, 0, 6, 4, 216, 0, 0] {
+        push_u32(&mut buf, value);
+    }
+    push_segment(&mut buf, "__TEXT", 0, 0, 0x1000, &[
+        ("__text", 0x400, 0x100, 0x80000400),
+    ]);
+    push_u32(&mut buf, 0x1b);
+    push_u32(&mut buf, 24);
+    buf.extend_from_slice(uuid.parse::<Uuid>().unwrap().as_bytes());
+    // LC_SYMTAB and LC_FUNCTION_STARTS
+    for &value in &[0x2, 24, 0x800, 1, 0x810, 8, 0x26, 16, 0x820, 8] {
+        push_u32(&mut buf, value);
+    }
+    buf.resize(0x800, 0);
+
+    // an external symbol in the first section
+    push_u32(&mut buf, 1);
+    buf.extend_from_slice(&[0x0f, 1, 0, 0]);
+    push_u64(&mut buf, 0x400);
+    buf.extend_from_slice(b"\x00_foo\x00\x00\x00");
+    // function starts as deltas from the start of the segment
+    buf.extend_from_slice(&[0x80, 0x08, 0x80, 0x01, 0, 0, 0, 0]);
+    buf.resize(0x1000, 0);
+    buf
+}
+
+#[test]
+fn test_stripped_functions() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let sdk_path = tmp.path().join("iOS DeviceSupport").join("10.2 (14C92)");
+    let dylib_path = sdk_path.join("Symbols").join("usr/lib/libstripped.dylib");
+    fs::create_dir_all(dylib_path.parent().unwrap()).unwrap();
+    fs::File::create(&dylib_path).unwrap().write_all(&stripped_dylib()).unwrap();
+    let mut buf = std::io::Cursor::new(vec![]);
+    Sdk::new(&sdk_path).unwrap().dump_memdb(&mut buf, DumpOptions::default()).unwrap();
+    let memdb = MemDb::from_vec(buf.into_inner()).unwrap();
+
+    let uuid: Uuid = STRIPPED_UUID.parse().unwrap();
+    let placeholders: Vec<_> = memdb.iter_index(&uuid).unwrap()
+        .map(|x| x.unwrap())
+        .filter(|x| x.symbol() == Some(STRIPPED_SYMBOL))
+        .map(|x| x.addr())
+        .collect();
+    assert_eq!(placeholders, vec![0x480]);
+
+    // the stripped function ends the function before it
+    let sym = memdb.lookup_by_uuid(&uuid, 0x47c).unwrap();
+    assert_eq!(sym.symbol(), "_foo");
+    let sym = memdb.lookup_by_uuid(&uuid, 0x484).unwrap();
+    assert_eq!(sym.symbol(), STRIPPED_SYMBOL);
+    assert_eq!(sym.addr(), 0x480);
+
+    assert!(memdb.iter_symbols(&uuid).unwrap().all(|x| x.unwrap().symbol() != STRIPPED_SYMBOL));
+    assert_eq!(memdb.lookup_by_name(STRIPPED_SYMBOL, NameMatch::Exact).unwrap().count(), 0);
+}
+
+#[test]
+fn test_export_stripped_functions() {
+    let memdb = MemDb::from_vec(convert_files(&[
+        ("usr/lib/libstripped.dylib", stripped_dylib()),
+    ], DumpOptions::default())).unwrap();
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    assert_eq!(export_memdb(&memdb, tmp.path()).unwrap(), 1);
+
+    // the placeholder ends `_foo` and the section end the placeholder
+    let mut exported = String::new();
+    fs::File::open(tmp.path().join(
+        "libstripped.dylib/000102030405060708090A0B0C0D0E0F0/libstripped.dylib.sym"))
+        .unwrap().read_to_string(&mut exported).unwrap();
+    assert_eq!(exported, "\
+MODULE mac arm64 000102030405060708090A0B0C0D0E0F0 libstripped.dylib
+FUNC 400 80 0 _foo
+FUNC 480 80 0 <redacted>
+");
+}
+
+#[test]
+fn test_bad_memdb() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let mut buf = vec![];
+    fs::File::open(convert_fixture(&tmp)).unwrap().read_to_end(&mut buf).unwrap();
+    assert!(MemDb::from_slice(&buf[..]).is_ok());
+
+    // truncated files
+    assert!(MemDb::from_slice(&buf[..2]).is_err());
+    assert!(MemDb::from_slice(&buf[..40]).is_err());
+    assert!(MemDb::from_slice(&buf[..buf.len() - 1]).is_err());
+
+    // tables pointing outside of the file
+    let mut broken = buf.clone();
+    for byte in &mut broken[46..50] {
+        *byte = 0xff;
+    }
+    assert!(MemDb::from_slice(&broken[..]).is_err());
+
+    // files with the widened index that were still written as version 3
+    let mut wide_v3 = buf.clone();
+    wide_v3[0] = 3;
+    let memdb = MemDb::from_slice(&wide_v3[..]).unwrap();
+    let uuid: Uuid = "fe6d76d4-8c3a-3a9a-9f63-f4a475501f1b".parse().unwrap();
+    let sym = memdb.lookup_by_uuid(&uuid, 6804482832 - 6804459520).unwrap();
+    assert_eq!(sym.symbol(), "___CFFromMacKoreanLen");
+
+    // files written by a newer version
+    let mut newer = buf.clone();
+    newer[0] += 1;
+    assert!(MemDb::from_slice(&newer[..]).is_err());
+}
+
+#[test]
+fn test_verify() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let mut buf = vec![];
+    fs::File::open(convert_fixture(&tmp)).unwrap().read_to_end(&mut buf).unwrap();
+    assert_eq!(MemDb::from_slice(&buf[..]).unwrap().verify().unwrap(), true);
+
+    // flip a bit in the symbol index of the first variant
+    buf[100] ^= 0x01;
+    let memdb = MemDb::from_slice(&buf[..]).unwrap();
+    assert!(memdb.verify().is_err());
+}
+
+#[test]
+fn test_merge() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let memdbs = vec![
+        MemDb::from_path(convert_fixture_as(&tmp, "10.2 (14C92)")).unwrap(),
+        MemDb::from_path(convert_fixture_as(&tmp, "10.3 (14E277)")).unwrap(),
+    ];
+    let info = SdkInfo::new("iOS", 10, 0, 0, None);
+    let merged_path = tmp.path().join(info.memdb_filename());
+    merge_memdbs(fs::File::create(&merged_path).unwrap(), &info,
+                 DumpOptions::default(), &memdbs[..]).unwrap();
+
+    let memdb = MemDb::from_path(&merged_path).unwrap();
+    assert_eq!(memdb.verify().unwrap(), true);
+    assert_eq!(memdb.info(), &info);
+    assert_eq!(memdb.source_sdks().unwrap().len(), 2);
+    assert_eq!(memdb.list_tagged_objects().unwrap().len(), 2);
+
+    let uuid: Uuid = "fe6d76d4-8c3a-3a9a-9f63-f4a475501f1b".parse().unwrap();
+    let sdks = memdb.sdks_for_uuid(&uuid).unwrap();
+    assert_eq!(sdks.len(), 2);
+    assert_eq!(sdks[0].build(), Some("14C92"));
+    assert_eq!(sdks[1].build(), Some("14E277"));
+
+    let sym = memdb.lookup_by_uuid(&uuid, 6804482832 - 6804459520).unwrap();
+    assert_eq!(sym.symbol(), "___CFFromMacKoreanLen");
+    assert_eq!(memdb.iter_symbols(&uuid).unwrap().count(), 15);
+}
+
+#[test]
+fn test_diff() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let memdb = MemDb::from_path(convert_fixture(&tmp)).unwrap();
+    assert!(diff_memdbs(&memdb, &memdb).unwrap().is_empty());
+
+    let empty_path = tmp.path().join("iOS DeviceSupport").join("10.3 (14E277)");
+    fs::create_dir_all(empty_path.join("Symbols")).unwrap();
+    let empty_sdk = Sdk::new(&empty_path).unwrap();
+    let mut buf = std::io::Cursor::new(vec![]);
+    empty_sdk.dump_memdb(&mut buf, DumpOptions::default()).unwrap();
+    let empty = MemDb::from_vec(buf.into_inner()).unwrap();
+
+    let diff = diff_memdbs(&memdb, &empty).unwrap();
+    assert_eq!(diff.removed_objects().len(), 2);
+    assert_eq!(diff.added_objects().len(), 0);
+    assert_eq!(diff.removed_objects()[0].name(),
+               format!("{}:arm64", DEMO_DYLIB_NAME));
+
+    let diff = diff_memdbs(&empty, &memdb).unwrap();
+    assert_eq!(diff.added_objects().len(), 2);
+    assert_eq!(diff.changed_objects().len(), 0);
+}
+
+/// Imports Breakpad symbol files with the given debug ids and functions
+fn import_modules(tmp: &TempDir, modules: &[(&str, &[&str])]) -> MemDb<'static> {
+    let mut paths = vec![];
+    for (idx, &(debug_id, functions)) in modules.iter().enumerate() {
+        let mut sym = format!("MODULE Linux arm64 {} libfoo.so\n", debug_id);
+        for (func_idx, func) in functions.iter().enumerate() {
+            sym.push_str(&format!("PUBLIC {:x} 0 {}\n", 0x1000 + func_idx * 0x10, func));
+        }
+        let path = tmp.path().join(format!("{}.sym", idx));
+        fs::File::create(&path).unwrap().write_all(sym.as_bytes()).unwrap();
+        paths.push(path);
+    }
+    let info = SdkInfo::new("Android", 8, 1, 0, None);
+    let mut buf = std::io::Cursor::new(vec![]);
+    import_breakpad(&mut buf, &info, DumpOptions::default(), &paths).unwrap();
+    MemDb::from_vec(buf.into_inner()).unwrap()
+}
+
+#[test]
+fn test_diff_symbols() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let old = import_modules(&tmp, &[("000102030405060708090A0B0C0D0E0F0", &["foo", "bar"][..])]);
+    let new = import_modules(&tmp, &[("101112131415161718191A1B1C1D1E1F0", &["foo", "baz"][..])]);
+
+    let diff = diff_memdbs(&old, &new).unwrap();
+    assert!(diff.added_objects().is_empty());
+    assert!(diff.removed_objects().is_empty());
+    assert_eq!(diff.changed_objects().len(), 1);
+    let change = &diff.changed_objects()[0];
+    assert_eq!(change.name(), "libfoo.so:arm64");
+    assert_eq!(change.old_uuid(), "00010203-0405-0607-0809-0a0b0c0d0e0f".parse().unwrap());
+    assert_eq!(change.new_uuid(), "10111213-1415-1617-1819-1a1b1c1d1e1f".parse().unwrap());
+    assert_eq!(change.added_symbols(), &["baz".to_string()]);
+    assert_eq!(change.removed_symbols(), &["bar".to_string()]);
+}
+
+#[test]
+fn test_diff_duplicate_names() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let old = import_modules(&tmp, &[
+        ("000102030405060708090A0B0C0D0E0F0", &["foo"][..]),
+        ("101112131415161718191A1B1C1D1E1F0", &["foo"][..]),
+    ]);
+    let new = import_modules(&tmp, &[("202122232425262728292A2B2C2D2E2F0", &["foo"][..])]);
+
+    // the new object cannot be matched up with one of the old ones
+    let diff = diff_memdbs(&old, &new).unwrap();
+    assert!(diff.changed_objects().is_empty());
+    assert_eq!(diff.removed_objects().len(), 2);
+    assert_eq!(diff.added_objects().len(), 1);
+    assert_eq!(diff.added_objects()[0].uuid(),
+               "20212223-2425-2627-2829-2a2b2c2d2e2f".parse().unwrap());
+}
+
+#[test]
+fn test_update() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let memdb = MemDb::from_path(convert_fixture(&tmp)).unwrap();
+    let objects = vec![("/libKoreanConverter.dylib".to_string(),
+                        Object::from_slice(DEMO_DYLIB).unwrap())];
+
+    // replacing the objects with themselves keeps everything
+    let mut buf = std::io::Cursor::new(vec![]);
+    update_memdb(&mut buf, &memdb, DumpOptions::default(), &objects[..]).unwrap();
+    let updated = MemDb::from_vec(buf.into_inner()).unwrap();
+    assert_eq!(updated.info(), memdb.info());
+    assert!(diff_memdbs(&memdb, &updated).unwrap().is_empty());
+
+    // adding objects to an empty memdb
+    let empty_path = tmp.path().join("iOS DeviceSupport").join("10.3 (14E277)");
+    fs::create_dir_all(empty_path.join("Symbols")).unwrap();
+    let mut buf = std::io::Cursor::new(vec![]);
+    Sdk::new(&empty_path).unwrap().dump_memdb(&mut buf, DumpOptions::default()).unwrap();
+    let empty = MemDb::from_vec(buf.into_inner()).unwrap();
+    let mut buf = std::io::Cursor::new(vec![]);
+    update_memdb(&mut buf, &empty, DumpOptions::default(), &objects[..]).unwrap();
+    let updated = MemDb::from_vec(buf.into_inner()).unwrap();
+    assert_eq!(updated.info(), empty.info());
+    assert_eq!(updated.list_tagged_objects().unwrap().len(), 2);
+
+    let uuid: Uuid = "fe6d76d4-8c3a-3a9a-9f63-f4a475501f1b".parse().unwrap();
+    let sym = updated.lookup_by_uuid(&uuid, 6804482832 - 6804459520).unwrap();
+    assert_eq!(sym.symbol(), "___CFFromMacKoreanLen");
+    assert_eq!(sym.object_name(), DEMO_DYLIB_NAME);
+}
+
+#[test]
+fn test_metadata() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let memdb = MemDb::from_path(convert_fixture_with(&tmp, "10.2 (14C92)", DumpOptions {
+        metadata: vec![("device_model".into(), "iPhone9,1".into())],
+        ..Default::default()
+    })).unwrap();
+    let metadata = memdb.metadata().unwrap();
+    assert_eq!(metadata.get("device_model").map(|x| x.as_str()), Some("iPhone9,1"));
+    assert!(metadata.contains_key("converter_version"));
+    assert!(!metadata.contains_key("created"));
+    assert!(!metadata.contains_key("source_md5"));
+    assert_eq!(memdb.verify().unwrap(), true);
+}
+
+#[test]
+fn test_threads() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let path = convert_fixture_with(&tmp, "10.2 (14C92)", DumpOptions {
+        threads: 1,
+        ..Default::default()
+    });
+    let single = MemDb::from_path(&path).unwrap();
+    let mut single_buf = vec![];
+    fs::File::open(&path).unwrap().read_to_end(&mut single_buf).unwrap();
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let path = convert_fixture_with(&tmp, "10.2 (14C92)", DumpOptions {
+        threads: 4,
+        ..Default::default()
+    });
+    let multi = MemDb::from_path(&path).unwrap();
+    let mut multi_buf = vec![];
+    fs::File::open(&path).unwrap().read_to_end(&mut multi_buf).unwrap();
+    assert_eq!(md5::compute(&single_buf[..]).0, md5::compute(&multi_buf[..]).0);
+
+    assert_eq!(single.list_tagged_objects().unwrap(), multi.list_tagged_objects().unwrap());
+    assert!(diff_memdbs(&single, &multi).unwrap().is_empty());
+    let uuid: Uuid = "fe6d76d4-8c3a-3a9a-9f63-f4a475501f1b".parse().unwrap();
+    let single_syms: Vec<_> = single.iter_symbols(&uuid).unwrap()
+        .map(|x| x.unwrap().symbol().to_string()).collect();
+    let multi_syms: Vec<_> = multi.iter_symbols(&uuid).unwrap()
+        .map(|x| x.unwrap().symbol().to_string()).collect();
+    assert_eq!(single_syms, multi_syms);
+}
+
+/// Converts an SDK with the given files, created in the given order
+fn convert_files(files: &[(&str, Vec<u8>)], opts: DumpOptions) -> Vec<u8> {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let sdk_path = tmp.path().join("iOS DeviceSupport").join("10.2 (14C92)");
+    for &(name, ref data) in files {
+        let path = sdk_path.join("Symbols").join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::File::create(&path).unwrap().write_all(data).unwrap();
+    }
+    let mut buf = std::io::Cursor::new(vec![]);
+    Sdk::new(&sdk_path).unwrap().dump_memdb(&mut buf, opts).unwrap();
+    buf.into_inner()
+}
+
+#[test]
+fn test_reproducible() {
+    let mut files = vec![
+        (&DEMO_DYLIB_NAME[1..], DEMO_DYLIB.to_vec()),
+        ("usr/lib/libstripped.dylib", stripped_dylib()),
+        ("usr/lib/system/libfirst.dylib",
+         stripped_dylib_with_uuid("10111213-1415-1617-1819-1a1b1c1d1e1f")),
+        ("Developer/usr/lib/libsecond.dylib",
+         stripped_dylib_with_uuid("20212223-2425-2627-2829-2a2b2c2d2e2f")),
+    ];
+    for &low_memory in &[false, true] {
+        let opts = DumpOptions {
+            low_memory: low_memory,
+            ..Default::default()
+        };
+        let forward = convert_files(&files, opts.clone());
+        files.reverse();
+        let backward = convert_files(&files, opts.clone());
+        files.reverse();
+        assert_eq!(md5::compute(&forward[..]).0, md5::compute(&backward[..]).0);
+
+        let memdb = MemDb::from_vec(forward).unwrap();
+        assert_eq!(memdb.list_tagged_objects().unwrap().len(), 5);
+    }
+}
+
+#[test]
+fn test_low_memory() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let memdb = MemDb::from_path(convert_fixture_with(&tmp, "10.2 (14C92)", DumpOptions {
+        low_memory: true,
+        ..Default::default()
+    })).unwrap();
+    assert_eq!(memdb.verify().unwrap(), true);
+
+    let uuid: Uuid = "fe6d76d4-8c3a-3a9a-9f63-f4a475501f1b".parse().unwrap();
+    let sym = memdb.lookup_by_uuid(&uuid, 6804482832 - 6804459520).unwrap();
+    assert_eq!(sym.symbol(), "___CFFromMacKoreanLen");
+    assert_eq!(sym.object_name(), DEMO_DYLIB_NAME);
+    assert_eq!(memdb.iter_symbols(&uuid).unwrap().count(), 15);
+
+    let hits: Vec<_> = memdb.lookup_by_name("___CFFromMacKoreanLen", NameMatch::Exact)
+        .unwrap().map(|x| x.unwrap()).collect();
+    assert_eq!(hits.len(), 2);
+}
@@ -1,6 +1,7 @@
 extern crate libsymbolserver;
 
 use std::path::Path;
+use std::collections::HashSet;
 
 use libsymbolserver::sdk::SdkInfo;
 
@@ -73,3 +74,80 @@ fn test_sdk_info_parse_filename_no_build() {
     assert_eq!(info.version_patchlevel(), 3);
     assert_eq!(info.build(), None);
 }
+
+#[test]
+fn test_sdk_info_parse_other_platforms() {
+    for &(folder, name) in &[("watchOS DeviceSupport", "watchOS"),
+                             ("macOS DeviceSupport", "macOS"),
+                             ("visionOS DeviceSupport", "visionOS"),
+                             ("xrOS DeviceSupport", "visionOS")] {
+        let path = Path::new("/Users/mitsuhiko/Library/Developer/Xcode").join(folder).join("1.0 (21N305)");
+        let info = SdkInfo::from_path(&path).unwrap();
+        assert_eq!(info.name(), name);
+        assert_eq!(info.version_major(), 1);
+        assert_eq!(info.version_minor(), 0);
+        assert_eq!(info.build(), Some("21N305"));
+        assert_eq!(info.model(), None);
+        assert_eq!(info.arch(), None);
+    }
+}
+
+#[test]
+fn test_sdk_info_parse_unknown_folder() {
+    assert!(SdkInfo::from_path(Path::new("/Users/mitsuhiko/Downloads/17.1 (21B80)")).is_none());
+}
+
+#[test]
+fn test_sdk_info_parse_model() {
+    let info = SdkInfo::from_path(Path::new("/Users/mitsuhiko/Library/Developer/Xcode/iOS DeviceSupport/iPhone15,2 17.1 (21B80)")).unwrap();
+    assert_eq!(info.name(), "iOS");
+    assert_eq!(info.version_major(), 17);
+    assert_eq!(info.version_minor(), 1);
+    assert_eq!(info.version_patchlevel(), 0);
+    assert_eq!(info.build(), Some("21B80"));
+    assert_eq!(info.model(), Some("iPhone15,2"));
+    assert_eq!(info.arch(), None);
+    assert_eq!(info.sdk_id(), "iOS_17.1.0_21B80");
+}
+
+#[test]
+fn test_sdk_info_parse_arch() {
+    let info = SdkInfo::from_path(Path::new("/Users/mitsuhiko/Library/Developer/Xcode/iOS DeviceSupport/17.1 (21B80) arm64e")).unwrap();
+    assert_eq!(info.name(), "iOS");
+    assert_eq!(info.version_major(), 17);
+    assert_eq!(info.version_minor(), 1);
+    assert_eq!(info.build(), Some("21B80"));
+    assert_eq!(info.model(), None);
+    assert_eq!(info.arch(), Some("arm64e"));
+}
+
+#[test]
+fn test_sdk_info_parse_model_and_arch_ext() {
+    let info = SdkInfo::from_path(Path::new("/Users/mitsuhiko/Library/Developer/Xcode/watchOS DeviceSupport/Watch6,1 10.1.1 (21S71) arm64_32.zip")).unwrap();
+    assert_eq!(info.name(), "watchOS");
+    assert_eq!(info.version_major(), 10);
+    assert_eq!(info.version_minor(), 1);
+    assert_eq!(info.version_patchlevel(), 1);
+    assert_eq!(info.build(), Some("21S71"));
+    assert_eq!(info.model(), Some("Watch6,1"));
+    assert_eq!(info.arch(), Some("arm64_32"));
+}
+
+#[test]
+fn test_sdk_info_parse_memdb_has_no_model() {
+    let info = SdkInfo::from_filename("visionOS_1.0.0_21N305.memdb").unwrap();
+    assert_eq!(info.name(), "visionOS");
+    assert_eq!(info.model(), None);
+    assert_eq!(info.arch(), None);
+}
+
+#[test]
+fn test_sdk_info_eq_ignores_model() {
+    let info = SdkInfo::from_path(Path::new("/Users/mitsuhiko/Library/Developer/Xcode/iOS DeviceSupport/iPhone15,2 17.1 (21B80) arm64e")).unwrap();
+    let memdb_info = SdkInfo::from_filename("iOS_17.1.0_21B80.memdb").unwrap();
+    assert_eq!(info, memdb_info);
+
+    let mut set = HashSet::new();
+    set.insert(info);
+    assert!(set.contains(&memdb_info));
+}
@@ -0,0 +1,104 @@
+extern crate libsymbolserver;
+extern crate tempdir;
+extern crate uuid;
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+
+use tempdir::TempDir;
+use uuid::Uuid;
+
+use libsymbolserver::shared_cache::{SharedCache, is_shared_cache};
+use libsymbolserver::sdk::{Sdk, DumpOptions};
+use libsymbolserver::memdb::read::MemDb;
+
+use common::{push_u32, push_u64};
+
+const UUID: &'static str = "fe6d76d4-8c3a-3a9a-9f63-f4a475501f1b";
+
+/// A cache with one mapping and the image `/usr/lib/libfoo.dylib`
+///
+/// The image `/usr/lib/libbar.dylib` is outside of the mapping as if it
+/// was in another file of a split cache.
+fn shared_cache() -> Vec<u8> {
+    let mut buf = b"dyld_v1   arm64\x00".to_vec();
+    // mapping offset and count, images offset and count
+    push_u32(&mut buf, 0x48);
+    push_u32(&mut buf, 1);
+    push_u32(&mut buf, 0x68);
+    push_u32(&mut buf, 2);
+    buf.resize(0x48, 0);
+
+    // mapping: address, size, file offset, protection
+    push_u64(&mut buf, 0x180000000);
+    push_u64(&mut buf, 0x1000);
+    push_u64(&mut buf, 0);
+    push_u64(&mut buf, 0);
+
+    // images: address, mod time, inode, path offset
+    for &(addr, path_offset) in &[(0x180000100, 0xa8), (0x190000000, 0xc0)] {
+        push_u64(&mut buf, addr);
+        push_u64(&mut buf, 0);
+        push_u64(&mut buf, 0);
+        push_u32(&mut buf, path_offset);
+        push_u32(&mut buf, 0);
+    }
+    buf.extend_from_slice(b"/usr/lib/libfoo.dylib\x00");
+    buf.resize(0xc0, 0);
+    buf.extend_from_slice(b"/usr/lib/libbar.dylib\x00");
+    buf.resize(0x100, 0);
+
+    // mach header with a single LC_UUID command
+    for &value in &[0xfeedfacfu32, 0x100000c, 0, 6, 1, 24, 0, 0] {
+        push_u32(&mut buf, value);
+    }
+    push_u32(&mut buf, 0x1b);
+    push_u32(&mut buf, 24);
+    buf.extend_from_slice(UUID.parse::<Uuid>().unwrap().as_bytes());
+    buf
+}
+
+#[test]
+fn test_images() {
+    let data = shared_cache();
+    assert!(is_shared_cache(&data));
+
+    let cache = SharedCache::parse(&data).unwrap();
+    let images = cache.images();
+    assert_eq!(images.len(), 1);
+    assert_eq!(images[0].path(), "/usr/lib/libfoo.dylib");
+    assert_eq!(images[0].uuid(), Some(UUID.parse().unwrap()));
+    assert_eq!(images[0].addr(), 0x180000100);
+    assert_eq!(images[0].offset(), 0x100);
+    assert_eq!(cache.skipped_images(), 1);
+}
+
+#[test]
+fn test_truncated() {
+    let data = shared_cache();
+    assert!(SharedCache::parse(&data[..0x110]).is_err());
+    assert!(SharedCache::parse(b"not a cache").is_err());
+}
+
+#[test]
+fn test_dump_memdb() {
+    let tmp = TempDir::new("symbolserver-test").unwrap();
+    let sdk_path = tmp.path().join("iOS DeviceSupport").join("17.0 (21A329)");
+    let cache_path = sdk_path.join("Symbols/System/Library/Caches/com.apple.dyld");
+    fs::create_dir_all(&cache_path).unwrap();
+    fs::File::create(cache_path.join("dyld_shared_cache_arm64")).unwrap()
+        .write_all(&shared_cache()).unwrap();
+
+    let sdk = Sdk::new(&sdk_path).unwrap();
+    let names: Vec<_> = sdk.objects().unwrap().map(|x| x.unwrap().1).collect();
+    assert_eq!(names, vec!["/usr/lib/libfoo.dylib".to_string()]);
+
+    let memdb_path = tmp.path().join(sdk.info().memdb_filename());
+    sdk.dump_memdb(fs::File::create(&memdb_path).unwrap(), DumpOptions::default()).unwrap();
+    let memdb = MemDb::from_path(&memdb_path).unwrap();
+    assert_eq!(memdb.find_uuid("/usr/lib/libfoo.dylib", "arm64").unwrap(),
+               Some(UUID.parse().unwrap()));
+    assert_eq!(memdb.find_uuid("/usr/lib/libbar.dylib", "arm64").unwrap(), None);
+}